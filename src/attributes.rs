@@ -0,0 +1,220 @@
+//! Attributes of students, such as a skill level or a grade, which the
+//! shuffles can use to balance or group the teams.
+
+use std::collections::HashMap;
+
+use database::{Data, Student};
+use notify;
+
+/// Parse comma separated values, allowing quoted fields as written by
+/// `statistics::to_csv`.  Blank lines are skipped.
+pub fn parse_csv(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    field.push('"');
+                } else {
+                    quoted = false;
+                }
+            }
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => row.push(::std::mem::take(&mut field)),
+            '\n' if !quoted => {
+                row.push(::std::mem::take(&mut field));
+                if row.iter().any(|f| !f.trim().is_empty()) {
+                    rows.push(row);
+                }
+                row = Vec::new();
+            }
+            '\r' if !quoted => (),
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("Unterminated quote in csv".to_string());
+    }
+    row.push(field);
+    if row.iter().any(|f| !f.trim().is_empty()) {
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+/// Import attributes from csv with a header row.  The first column
+/// names the student, and each remaining column is an attribute, except
+/// that a column headed "tags" holds the student's tags separated by
+/// semicolons, and one headed "email" their email address.  An empty
+/// cell removes that attribute.  Nothing is changed if any student is
+/// unknown or any value is an infinite or undefined number.  Returns
+/// the number of students updated.
+pub fn import_csv(data: &mut Data, text: &str) -> Result<usize, String> {
+    let rows = parse_csv(text)?;
+    let (header, rows) = match rows.split_first() {
+        Some(x) => x,
+        None => return Err("The csv is empty".to_string()),
+    };
+    let names: Vec<String> = header.iter().skip(1).map(|h| h.trim().to_string()).collect();
+    if names.is_empty() || names.iter().any(|n| n.is_empty()) {
+        return Err("The csv header needs a name for each attribute column".to_string());
+    }
+    let mut updates = Vec::new();
+    for (i, row) in rows.iter().enumerate() {
+        let student = Student::from(row[0].trim().to_string());
+        if data.student_section(student).is_none() {
+            return Err(format!("Line {}: no such student {:?}", i+2, row[0]));
+        }
+        for (name, value) in names.iter().zip(row.iter().skip(1)) {
+            if name == "email" && !value.trim().is_empty() {
+                notify::check_email(value.trim()).map_err(|e| format!("Line {}: {}", i+2, e))?;
+            }
+            if let Ok(x) = value.trim().parse::<f64>() {
+                if !x.is_finite() {
+                    return Err(format!("Line {}: {} of {:?} is not a finite number",
+                                       i+2, name, value.trim()));
+                }
+            }
+        }
+        updates.push((student, row.iter().skip(1).map(|v| v.trim().to_string())));
+    }
+    let num = updates.len();
+    for (student, values) in updates {
+        for (name, value) in names.iter().zip(values) {
+            if name == "tags" {
+                data.set_tags(student, value.split(';').map(|t| t.to_string()));
+            } else if name == "email" {
+                data.set_email(student, &value)?;
+            } else {
+                data.set_attribute(student, name, &value);
+            }
+        }
+    }
+    Ok(num)
+}
+
+/// How undesirable it is to pair two students.  Lower is better.
+pub type Cost = Box<dyn Fn(Student, Student) -> f64>;
+
+/// A cost that prefers to pair up students whose values of `attribute`
+/// balance each other (`balance`) or are similar (otherwise).  If every
+/// known value is a number, numbers are compared: balanced pairs add up
+/// to close to twice the average, and similar pairs are close to one
+/// another.  Otherwise values are categories, which balanced pairs
+/// mix and similar pairs share.  Students without a value are neutral.
+/// Numbers which are not finite, or whose sum is not, are treated as
+/// categories.
+pub fn partner_cost(data: &Data, students: &[Student], attribute: &str, balance: bool) -> Cost {
+    let values: HashMap<Student, String> = students.iter()
+        .filter_map(|&s| data.attribute(s, attribute).map(|v| (s, v.to_string())))
+        .collect();
+    let numbers: HashMap<Student, f64> = values.iter()
+        .filter_map(|(&s, v)| v.parse().ok().filter(|x: &f64| x.is_finite()).map(|x| (s, x)))
+        .collect();
+    let mean = numbers.values().sum::<f64>() / numbers.len() as f64;
+    if values.is_empty() {
+        Box::new(|_, _| 0.0)
+    } else if numbers.len() == values.len() && mean.is_finite() {
+        let value = move |s| numbers.get(&s).cloned().unwrap_or(mean);
+        if balance {
+            Box::new(move |a, b| (value(a) + value(b) - 2.0*mean).abs())
+        } else {
+            Box::new(move |a, b| (value(a) - value(b)).abs())
+        }
+    } else {
+        Box::new(move |a, b| match (values.get(&a), values.get(&b)) {
+            (Some(x), Some(y)) if (x == y) != balance => 0.0,
+            (Some(_), Some(_)) => 1.0,
+            _ => 0.5,
+        })
+    }
+}
+
+#[test]
+fn test_parse_csv() {
+    let rows = parse_csv("student,skill\r\n\"dan, jr.\",3\n\nann,\"say \"\"hi\"\"\"\n").unwrap();
+    assert_eq!(rows, vec![vec!["student", "skill"], vec!["dan, jr.", "3"],
+                          vec!["ann", "say \"hi\""]]);
+    assert!(parse_csv("a,\"b\n").is_err());
+}
+
+#[test]
+fn test_balance_and_group() {
+    use database::{Day, Section, ShuffleMode, Team, Zoom};
+    let mut data = Data::empty("testcourse", "testcourse.yaml");
+    let section = Section::from("A".to_string());
+    data.new_section(section, Zoom::from("".to_string()));
+    let s: Vec<_> = ["ann", "bob", "cat", "dan"].iter()
+        .map(|n| Student::from(n.to_string())).collect();
+    for &st in s.iter() {
+        data.new_student(st, section);
+    }
+    data.new_team(Team::from("red".to_string()));
+    data.new_team(Team::from("blue".to_string()));
+    assert!(import_csv(&mut data, "name,skill\nann,1\nzed,2\n").is_err());
+    assert_eq!(data.attribute(s[0], "skill"), None);
+    assert_eq!(import_csv(&mut data, "name,skill,tags\nann,1,a;b\nbob,2,\ncat,9\ndan,10\n"),
+               Ok(4));
+    assert_eq!(data.student_tags(s[0]), vec!["a".to_string(), "b".to_string()]);
+    assert_eq!(data.attribute_names(), vec!["skill".to_string()]);
+    assert!(import_csv(&mut data, "name,email\nann,ann@example.com\nbob,bob\n").is_err());
+    assert_eq!(data.student_email(s[0]), None);
+    assert_eq!(import_csv(&mut data, "name,email\nann,ann@example.com\n"), Ok(1));
+    assert_eq!(data.student_email(s[0]), Some("ann@example.com"));
+    assert_eq!(data.attribute_names(), vec!["skill".to_string()]);
+    data.set_balance_attribute(Some("skill".to_string()));
+
+    let pairs = |data: &Data, day: usize| {
+        let mut pairs: Vec<_> = data.pairings()[day].iter()
+            .map(|p| { let mut v = p.present_students(); v.sort(); v }).collect();
+        pairs.sort();
+        pairs
+    };
+    data.add_day();
+    data.run_shuffle(Day::from(0), ShuffleMode::Group, None);
+    assert_eq!(pairs(&data, 0), vec![vec![s[0], s[1]], vec![s[2], s[3]]]);
+    data.add_day();
+    data.run_shuffle(Day::from(1), ShuffleMode::Balance, None);
+    assert_eq!(pairs(&data, 1), vec![vec![s[0], s[3]], vec![s[1], s[2]]]);
+    // Grouping again would repeat day 0, so non-repeats win out.
+    data.add_day();
+    data.run_shuffle(Day::from(2), ShuffleMode::Group, None);
+    assert_eq!(pairs(&data, 2), vec![vec![s[0], s[2]], vec![s[1], s[3]]]);
+}
+
+#[test]
+fn test_non_finite_attributes() {
+    use database::{Day, Section, ShuffleMode, Team, Zoom};
+    let mut data = Data::empty("testcourse", "testcourse.yaml");
+    let section = Section::from("A".to_string());
+    data.new_section(section, Zoom::from("".to_string()));
+    let s: Vec<_> = ["ann", "bob", "cat", "dan"].iter()
+        .map(|n| Student::from(n.to_string())).collect();
+    for &st in s.iter() {
+        data.new_student(st, section);
+    }
+    data.new_team(Team::from("red".to_string()));
+    data.new_team(Team::from("blue".to_string()));
+    assert!(import_csv(&mut data, "name,skill\nann,NaN\nbob,2\n").is_err());
+    assert!(import_csv(&mut data, "name,skill\nann,1\nbob,-inf\n").is_err());
+    assert_eq!(data.attribute(s[0], "skill"), None);
+    // Values set one at a time, or which overflow when added up, must
+    // not upset the shuffles either.
+    data.set_attribute(s[0], "skill", "NaN");
+    data.set_attribute(s[1], "skill", "inf");
+    data.set_attribute(s[2], "size", "1e308");
+    data.set_attribute(s[3], "size", "1e308");
+    for (id, attribute) in ["skill", "size"].iter().enumerate() {
+        data.set_balance_attribute(Some(attribute.to_string()));
+        data.add_day();
+        for &mode in [ShuffleMode::Balance, ShuffleMode::Group].iter() {
+            data.run_shuffle(Day::from(id), mode, None);
+            assert!(data.check().is_empty());
+        }
+    }
+}
@@ -0,0 +1,859 @@
+//! Command-line administration of a course, working directly on its
+//! yaml file.
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use database::{Data, Day, Student, Section, Team, Zoom, Pairing, ShuffleMode, TagObjective,
+               MAX_PLANNED_DAYS};
+use registry::{self, CourseInfo};
+use attributes;
+use config::Config;
+use hooks;
+use notify;
+use outbox;
+use preview;
+use roster::{self, RosterSource, RosterSync};
+use sheet;
+
+const USAGE: &str = "\
+Usage: rust-student-pairs [serve] [OPTIONS]
+       rust-student-pairs COURSE.yaml COMMAND [ARGS...]
+
+Commands:
+  init                              create a new empty course file
+  days                              list the days
+  add-day [NAME]                    add a day
+  plan N                            add N days with pairings planned in advance
+  name-day DAY NAME                 rename a day
+  lock DAY                          lock a day
+  unlock DAY                        unlock a day
+  students                          list students by section
+  add-student NAME SECTION          add a student
+  rename-student OLD NEW [SECTION]  rename (or move) a student
+  delete-student NAME               delete a student
+  activate NAME                     reactivate a student who had dropped
+  deactivate NAME                   mark a student as dropped, keeping their history
+  attributes                        list the attributes of students
+  import-attributes FILE.csv        set attributes from csv (student, then attributes)
+  set-attribute NAME ATTR [VALUE]   set (or remove) an attribute of a student
+  balance-by [ATTR]                 choose the attribute for balance and group shuffles
+  tags NAME [TAG...]                show or set the tags of a student
+  email NAME [ADDRESS]              show or set (or remove) the email address of a student
+  roster-source [none|csv FILE|json URL|lti URL SECTION [TOKEN]]
+                                    show or set where the roster is synced from
+  sync-roster [--dry-run] [FILE.csv]
+                                    add, move and drop students to match the roster
+                                    source (or a csv file)
+  roster-log                        list what each roster sync changed
+  sections                          list sections
+  add-section NAME [ZOOM]           add a section
+  rename-section OLD NEW [ZOOM]     rename a section (or change its zoom)
+  delete-section NAME               delete a section
+  objective SECTION [OBJECTIVE]     show or set how a section uses tags
+  capacity SECTION [N|none]         show or set how many students fit in a section
+  prefer SECTION [STUDENT...]       show or set students to place in a section
+  links                             show the link templates for sections and teams
+  meeting-link TEMPLATE             set the link to each section's meeting, e.g.
+                                    https://zoom.us/j/{zoom}
+  team-link LABEL [TEMPLATE]        set (or remove) a link for each team, e.g.
+                                    https://example.com/pair-{team}
+  webhooks [URL...]                 show or set the endpoints posted each change
+  outbox                            list the webhook posts and provisioning waiting to be done
+  teams                             list teams
+  add-team [NAME]                   add a team (with a random name if none given)
+  rename-team OLD NEW               rename a team
+  delete-team NAME                  delete a team
+  resources                         list the provisioned resources of each team
+  provision CONFIG.yaml [TEAM...]   run the provisioning hook of a server configuration
+                                    for the teams (or every team without resources)
+  shuffle DAY MODE [SECTION]        shuffle a day (all sections if none given)
+  preview DAY [MODE|all [COUNT]]    compare candidate shuffles of a day with its pairings
+  accept DAY N                      replace a day's pairings with candidate N of its preview
+  rotation [LEN...]                 show or set the lengths of rotation blocks
+  pins DAY                          list the pinned teams and students of a day
+  pin DAY TEAM|STUDENT              keep a team or student in place when shuffling
+  unpin DAY TEAM|STUDENT            let a team or student be shuffled again
+  show DAY                          print the pairings for a day
+  export DAY [text|markdown]        print a day's teams by section, with meetings
+  publish [--again] DAY CONFIG.yaml lock a day and send each student their team through
+                                    the channels of a server configuration (again if
+                                    it has already been published)
+  deliveries DAY                    list the messages sent when publishing a day
+  check                             check the course for inconsistencies
+  clone [--roster] [TITLE [TERM]]   start a new course with the same sections and
+                                    teams (and roster), printing its file name
+
+Shuffle modes: ";
+
+fn usage() -> String {
+    let modes: Vec<_> = ShuffleMode::all().iter().map(|m| m.name()).collect();
+    let objectives: Vec<_> = TagObjective::all().iter().map(|o| o.name()).collect();
+    format!("{}{}\nTag objectives: {}\n\n{}", USAGE, modes.join(", "),
+            objectives.join(", "), ::config::USAGE)
+}
+
+/// Run a command given the command-line arguments (excluding the
+/// program name).
+pub fn run(args: &[String]) -> Result<(), String> {
+    if args.len() < 2 {
+        return Err(usage());
+    }
+    let file = Path::new(&args[0]);
+    let command = args[1].as_str();
+    let args = &args[2..];
+    if command == "init" {
+        if file.exists() {
+            return Err(format!("{:?} already exists", file));
+        }
+        let path = file.file_stem().map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        Data::empty(&path, file).save();
+        return Ok(());
+    }
+    let mut data = Data::open(file)?;
+    let modified = match command {
+        "days" => {
+            expect_args(args, 0, 0)?;
+            for day in data.list_days() {
+                println!("{:>3} {} {}", day.id,
+                         if day.unlocked { "unlocked" } else { "locked  " },
+                         day.pretty());
+            }
+            false
+        }
+        "add-day" => {
+            expect_args(args, 0, 1)?;
+            let id = data.list_days().len();
+            data.add_day();
+            if let Some(name) = args.first() {
+                data.name_day(id, name.clone());
+            }
+            println!("Added day {}", id);
+            true
+        }
+        "plan" => {
+            expect_args(args, 1, 1)?;
+            let n: usize = args[0].parse().ok()
+                .filter(|n| (1..=MAX_PLANNED_DAYS).contains(n))
+                .ok_or_else(|| format!("Invalid number of days {:?} (from 1 to {})", args[0],
+                                       MAX_PLANNED_DAYS))?;
+            for day in data.plan_days(n) {
+                print!("{}", day_table(&data, day));
+            }
+            true
+        }
+        "name-day" => {
+            expect_args(args, 2, 2)?;
+            let day = parse_day(&data, &args[0])?;
+            data.name_day(day.id, args[1].clone());
+            true
+        }
+        "lock" | "unlock" => {
+            expect_args(args, 1, 1)?;
+            let day = parse_day(&data, &args[0])?;
+            if data.day_unlocked(day) != (command == "unlock") {
+                data.toggle_lock_day(day);
+            }
+            true
+        }
+        "students" => {
+            expect_args(args, 0, 0)?;
+            for (section, students) in data.list_students_by_section() {
+                println!("{}:", section.name);
+                for s in students {
+                    if data.is_active(s) {
+                        println!("    {}", s.name);
+                    } else {
+                        println!("    {} (dropped)", s.name);
+                    }
+                }
+            }
+            false
+        }
+        "add-student" => {
+            expect_args(args, 2, 2)?;
+            let student = Student::from(args[0].clone());
+            let section = existing_section(&data, &args[1])?;
+            if data.student_section(student).is_some() {
+                return Err(format!("Student {:?} already exists", args[0]));
+            }
+            data.new_student(student, section);
+            true
+        }
+        "rename-student" => {
+            expect_args(args, 2, 3)?;
+            let old = existing_student(&data, &args[0])?;
+            let new = Student::from(args[1].clone());
+            let section = match args.get(2) {
+                Some(s) => existing_section(&data, s)?,
+                None => data.student_section(old).unwrap(),
+            };
+            data.rename_student(old, new, section)?;
+            true
+        }
+        "delete-student" => {
+            expect_args(args, 1, 1)?;
+            let student = existing_student(&data, &args[0])?;
+            data.delete_student(student);
+            true
+        }
+        "activate" | "deactivate" => {
+            expect_args(args, 1, 1)?;
+            let student = existing_student(&data, &args[0])?;
+            data.set_active(student, command == "activate");
+            true
+        }
+        "attributes" => {
+            expect_args(args, 0, 0)?;
+            for student in data.list_students() {
+                let a: Vec<_> = data.student_attributes(student).into_iter()
+                    .map(|(k, v)| format!("{}={}", k, v)).collect();
+                println!("{}\t{}", student.name, a.join(" "));
+            }
+            if let Some(a) = data.balance_attribute() {
+                println!("Balancing by {}", a);
+            }
+            false
+        }
+        "import-attributes" => {
+            expect_args(args, 1, 1)?;
+            let csv = ::std::fs::read_to_string(&args[0])
+                .map_err(|e| format!("Unable to read {:?}: {}", args[0], e))?;
+            let n = attributes::import_csv(&mut data, &csv)?;
+            println!("Updated {} students", n);
+            true
+        }
+        "set-attribute" => {
+            expect_args(args, 2, 3)?;
+            let student = existing_student(&data, &args[0])?;
+            data.set_attribute(student, &args[1], args.get(2).map(|v| v.as_str()).unwrap_or(""));
+            true
+        }
+        "balance-by" => {
+            expect_args(args, 0, 1)?;
+            data.set_balance_attribute(args.first().cloned());
+            true
+        }
+        "tags" => {
+            expect_args(args, 1, usize::MAX)?;
+            let student = existing_student(&data, &args[0])?;
+            if args.len() == 1 {
+                println!("{}", data.student_tags(student).join(" "));
+                false
+            } else {
+                data.set_tags(student, args[1..].iter().cloned());
+                true
+            }
+        }
+        "email" => {
+            expect_args(args, 1, 2)?;
+            let student = existing_student(&data, &args[0])?;
+            match args.get(1) {
+                Some(email) => {
+                    data.set_email(student, email)?;
+                    true
+                }
+                None => {
+                    println!("{}", data.student_email(student).unwrap_or(""));
+                    false
+                }
+            }
+        }
+        "roster-source" => {
+            expect_args(args, 0, 4)?;
+            match args.first().map(|a| a.as_str()) {
+                None => {
+                    if let Some(source) = data.roster_source() {
+                        println!("{}", source);
+                    }
+                    false
+                }
+                Some(kind) => {
+                    let kind = if kind == "none" { "" } else { kind };
+                    let arg = |i: usize| args.get(i).map(|a| a.as_str()).unwrap_or("");
+                    let source = RosterSource::new(kind, arg(1), arg(2), arg(3))?;
+                    data.set_roster_source(source);
+                    true
+                }
+            }
+        }
+        "sync-roster" => {
+            let dry_run = args.first().map(|a| a == "--dry-run").unwrap_or(false);
+            let args = if dry_run { &args[1..] } else { args };
+            expect_args(args, 0, 1)?;
+            let sync = match args.first() {
+                Some(csv) => roster::sync(&mut data, &roster::CsvFile(PathBuf::from(csv)),
+                                          &format!("csv {}", csv), dry_run)?,
+                None => {
+                    let source = data.roster_source().cloned()
+                        .ok_or("There is no roster source, see roster-source")?;
+                    roster::sync(&mut data, &*source.provider(), &source.to_string(), dry_run)?
+                }
+            };
+            print_roster_sync(&sync);
+            !dry_run
+        }
+        "roster-log" => {
+            expect_args(args, 0, 0)?;
+            for sync in data.roster_log() {
+                println!("{} from {}", registry::format_time(sync.time), sync.source);
+                print_roster_sync(sync);
+            }
+            false
+        }
+        "objective" => {
+            expect_args(args, 1, 2)?;
+            let section = existing_section(&data, &args[0])?;
+            match args.get(1) {
+                Some(o) => {
+                    data.set_tag_objective(section, TagObjective::from_str(o)?);
+                    true
+                }
+                None => {
+                    println!("{}", data.tag_objective(section).name());
+                    false
+                }
+            }
+        }
+        "capacity" => {
+            expect_args(args, 1, 2)?;
+            let section = existing_section(&data, &args[0])?;
+            match args.get(1).map(|a| a.as_str()) {
+                Some("none") => {
+                    data.set_capacity(section, None);
+                    true
+                }
+                Some(n) => {
+                    let n = n.parse().map_err(|_| format!("Invalid capacity {:?}", n))?;
+                    data.set_capacity(section, Some(n));
+                    true
+                }
+                None => {
+                    match data.capacity(section) {
+                        Some(n) => println!("{}", n),
+                        None => println!("none"),
+                    }
+                    false
+                }
+            }
+        }
+        "prefer" => {
+            expect_args(args, 1, usize::MAX)?;
+            let section = existing_section(&data, &args[0])?;
+            if args.len() == 1 {
+                for s in data.preferred_students(section) {
+                    println!("{}", s.name);
+                }
+                false
+            } else {
+                let students = args[1..].iter().map(|s| existing_student(&data, s))
+                    .collect::<Result<Vec<_>, _>>()?;
+                data.set_preferred_students(section, students);
+                true
+            }
+        }
+        "sections" => {
+            expect_args(args, 0, 0)?;
+            for (section, zoom) in data.zoom_sections() {
+                let capacity = data.capacity(section).map(|c| c.to_string())
+                    .unwrap_or_else(|| "-".to_string());
+                println!("{}\t{}\t{}\t{}", section.name, zoom.id,
+                         data.tag_objective(section).name(), capacity);
+            }
+            false
+        }
+        "add-section" => {
+            expect_args(args, 1, 2)?;
+            let section = Section::from(args[0].clone());
+            if data.section_zoom(section).is_some() {
+                return Err(format!("Section {:?} already exists", args[0]));
+            }
+            let zoom = Zoom::from(args.get(1).cloned().unwrap_or_default());
+            data.new_section(section, zoom);
+            true
+        }
+        "rename-section" => {
+            expect_args(args, 2, 3)?;
+            let old = existing_section(&data, &args[0])?;
+            let new = Section::from(args[1].clone());
+            let zoom = match args.get(2) {
+                Some(z) => Zoom::from(z.clone()),
+                None => data.section_zoom(old).unwrap(),
+            };
+            data.rename_section(old, new, zoom)?;
+            true
+        }
+        "delete-section" => {
+            expect_args(args, 1, 1)?;
+            let section = existing_section(&data, &args[0])?;
+            data.delete_section(section);
+            true
+        }
+        "links" => {
+            expect_args(args, 0, 0)?;
+            println!("meeting\t{}", data.links().meeting);
+            for link in data.links().team.iter() {
+                println!("{}\t{}", link.label, link.template);
+            }
+            false
+        }
+        "meeting-link" => {
+            expect_args(args, 1, 1)?;
+            let mut links = data.links().clone();
+            links.set_meeting(&args[0])?;
+            data.set_links(links);
+            true
+        }
+        "team-link" => {
+            expect_args(args, 1, 2)?;
+            let mut links = data.links().clone();
+            links.set_team_link(&args[0], args.get(1).map(|t| t.as_str()).unwrap_or(""))?;
+            data.set_links(links);
+            true
+        }
+        "webhooks" => {
+            if args.is_empty() {
+                for url in data.webhooks() {
+                    println!("{}", url);
+                }
+                false
+            } else {
+                data.set_webhooks(&args.join("\n"))?;
+                true
+            }
+        }
+        "outbox" => {
+            expect_args(args, 0, 0)?;
+            for p in outbox::pending(data.file()) {
+                println!("{}\t{}\t{}\t{}", p.id, p.job.describe(), p.attempts,
+                         p.error.unwrap_or_default());
+            }
+            false
+        }
+        "teams" => {
+            expect_args(args, 0, 0)?;
+            for team in data.list_teams() {
+                println!("{}", team.name);
+            }
+            false
+        }
+        "add-team" => {
+            expect_args(args, 0, 1)?;
+            let name = args.first().cloned().unwrap_or_else(|| {
+                format!("{:02}-{}", data.list_teams().len()+1,
+                        memorable_wordlist::camel_case(20))
+            });
+            if data.list_teams().contains(&Team::from(name.clone())) {
+                return Err(format!("Team {:?} already exists", name));
+            }
+            println!("Added team {}", name);
+            data.new_team(Team::from(name));
+            true
+        }
+        "rename-team" => {
+            expect_args(args, 2, 2)?;
+            let old = existing_team(&data, &args[0])?;
+            data.rename_team(old, Team::from(args[1].clone()))?;
+            true
+        }
+        "delete-team" => {
+            expect_args(args, 1, 1)?;
+            let team = existing_team(&data, &args[0])?;
+            data.delete_team(team);
+            true
+        }
+        "resources" => {
+            expect_args(args, 0, 0)?;
+            for team in data.list_teams() {
+                for (label, url) in data.team_resources(team) {
+                    println!("{}\t{}\t{}", team.name, label, url);
+                }
+            }
+            false
+        }
+        "provision" => {
+            if args.is_empty() {
+                return Err(format!("Wrong number of arguments\n\n{}", usage()));
+            }
+            let hook = Config::read(&args[0])?.provision
+                .ok_or_else(|| format!("{} has no provisioning hook", args[0]))?;
+            let teams = if args.len() > 1 {
+                args[1..].iter().map(|t| existing_team(&data, t)).collect::<Result<Vec<_>, _>>()?
+            } else {
+                data.list_teams().into_iter()
+                    .filter(|&t| data.team_resources(t).is_empty()).collect()
+            };
+            for team in teams {
+                let event = hooks::created(&data, team);
+                if let Err(e) = hooks::provision(&hook, &mut data, &event) {
+                    data.save();
+                    return Err(format!("Unable to provision {}: {}", team, e));
+                }
+                for (label, url) in data.team_resources(team) {
+                    println!("{}\t{}\t{}", team.name, label, url);
+                }
+            }
+            true
+        }
+        "shuffle" => {
+            expect_args(args, 2, 3)?;
+            let day = parse_day(&data, &args[0])?;
+            let mode = ShuffleMode::from_str(&args[1])?;
+            let section = match args.get(2) {
+                Some(s) => Some(existing_section(&data, s)?),
+                None => None,
+            };
+            if data.list_sections().is_empty() {
+                return Err("There are no sections to shuffle".to_string());
+            }
+            if !data.day_unlocked(day) {
+                return Err(format!("Cannot modify locked day: {}", day.pretty()));
+            }
+            let problems = data.run_shuffle(day, mode, section);
+            print!("{}", day_table(&data, day));
+            for p in problems {
+                println!("Warning: {}", p);
+            }
+            true
+        }
+        "preview" => {
+            expect_args(args, 1, 3)?;
+            let day = parse_day(&data, &args[0])?;
+            let mode = match args.get(1).map(|m| m.as_str()) {
+                None | Some("all") => None,
+                Some(m) => Some(ShuffleMode::from_str(m)?),
+            };
+            let count = match args.get(2) {
+                Some(n) => n.parse().map_err(|_| format!("Invalid count {:?}", n))?,
+                None => 1,
+            };
+            if data.list_sections().is_empty() {
+                return Err("There are no sections to shuffle".to_string());
+            }
+            if !data.day_unlocked(day) {
+                return Err(format!("Cannot modify locked day: {}", day.pretty()));
+            }
+            let candidates = preview::candidates(&data, day, mode, None, count);
+            let current = &data.pairings()[day.id];
+            for (i, c) in candidates.iter().enumerate() {
+                let score = preview::score(&data, day, current, &c.pairings);
+                println!("{}: {}: {} repeats, {} solos, {} kept, role imbalance {}, {} changes",
+                         i, c.label(), score.repeats, score.solos, score.continuity,
+                         score.role_imbalance, score.changes);
+                for change in preview::diff(current, &c.pairings) {
+                    println!("    {}: {} -> {}", change.student.name, change.before,
+                             change.after);
+                }
+                for w in c.warnings.iter() {
+                    println!("    Warning: {}", w);
+                }
+            }
+            data.set_preview(day, candidates);
+            true
+        }
+        "accept" => {
+            expect_args(args, 2, 2)?;
+            let day = parse_day(&data, &args[0])?;
+            let i = args[1].parse().map_err(|_| format!("Invalid candidate {:?}", args[1]))?;
+            if !data.day_unlocked(day) {
+                return Err(format!("Cannot modify locked day: {}", day.pretty()));
+            }
+            data.accept_candidate(day, i)?;
+            print!("{}", day_table(&data, day));
+            true
+        }
+        "rotation" => {
+            if args.is_empty() {
+                let blocks: Vec<_> = data.rotation().iter().map(|n| n.to_string()).collect();
+                println!("{}", blocks.join(" "));
+                false
+            } else {
+                let blocks = args.iter()
+                    .map(|a| a.parse().map_err(|_| format!("Invalid block length {:?}", a)))
+                    .collect::<Result<Vec<usize>, String>>()?;
+                data.set_rotation(blocks);
+                true
+            }
+        }
+        "pins" => {
+            expect_args(args, 1, 1)?;
+            let day = parse_day(&data, &args[0])?;
+            let pins = data.pins(day);
+            for t in pins.teams {
+                println!("team {}", t.name);
+            }
+            for s in pins.students {
+                println!("student {}", s.name);
+            }
+            false
+        }
+        "pin" | "unpin" => {
+            expect_args(args, 2, 2)?;
+            let day = parse_day(&data, &args[0])?;
+            let pins = data.pins(day);
+            let pin = command == "pin";
+            if let Ok(team) = existing_team(&data, &args[1]) {
+                if pins.teams.contains(&team) != pin {
+                    data.toggle_pin_team(day, team);
+                }
+            } else {
+                let student = existing_student(&data, &args[1])
+                    .map_err(|_| format!("No such team or student {:?}", args[1]))?;
+                if pins.students.contains(&student) != pin {
+                    data.toggle_pin_student(day, student);
+                }
+            }
+            true
+        }
+        "show" => {
+            expect_args(args, 1, 1)?;
+            let day = parse_day(&data, &args[0])?;
+            print!("{}", day_table(&data, day));
+            false
+        }
+        "export" => {
+            expect_args(args, 1, 2)?;
+            let day = parse_day(&data, &args[0])?;
+            let sheet = sheet::day_sheet(&data, day);
+            match args.get(1).map(|f| f.as_str()).unwrap_or("text") {
+                "text" => print!("{}", sheet::to_text(&sheet)),
+                "markdown" => print!("{}", sheet::to_markdown(&sheet)),
+                f => return Err(format!("Unknown format {:?}", f)),
+            }
+            false
+        }
+        "publish" => {
+            let again = args.first().map(|a| a == "--again").unwrap_or(false);
+            let args = if again { &args[1..] } else { args };
+            expect_args(args, 2, 2)?;
+            let day = parse_day(&data, &args[0])?;
+            let config = Config::read(&args[1])?;
+            if let Some(ref hook) = config.provision {
+                for event in hooks::used(&data, day) {
+                    if let Err(e) = hooks::provision(hook, &mut data, &event) {
+                        eprintln!("Unable to provision {}: {}", event.team, e);
+                    }
+                }
+            }
+            let before = data.deliveries(day).len();
+            notify::publish_day(&config.notify, &mut data, day, again)?;
+            // Send what can be sent now, leaving the rest to the server.
+            outbox::deliver_course(data.file(), outbox::now())?;
+            let data = Data::open(data.file())?;
+            let deliveries = &data.deliveries(day)[before..];
+            print_deliveries(deliveries);
+            let waiting = notify::waiting(&data, day);
+            if waiting > 0 {
+                eprintln!("{} messages are waiting to be retried by the server", waiting);
+            }
+            let failed = deliveries.iter().filter(|d| d.error.is_some()).count();
+            if failed > 0 {
+                return Err(format!("{} of {} messages failed", failed, deliveries.len()));
+            }
+            false
+        }
+        "deliveries" => {
+            expect_args(args, 1, 1)?;
+            let day = parse_day(&data, &args[0])?;
+            print_deliveries(data.deliveries(day));
+            false
+        }
+        "check" => {
+            expect_args(args, 0, 0)?;
+            let problems = data.check();
+            for p in problems.iter() {
+                println!("{}", p);
+            }
+            if !problems.is_empty() {
+                return Err(format!("Found {} problems", problems.len()));
+            }
+            false
+        }
+        "clone" => {
+            let roster = args.first().map(|a| a == "--roster").unwrap_or(false);
+            let args = if roster { &args[1..] } else { args };
+            expect_args(args, 0, 2)?;
+            let old = data.info();
+            let info = CourseInfo::new(args.first().unwrap_or(&old.title),
+                                       args.get(1).unwrap_or(&old.term),
+                                       &old.owner);
+            let dir = file.parent().unwrap_or_else(|| Path::new("."));
+            let path = registry::clone_course(dir, &data, info, roster);
+            println!("{}", dir.join(format!("{}.yaml", path)).display());
+            false
+        }
+        _ => {
+            return Err(format!("Unknown command {:?}\n\n{}", command, usage()));
+        }
+    };
+    if modified {
+        data.save();
+    }
+    Ok(())
+}
+
+fn expect_args(args: &[String], min: usize, max: usize) -> Result<(), String> {
+    if args.len() < min || args.len() > max {
+        Err(format!("Wrong number of arguments\n\n{}", usage()))
+    } else {
+        Ok(())
+    }
+}
+
+fn parse_day(data: &Data, s: &str) -> Result<Day, String> {
+    let day = Day::from_str(s).map_err(|_| format!("Invalid day {:?}", s))?;
+    data.existing_day(day).ok_or_else(|| format!("There is no day {}", day.id))
+}
+
+fn existing_student(data: &Data, s: &str) -> Result<Student, String> {
+    let student = Student::from(s.to_string());
+    if data.student_section(student).is_none() {
+        return Err(format!("No such student {:?}", s));
+    }
+    Ok(student)
+}
+
+fn existing_section(data: &Data, s: &str) -> Result<Section, String> {
+    let section = Section::from(s.to_string());
+    if data.section_zoom(section).is_none() {
+        return Err(format!("No such section {:?}", s));
+    }
+    Ok(section)
+}
+
+fn existing_team(data: &Data, s: &str) -> Result<Team, String> {
+    let team = Team::from(s.to_string());
+    if !data.list_teams().contains(&team) {
+        return Err(format!("No such team {:?}", s));
+    }
+    Ok(team)
+}
+
+fn print_deliveries(deliveries: &[notify::Delivery]) {
+    for d in deliveries {
+        println!("{}\t{}\t{}\t{}", registry::format_time(d.time), d.student.name, d.channel,
+                 d.error.as_deref().unwrap_or("delivered"));
+    }
+}
+
+fn print_roster_sync(sync: &RosterSync) {
+    for (student, section) in sync.added.iter() {
+        println!("    added\t{}\t{}", student.name, section.name);
+    }
+    for (student, from, to) in sync.moved.iter() {
+        println!("    moved\t{}\t{} -> {}", student.name, from.name, to.name);
+    }
+    for student in sync.dropped.iter() {
+        println!("    dropped\t{}", student.name);
+    }
+    for w in sync.warnings.iter() {
+        println!("    Warning: {}", w);
+    }
+}
+
+/// Format the pairings for a day as a plain text table.
+pub fn day_table(data: &Data, day: Day) -> String {
+    let mut rows = vec![["Section".to_string(), "Team".to_string(),
+                         "Primary".to_string(), "Secondary".to_string()]];
+    for (section, teams) in data.team_options(day) {
+        for t in teams {
+            let (primary, secondary) = match t.current_pairing {
+                Pairing::Pair { primary, secondary, .. } => {
+                    (primary.name.to_string(), secondary.name.to_string())
+                }
+                Pairing::Solo { student, .. } => (student.name.to_string(), "-".to_string()),
+                _ => continue,
+            };
+            rows.push([section.name.to_string(), t.team.name.to_string(),
+                       primary, secondary]);
+        }
+    }
+    let mut widths = [0; 4];
+    for row in rows.iter() {
+        for (w, cell) in widths.iter_mut().zip(row.iter()) {
+            *w = ::std::cmp::max(*w, cell.chars().count());
+        }
+    }
+    let mut out = format!("{}{}\n", day.pretty(), if day.unlocked { "" } else { " (locked)" });
+    for row in rows.iter() {
+        let cells: Vec<_> = row.iter().zip(widths.iter())
+            .map(|(cell, &w)| format!("{:<w$}", cell, w = w))
+            .collect();
+        out.push_str(cells.join("  ").trim_end());
+        out.push('\n');
+    }
+    let mut unassigned = data.unassigned_students(day);
+    let absent = data.absent_students(day);
+    unassigned.retain(|s| !absent.contains(s));
+    for (label, mut students) in [("Unassigned", unassigned), ("Absent", absent)] {
+        if !students.is_empty() {
+            students.sort();
+            let names: Vec<_> = students.iter().map(|s| s.name.to_string()).collect();
+            out.push_str(&format!("{}: {}\n", label, names.join(", ")));
+        }
+    }
+    out
+}
+
+#[test]
+fn test_rename_student() {
+    let dir = ::tempfile::TempDir::new().unwrap();
+    let file = dir.path().join("course.yaml").to_string_lossy().to_string();
+    let cli = |args: &str| {
+        let mut all = vec![file.clone()];
+        all.extend(args.split_whitespace().map(|a| a.to_string()));
+        run(&all)
+    };
+    for command in ["init", "add-section A", "add-section B", "add-student ann A",
+                    "add-student bob A", "add-team red", "add-team blue"].iter() {
+        cli(command).unwrap();
+    }
+    assert!(cli("rename-student ann bob").is_err());
+    assert!(cli("rename-student ann cat C").is_err());
+    assert!(cli("rename-student eve cat").is_err());
+    cli("rename-student ann ann B").unwrap();
+    cli("rename-student ann cat").unwrap();
+    let data = Data::open(Path::new(&file)).unwrap();
+    let (bob, cat) = (Student::from("bob".to_string()), Student::from("cat".to_string()));
+    assert_eq!(data.list_students().len(), 2);
+    assert_eq!(data.student_section(bob), Some(Section::from("A".to_string())));
+    assert_eq!(data.student_section(cat), Some(Section::from("B".to_string())));
+    assert!(cli("rename-section A B").is_err());
+    assert!(cli("rename-team red blue").is_err());
+    cli("rename-team red red").unwrap();
+}
+
+#[test]
+fn test_plan_and_provision_errors() {
+    let dir = ::tempfile::TempDir::new().unwrap();
+    let file = dir.path().join("course.yaml").to_string_lossy().to_string();
+    let cli = |args: &[&str]| {
+        let mut all = vec![file.clone()];
+        all.extend(args.iter().map(|a| a.to_string()));
+        run(&all)
+    };
+    cli(&["init"]).unwrap();
+    cli(&["add-section", "A"]).unwrap();
+    cli(&["add-student", "ann", "A"]).unwrap();
+    cli(&["add-student", "bob", "A"]).unwrap();
+    cli(&["add-team", "red"]).unwrap();
+    for n in ["0", "31", "-1", "two"].iter() {
+        assert!(cli(&["plan", n]).is_err(), "planned {} days", n);
+    }
+    assert!(cli(&["plan"]).is_err());
+    cli(&["plan", "2"]).unwrap();
+    assert_eq!(Data::open(Path::new(&file)).unwrap().list_days().len(), 2);
+
+    let config = dir.path().join("config.yaml");
+    ::std::fs::write(&config, "base: /pairs\n").unwrap();
+    let config = config.to_string_lossy().to_string();
+    assert!(cli(&["provision"]).is_err());
+    assert!(cli(&["provision", "missing.yaml"]).unwrap_err().contains("Unable to open"));
+    assert!(cli(&["provision", &config]).unwrap_err().contains("has no provisioning hook"));
+    ::std::fs::write(&config, "provision: {command: [\"true\"]}\n").unwrap();
+    assert!(cli(&["provision", &config, "blue"]).is_err());
+    ::std::fs::write(&config, "provision: {command: [\"false\"]}\n").unwrap();
+    assert!(cli(&["provision", &config, "red"]).unwrap_err().contains("Unable to provision red"));
+    assert!(cli(&["provision", &config]).is_err());
+}
@@ -0,0 +1,232 @@
+//! Configuration of the web server, read from a yaml file and/or the
+//! command line.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use serde_yaml;
+
+use hooks::Hook;
+use notify::Channel;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct Config {
+    /// The address (and port) on which to listen.
+    pub address: String,
+    /// The directory holding the course yaml files.
+    pub data_dir: PathBuf,
+    /// The URL path under which all pages are served, e.g. "/pairs".
+    pub base: String,
+    /// A css file to serve in place of the built-in style sheet.
+    pub stylesheet: Option<PathBuf>,
+    /// A directory of extra files to serve under `{base}/assets/`.
+    pub assets: Option<PathBuf>,
+    /// An html file to show above every page but the print and
+    /// projector views, e.g. for a site's own navigation.
+    pub header: Option<PathBuf>,
+    /// An html file to show below every page but the print and
+    /// projector views.
+    pub footer: Option<PathBuf>,
+    /// The login names and passwords of instructors who may use the
+    /// course dashboard.  If there are none, the dashboard is disabled.
+    pub instructors: BTreeMap<String, String>,
+    /// The hook which provisions each team's shared resources, if any,
+    /// e.g. `provision: {command: [/usr/local/bin/make-board]}` or
+    /// `provision: {url: "https://example.com/provision"}`.
+    pub provision: Option<Hook>,
+    /// The channels through which a published day is sent to the
+    /// students, e.g. `notify: [{smtp: {server: "localhost:25", from:
+    /// pairs@example.edu}}, {webhook: "https://example.com/notify"}]`.
+    pub notify: Vec<Channel>,
+    /// The hosts which a course's pages may point the server at, for
+    /// its webhooks and roster service, e.g. `outbound_hosts:
+    /// [lms.example.edu, "*.example.com"]`.  If there are none, these
+    /// can only be set from the command line.
+    pub outbound_hosts: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            address: "0.0.0.0:8088".to_string(),
+            data_dir: PathBuf::from("."),
+            base: "/pairs".to_string(),
+            stylesheet: None,
+            assets: None,
+            header: None,
+            footer: None,
+            instructors: BTreeMap::new(),
+            provision: None,
+            notify: Vec::new(),
+            outbound_hosts: Vec::new(),
+        }
+    }
+}
+
+pub const USAGE: &str = "\
+Options for serve:
+  --config FILE      read configuration from a yaml file
+  --address ADDR     listen on ADDR (default 0.0.0.0:8088)
+  --data-dir DIR     keep course files in DIR (default .)
+  --base PATH        serve pages under PATH (default /pairs)
+  --stylesheet FILE  serve FILE in place of the built-in style sheet
+  --assets DIR       serve the files in DIR under PATH/assets/
+  --header FILE      show the html in FILE above every page
+  --footer FILE      show the html in FILE below every page
+";
+
+impl Config {
+    /// Read a configuration file.
+    pub fn read(file: &str) -> Result<Config, String> {
+        let f = ::std::fs::File::open(file)
+            .map_err(|e| format!("Unable to open {:?}: {}", file, e))?;
+        serde_yaml::from_reader(&f)
+            .map_err(|e| format!("Unable to parse {:?}: {}", file, e))
+    }
+    /// Parse the arguments to `serve`.  Options given on the command
+    /// line override those in the configuration file.
+    pub fn from_args(args: &[String]) -> Result<Config, String> {
+        let mut config = Config::default();
+        if let Some(i) = args.iter().position(|a| a == "--config") {
+            let file = args.get(i+1).ok_or("--config needs a file name")?;
+            config = Config::read(file)?;
+        }
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            let mut value = || args.next().cloned()
+                .ok_or_else(|| format!("{} needs a value", flag));
+            match flag.as_str() {
+                "--config" => { value()?; }
+                "--address" => { config.address = value()?; }
+                "--data-dir" => { config.data_dir = PathBuf::from(value()?); }
+                "--base" => { config.base = value()?; }
+                "--stylesheet" => { config.stylesheet = Some(PathBuf::from(value()?)); }
+                "--assets" => { config.assets = Some(PathBuf::from(value()?)); }
+                "--header" => { config.header = Some(PathBuf::from(value()?)); }
+                "--footer" => { config.footer = Some(PathBuf::from(value()?)); }
+                _ => {
+                    return Err(format!("Unknown option {:?}\n\n{}", flag, USAGE));
+                }
+            }
+        }
+        config.base = normalize_base(&config.base);
+        Ok(config)
+    }
+    /// Add the header and footer to a page.  They are read for each
+    /// page, like the style sheet, so they can be changed while the
+    /// server runs.
+    pub fn decorate(&self, html: String) -> String {
+        let mut html = html;
+        if let (Some(header), Some(i)) = (read_fragment(&self.header), html.find("<main>")) {
+            html.insert_str(i, &header);
+        }
+        if let (Some(footer), Some(i)) = (read_fragment(&self.footer), html.rfind("</main>")) {
+            html.insert_str(i + "</main>".len(), &footer);
+        }
+        html
+    }
+    /// Check that a course's pages may have the server contact `url`,
+    /// so that they cannot reach, say, a service on the server's own
+    /// network.
+    pub fn check_outbound(&self, url: &str) -> Result<(), String> {
+        let host = host_of(url).unwrap_or_default();
+        let allowed = !host.is_empty() && self.outbound_hosts.iter().any(|h| {
+            let h = h.trim().to_lowercase();
+            match h.strip_prefix("*.") {
+                Some(domain) => host.ends_with(&format!(".{}", domain)),
+                None => host == h,
+            }
+        });
+        if allowed {
+            Ok(())
+        } else {
+            Err(format!("This server may not contact {:?}; \
+                         its administrator can allow the host", url))
+        }
+    }
+}
+
+/// The contents of a header or footer file, if one is configured and
+/// can be read.
+fn read_fragment(file: &Option<PathBuf>) -> Option<String> {
+    let file = file.as_ref()?;
+    match ::std::fs::read_to_string(file) {
+        Ok(html) => Some(html),
+        Err(e) => {
+            println!("Error reading {:?}: {}", file, e);
+            None
+        }
+    }
+}
+
+/// The host of a web `url`, in lower case, without the port.
+fn host_of(url: &str) -> Option<String> {
+    let rest = &url[url.find("://")?+3..];
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host_port = authority.rsplit('@').next()?;
+    let host = if host_port.starts_with('[') {
+        &host_port[..host_port.find(']')?+1]
+    } else {
+        host_port.split(':').next()?
+    };
+    Some(host.to_lowercase())
+}
+
+/// Make sure the base path starts with a slash and does not end with
+/// one, so that it can be used as a prefix of "/...".
+fn normalize_base(base: &str) -> String {
+    let base = base.trim_matches('/');
+    if base.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", base)
+    }
+}
+
+#[test]
+fn test_from_args() {
+    let args: Vec<String> = ["--base", "tools/pairs/", "--data-dir", "/srv/courses"]
+        .iter().map(|s| s.to_string()).collect();
+    let config = Config::from_args(&args).unwrap();
+    assert_eq!(config.base, "/tools/pairs");
+    assert_eq!(config.data_dir, PathBuf::from("/srv/courses"));
+    assert_eq!(config.address, Config::default().address);
+
+    let args = vec!["--base".to_string(), "/".to_string()];
+    assert_eq!(Config::from_args(&args).unwrap().base, "");
+    assert!(Config::from_args(&["--base".to_string()]).is_err());
+    assert!(Config::from_args(&["--port".to_string()]).is_err());
+}
+
+#[test]
+fn test_decorate() {
+    let dir = ::tempfile::TempDir::new().unwrap();
+    let (header, footer) = (dir.path().join("header.html"), dir.path().join("footer.html"));
+    ::std::fs::write(&header, "<nav>Tools</nav>").unwrap();
+    ::std::fs::write(&footer, "<footer>Help</footer>").unwrap();
+    let config = Config { header: Some(header), footer: Some(footer), ..Config::default() };
+    assert_eq!(config.decorate("<body><main>x</main></body>".to_string()),
+               "<body><nav>Tools</nav><main>x</main><footer>Help</footer></body>");
+    assert_eq!(config.decorate("<body>print</body>".to_string()), "<body>print</body>");
+    let config = Config { header: Some(dir.path().join("missing.html")), ..Config::default() };
+    assert_eq!(config.decorate("<main></main>".to_string()), "<main></main>");
+    assert_eq!(Config::default().decorate("<main></main>".to_string()), "<main></main>");
+}
+
+#[test]
+fn test_check_outbound() {
+    let config = Config {
+        outbound_hosts: vec!["lms.example.edu".to_string(), "*.example.com".to_string()],
+        ..Config::default()
+    };
+    assert!(config.check_outbound("https://lms.example.edu/roster").is_ok());
+    assert!(config.check_outbound("https://LMS.example.edu:8443/roster?x=1").is_ok());
+    assert!(config.check_outbound("http://hooks.example.com").is_ok());
+    assert!(config.check_outbound("https://example.com.evil.net/").is_err());
+    assert!(config.check_outbound("http://lms.example.edu@127.0.0.1/").is_err());
+    assert!(config.check_outbound("http://169.254.169.254/latest/meta-data/").is_err());
+    assert!(config.check_outbound("http://[::1]:8088/").is_err());
+    assert!(config.check_outbound("not a url").is_err());
+    assert!(Config::default().check_outbound("https://lms.example.edu/roster").is_err());
+}
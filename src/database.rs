@@ -1,12 +1,22 @@
-use std::collections::{HashSet, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashSet, HashMap};
 // use std::hash::Hash;
 use askama::Template;
 use internment::Intern;
 use atomicfile::AtomicFile;
+use attributes;
+use events;
+use links::{self, Links};
+use notify::{self, Delivery};
+use webhooks::{self, Changes, Event};
+use roster::{RosterSource, RosterSync};
+use preview::{Candidate, Preview};
+use registry::CourseInfo;
+use statistics;
 use serde_yaml;
 use std::str::FromStr;
 use rand::{thread_rng};
 use rand::seq::SliceRandom;
+use std::path::{Path, PathBuf};
 
 #[derive(Template,Serialize,Deserialize,Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash)]
 #[template(path = "day.html")]
@@ -48,48 +58,56 @@ impl FromStr for Day {
     }
 }
 
-#[derive(Template,Serialize,Deserialize,Clone,Copy,Debug,PartialEq,Eq,PartialOrd,Ord,Hash)]
-#[template(path = "section.html")]
+/// Names typed in by users display as plain text, which the templates
+/// escape wherever they appear.  Only types that are templates
+/// themselves, such as `Day` or `TeamOptions`, escape their own
+/// contents and so may be rendered with `|safe`.
+macro_rules! display_name {
+    ($t:ident) => {
+        impl ::std::fmt::Display for $t {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                f.write_str(&self.name)
+            }
+        }
+    }
+}
+
+#[derive(Serialize,Deserialize,Clone,Copy,Debug,PartialEq,Eq,PartialOrd,Ord,Hash)]
 pub struct Section { pub name: Intern<String> }
 impl From<String> for Section {
     fn from(s: String) -> Self {
         Section { name: Intern::new(s) }
     }
 }
+display_name!(Section);
 
-#[derive(Template,Serialize,Deserialize,Clone,Copy,Debug,PartialEq,Eq,PartialOrd,Ord,Hash)]
-#[template(path = "zoom.html")]
+/// The meeting of a section, such as a meeting id or link, from which
+/// `Links::meeting` makes the link.
+#[derive(Serialize,Deserialize,Clone,Copy,Debug,PartialEq,Eq,PartialOrd,Ord,Hash)]
 pub struct Zoom { pub id: Intern<String> }
 impl From<String> for Zoom {
     fn from(s: String) -> Self {
         Zoom { id: Intern::new(s) }
     }
 }
-impl Zoom {
-    pub fn url(&self) -> String {
-        let clean = self.id.replace(&[' ', '-'][..], "");
-        clean
-        // format!("https://oregonstate.zoom.us/j/{}", clean)
-    }
-}
 
-#[derive(Template,Serialize,Deserialize,Clone,Copy,Debug,PartialEq,Eq,PartialOrd,Ord,Hash)]
-#[template(path = "section.html")]
+#[derive(Serialize,Deserialize,Clone,Copy,Debug,PartialEq,Eq,PartialOrd,Ord,Hash)]
 pub struct Student { pub name: Intern<String> }
 impl From<String> for Student {
     fn from(s: String) -> Self {
         Student { name: Intern::new(s) }
     }
 }
+display_name!(Student);
 
-#[derive(Template,Serialize,Deserialize,Clone,Copy,Debug,PartialEq,Eq,PartialOrd,Ord,Hash)]
-#[template(path = "section.html")]
+#[derive(Serialize,Deserialize,Clone,Copy,Debug,PartialEq,Eq,PartialOrd,Ord,Hash)]
 pub struct Team { pub name: Intern<String> }
 impl From<String> for Team {
     fn from(s: String) -> Self {
         Team { name: Intern::new(s) }
     }
 }
+display_name!(Team);
 
 #[derive(Serialize,Deserialize,Debug,Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash)]
 pub enum Pairing {
@@ -169,11 +187,161 @@ impl Pairing {
     }
 }
 
+/// The ways we have of filling in the teams for a day.
+#[derive(Serialize,Deserialize,Debug,Clone,Copy,PartialEq,Eq,Hash)]
+pub enum ShuffleMode {
+    Shuffle,
+    Continuity,
+    Repeat,
+    Scheduled,
+    Balance,
+    Group,
+    Clear,
+    Grand,
+    GrandContinuity,
+}
+impl ShuffleMode {
+    pub fn all() -> Vec<ShuffleMode> {
+        use database::ShuffleMode::*;
+        vec![Shuffle, Continuity, Repeat, Scheduled, Balance, Group, Clear,
+             Grand, GrandContinuity]
+    }
+    /// The short name used on the command line.
+    pub fn name(&self) -> &'static str {
+        use database::ShuffleMode::*;
+        match *self {
+            Shuffle => "shuffle",
+            Continuity => "continuity",
+            Repeat => "repeat",
+            Scheduled => "scheduled",
+            Balance => "balance",
+            Group => "group",
+            Clear => "clear",
+            Grand => "grand",
+            GrandContinuity => "grand-continuity",
+        }
+    }
+    /// The label on the button in the web interface.
+    pub fn label(&self) -> &'static str {
+        use database::ShuffleMode::*;
+        match *self {
+            Shuffle => "Shuffle",
+            Continuity => "Shuffle with continuity",
+            Repeat => "Repeat",
+            Scheduled => "Follow rotation",
+            Balance => "Balance by attribute",
+            Group => "Group by attribute",
+            Clear => "Clear all",
+            Grand => "Grand shuffle",
+            GrandContinuity => "Grand shuffle with continuity",
+        }
+    }
+    /// What was done to `what` (e.g. "section A"), for a flash message.
+    pub fn done(&self, what: &str) -> String {
+        use database::ShuffleMode::*;
+        match *self {
+            Shuffle => format!("Shuffled {}", what),
+            Continuity => format!("Shuffled {} with continuity", what),
+            Repeat => format!("Repeated the teams in {}", what),
+            Scheduled => format!("Followed the rotation in {}", what),
+            Balance => format!("Balanced {} by attribute", what),
+            Group => format!("Grouped {} by attribute", what),
+            Clear => format!("Cleared {}", what),
+            Grand => format!("Grand shuffled {}", what),
+            GrandContinuity => format!("Grand shuffled {} with continuity", what),
+        }
+    }
+    /// Grand shuffles operate on the entire class rather than a
+    /// single section.
+    pub fn is_grand(&self) -> bool {
+        *self == ShuffleMode::Grand || *self == ShuffleMode::GrandContinuity
+    }
+}
+impl ::std::fmt::Display for ShuffleMode {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        f.write_str(self.label())
+    }
+}
+impl FromStr for ShuffleMode {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ShuffleMode::all().into_iter()
+            .find(|m| m.name() == s || m.label() == s)
+            .ok_or_else(|| format!("Unknown shuffle mode {:?}", s))
+    }
+}
+
+/// How the tags of students should influence who works together.
+#[derive(Serialize,Deserialize,Debug,Clone,Copy,PartialEq,Eq,Hash,Default)]
+pub enum TagObjective {
+    #[default]
+    Ignore,
+    /// Prefer partners with as few tags in common as possible.
+    Mix,
+    /// Never pair students who share a tag, unless there is no other
+    /// option, even if that means repeating a partner.
+    AvoidSame,
+}
+impl TagObjective {
+    pub fn all() -> Vec<TagObjective> {
+        vec![TagObjective::Ignore, TagObjective::Mix, TagObjective::AvoidSame]
+    }
+    /// The short name used on the command line.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            TagObjective::Ignore => "ignore",
+            TagObjective::Mix => "mix",
+            TagObjective::AvoidSame => "avoid-same",
+        }
+    }
+    pub fn label(&self) -> &'static str {
+        match *self {
+            TagObjective::Ignore => "Ignore tags",
+            TagObjective::Mix => "Maximize tag mixing",
+            TagObjective::AvoidSame => "Avoid pairing same tag",
+        }
+    }
+}
+impl ::std::fmt::Display for TagObjective {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        f.write_str(self.label())
+    }
+}
+impl FromStr for TagObjective {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TagObjective::all().into_iter()
+            .find(|o| o.name() == s || o.label() == s)
+            .ok_or_else(|| format!("Unknown tag objective {:?}", s))
+    }
+}
+
+/// Where a student can be moved to on a day, see `Data::move_student`.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Destination {
+    Team(Section, Team),
+    /// Whichever team is unused that day, in the section.
+    NewTeam(Section),
+    Unassigned,
+    Absent,
+}
+
+/// The teams and students on a day that the shuffles leave alone.
+#[derive(Serialize,Deserialize,Clone,Debug,Default,PartialEq,Eq)]
+pub struct Pins {
+    #[serde(default)]
+    pub teams: BTreeSet<Team>,
+    #[serde(default)]
+    pub students: BTreeSet<Student>,
+}
+
 #[derive(Serialize,Deserialize,Clone,PartialEq,Eq)]
 pub struct Data {
     #[serde(default)]
     course_path: String,
     #[serde(default)]
+    info: CourseInfo,
+    #[serde(default)]
     student_sections: HashMap<Student, Section>,
     #[serde(default)]
     sections: HashMap<Section, Zoom>,
@@ -185,77 +353,435 @@ pub struct Data {
     daynames: HashMap<usize, Intern<String>>,
     #[serde(default)]
     days_unlocked: HashSet<usize>,
+    /// The lengths of the blocks of days with the same partners.
+    #[serde(default)]
+    rotation: Vec<usize>,
+    /// The days generated by `plan_days` which have not yet been
+    /// locked.
+    #[serde(default)]
+    planned: HashSet<usize>,
+    /// Attributes of each student, by name, e.g. a skill level.
+    #[serde(default)]
+    attributes: HashMap<Student, BTreeMap<String, String>>,
+    /// The attribute used by the balance and group shuffles.
+    #[serde(default)]
+    balance_attribute: Option<String>,
+    /// Free-form tags of each student, e.g. their major.
+    #[serde(default)]
+    tags: HashMap<Student, BTreeSet<String>>,
+    /// How each section uses the tags when choosing partners.
+    #[serde(default)]
+    tag_objectives: HashMap<Section, TagObjective>,
+    /// How many students fit in each section, if limited.
+    #[serde(default)]
+    capacities: HashMap<Section, usize>,
+    /// Students whom a grand shuffle should place in a given section,
+    /// in preference to their own section.
+    #[serde(default)]
+    preferred: HashMap<Section, BTreeSet<Student>>,
+    /// The pins for each day, by day id.
+    #[serde(default)]
+    pins: HashMap<usize, Pins>,
+    /// The shuffles being previewed for each day, by day id.
+    #[serde(default)]
+    previews: HashMap<usize, Preview>,
+    /// The templates for the links of sections and teams.
+    #[serde(default)]
+    links: Links,
+    /// The links to each team's shared resources, by label, as
+    /// reported by the provisioning hook, see `hooks`.
+    #[serde(default)]
+    resources: HashMap<Team, BTreeMap<String, String>>,
+    /// The email address of each student, for publishing days.
+    #[serde(default)]
+    emails: HashMap<Student, String>,
+    /// The messages sent when publishing each day, by day id.
+    #[serde(default)]
+    deliveries: HashMap<usize, Vec<Delivery>>,
+    /// Students who have dropped the course, who are kept for the
+    /// history but left out of new pairings.
+    #[serde(default)]
+    inactive: BTreeSet<Student>,
+    /// Where the roster is synced from, if anywhere.
+    #[serde(default)]
+    roster_source: Option<RosterSource>,
+    /// What each roster sync changed, oldest first.
+    #[serde(default)]
+    roster_log: Vec<RosterSync>,
+    /// The endpoints which are posted each change to the course.
+    #[serde(default)]
+    webhooks: Vec<String>,
+    /// What has changed since the course was read or last saved.
+    #[serde(skip)]
+    changes: Changes,
+    /// The file we were read from, and will be saved to.
+    #[serde(skip)]
+    file: PathBuf,
 }
 
 impl Data {
-    pub fn save(&self) {
-        let f = AtomicFile::create(format!("{}.yaml", self.course_path))
+    pub fn save(&mut self) {
+        let events = self.take_events();
+        let f = AtomicFile::create(&self.file)
             .expect("error creating save file");
-        serde_yaml::to_writer(&f, self).expect("error writing yaml")
+        serde_yaml::to_writer(&f, &*self).expect("error writing yaml");
+        if self.changes.endpoints {
+            self.changes.endpoints = false;
+            if let Err(e) = webhooks::retain_endpoints(&self.file, &self.webhooks) {
+                println!("Error updating the outbox: {}", e);
+            }
+        }
+        if !events.is_empty() && !self.webhooks.is_empty() {
+            if let Err(e) = webhooks::enqueue(&self.file, &self.course_path, &self.webhooks,
+                                              events) {
+                println!("Error queueing events: {}", e);
+            }
+        }
+        if let Some(course) = self.file.file_stem().and_then(|s| s.to_str()) {
+            events::publish(course);
+        }
     }
-    pub fn new(path: &str) -> Self {
+    /// Read the course with the given secret path from the data
+    /// directory, or create it if it does not yet exist.
+    pub fn new<P: AsRef<Path>>(data_dir: P, path: &str) -> Self {
         assert!(path.chars().all(char::is_alphanumeric));
         assert!(path.len() > 10);
-        if let Ok(f) = ::std::fs::File::open(format!("{}.yaml", path)) {
-            match serde_yaml::from_reader::<_,Self>(&f) {
-                Ok(s) => {
-                    return s;
-                }
-                Err(e) => {
-                    println!("Error reading: {:?}", e);
+        let file = data_dir.as_ref().join(format!("{}.yaml", path));
+        match Data::open(&file) {
+            Ok(s) => s,
+            Err(e) => {
+                if file.exists() {
+                    println!("Error reading: {}", e);
                 }
+                Data::empty(path, file)
             }
         }
+    }
+    /// A brand new course with nothing in it, which will be saved to
+    /// `file`.
+    pub fn empty<P: AsRef<Path>>(path: &str, file: P) -> Self {
         Data {
             course_path: path.to_string(),
+            info: CourseInfo::default(),
             days: Vec::new(),
             sections: HashMap::new(),
             student_sections: HashMap::new(),
             teams: HashSet::new(),
             daynames: HashMap::new(),
             days_unlocked: HashSet::new(),
+            rotation: Vec::new(),
+            planned: HashSet::new(),
+            attributes: HashMap::new(),
+            balance_attribute: None,
+            tags: HashMap::new(),
+            tag_objectives: HashMap::new(),
+            capacities: HashMap::new(),
+            preferred: HashMap::new(),
+            pins: HashMap::new(),
+            previews: HashMap::new(),
+            links: Links::default(),
+            resources: HashMap::new(),
+            emails: HashMap::new(),
+            deliveries: HashMap::new(),
+            inactive: BTreeSet::new(),
+            roster_source: None,
+            roster_log: Vec::new(),
+            webhooks: Vec::new(),
+            changes: Changes::default(),
+            file: file.as_ref().to_path_buf(),
         }
     }
+    /// Read a course directly from a yaml file, which must exist.
+    pub fn open<P: AsRef<Path>>(file: P) -> Result<Self, String> {
+        let file = file.as_ref();
+        let f = ::std::fs::File::open(file)
+            .map_err(|e| format!("Unable to open {:?}: {}", file, e))?;
+        let mut data = serde_yaml::from_reader::<_,Self>(&f)
+            .map_err(|e| format!("Unable to parse {:?}: {}", file, e))?;
+        data.file = file.to_path_buf();
+        Ok(data)
+    }
+    pub fn path(&self) -> &str {
+        &self.course_path
+    }
+    /// The file the course is saved to.
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+    pub fn info(&self) -> &CourseInfo {
+        &self.info
+    }
+    pub fn set_info(&mut self, info: CourseInfo) {
+        self.info = info;
+    }
+    /// Start a new course at `path` in the data directory, with the
+    /// same sections (and their zooms) and teams as this one but no
+    /// days.  If `roster` is true, the students still taking this course
+    /// are copied along with their emails, attributes, tags and
+    /// preferred sections; students who dropped it are left out, and
+    /// the roster source is not copied since a new term has a new list.
+    pub fn clone_structure<P: AsRef<Path>>(&self, data_dir: P, path: &str,
+                                           info: CourseInfo, roster: bool) -> Data {
+        let mut data = Data::empty(path, data_dir.as_ref().join(format!("{}.yaml", path)));
+        data.info = info;
+        data.sections = self.sections.clone();
+        data.teams = self.teams.clone();
+        data.rotation = self.rotation.clone();
+        data.balance_attribute = self.balance_attribute.clone();
+        data.tag_objectives = self.tag_objectives.clone();
+        data.capacities = self.capacities.clone();
+        data.links = self.links.clone();
+        if roster {
+            let active = |s: &Student| self.is_active(*s);
+            data.student_sections = self.student_sections.iter()
+                .filter(|(s, _)| active(s)).map(|(&s, &sec)| (s, sec)).collect();
+            data.emails = self.emails.iter()
+                .filter(|(s, _)| active(s)).map(|(&s, e)| (s, e.clone())).collect();
+            data.attributes = self.attributes.iter()
+                .filter(|(s, _)| active(s)).map(|(&s, a)| (s, a.clone())).collect();
+            data.tags = self.tags.iter()
+                .filter(|(s, _)| active(s)).map(|(&s, t)| (s, t.clone())).collect();
+            data.preferred = self.preferred.iter()
+                .map(|(&sec, ss)| (sec, ss.iter().cloned().filter(active).collect::<BTreeSet<_>>()))
+                .filter(|(_, ss)| !ss.is_empty()).collect();
+        }
+        data
+    }
     pub fn day(&mut self, day: Day) -> &HashSet<Pairing> {
         while day.id >= self.days.len() {
             self.days.push(HashSet::new());
         }
         return &self.days[day.id];
     }
+    /// The pairings for every day, indexed by day id.
+    pub fn pairings(&self) -> &[HashSet<Pairing>] {
+        &self.days
+    }
+    /// Like `improve_day`, but `None` if the course has no such day.
+    pub fn existing_day(&self, day: Day) -> Option<Day> {
+        if day.id < self.days.len() {
+            Some(self.improve_day(day))
+        } else {
+            None
+        }
+    }
+    /// The day with its name and whether it is unlocked filled in,
+    /// whether or not it has a name (a planned day usually has none,
+    /// but is unlocked).
     pub fn improve_day(&self, day: Day) -> Day {
-        if let Some(&n) = self.daynames.get(&day.id) {
-            return Day { id: day.id, name: Some(n), unlocked: self.day_unlocked(day) };
+        Day {
+            id: day.id,
+            name: self.daynames.get(&day.id).cloned(),
+            unlocked: self.day_unlocked(day),
         }
-        day
     }
     pub fn name_day(&mut self, id: usize, name: String) {
         self.daynames.insert(id, Intern::new(name));
     }
-    fn nonrepeat_partners_for_day(&self, day: Day, s1: Student, s2: Student) -> bool {
-        for d in 0..day.id {
-            if self.days[d].iter().any(|p| p.present_students().contains(&s1)
-                                           && p.present_students().contains(&s2)) {
-                return false;
+    /// The days prior to `day` on which `s1` and `s2` worked together,
+    /// and the team they were on.
+    pub fn partner_history(&self, day: Day, s1: Student, s2: Student) -> Vec<(Day, Team)> {
+        let mut history = Vec::new();
+        for d in 0..::std::cmp::min(day.id, self.days.len()) {
+            if let Some(p) = self.days[d].iter().find(|p| p.present_students().contains(&s1)
+                                                       && p.present_students().contains(&s2)) {
+                if let Some(team) = p.team() {
+                    history.push((self.improve_day(Day::from(d)), team));
+                }
             }
         }
-        true
+        history
+    }
+    fn nonrepeat_partners_for_day(&self, day: Day, s1: Student, s2: Student) -> bool {
+        self.partner_history(day, s1, s2).is_empty()
     }
+    /// Pick the first of the options who has not yet worked with `s1`,
+    /// or if there is no such option, the first option.  When the
+    /// section of `s1` has a `TagObjective`, options with fewer tags in
+    /// common come first, and with `AvoidSame` that matters even more
+    /// than avoiding a repeat.
     fn pick_partner_from(&self, day: Day, s1: Student, options: &mut Vec<Student>)
                          -> Option<Student> {
-        if let Some(s2) = options.iter().cloned()
-            .filter(|&s2| self.nonrepeat_partners_for_day(day, s1, s2))
-            .next()
-        {
-            *options = options.iter().cloned().filter(|&s| s != s2).collect();
-            Some(s2)
+        let hard = self.student_tag_objective(s1) == TagObjective::AvoidSame;
+        let key = |s2: Student| {
+            let repeat = if self.nonrepeat_partners_for_day(day, s1, s2) { 0.0 } else { 1.0 };
+            let tags = self.tag_cost(s1, s2);
+            if hard { (tags, repeat) } else { (repeat, tags) }
+        };
+        let i = options.iter().map(|&s2| key(s2)).enumerate()
+            .min_by(|(_, a), (_, b)| a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1)))
+            .map(|(i, _)| i)?;
+        Some(options.remove(i))
+    }
+    fn student_tag_objective(&self, s: Student) -> TagObjective {
+        self.student_section(s).map(|sec| self.tag_objective(sec)).unwrap_or_default()
+    }
+    /// How much we would rather not pair `s1` with `s2` according to
+    /// the tag objective of the section of `s1`, from 0 to 1.
+    fn tag_cost(&self, s1: Student, s2: Student) -> f64 {
+        let (t1, t2) = match (self.tags.get(&s1), self.tags.get(&s2)) {
+            (Some(t1), Some(t2)) => (t1, t2),
+            _ => return 0.0,
+        };
+        let shared = t1.intersection(t2).count();
+        match self.student_tag_objective(s1) {
+            TagObjective::Ignore => 0.0,
+            TagObjective::Mix => shared as f64 / t1.union(t2).count() as f64,
+            TagObjective::AvoidSame => if shared > 0 { 1.0 } else { 0.0 },
+        }
+    }
+    /// Shuffle the students, and then sort them by how unlucky they
+    /// have been prior to `day`, least unlucky first.  See
+    /// `statistics::StudentStats::bad_luck`.
+    fn shuffle_by_luck(&self, day: Day, students: &mut [Student]) {
+        students.shuffle(&mut thread_rng());
+        let luck = statistics::bad_luck(self, day);
+        students.sort_by_key(|s| luck.get(s).cloned().unwrap_or(0));
+    }
+    /// Pair up students (sorted by `shuffle_by_luck`) into teams.  The
+    /// most unlucky students pick partners first, so they are least
+    /// likely to be stuck with a repeat, and when there is an odd
+    /// number it is the least unlucky student who ends up solo.
+    fn pair_remaining(&mut self, day: Day, section: Section,
+                      students: Vec<Student>, possible_teams: Vec<Team>) {
+        self.pair_ranked(day, section, students, possible_teams, &|_, _| 0.0);
+    }
+    /// Like `pair_remaining`, but each student prefers the partners for
+    /// whom `cost` is lowest, provided they have not worked together
+    /// before.
+    fn pair_ranked(&mut self, day: Day, section: Section,
+                   mut students: Vec<Student>, mut possible_teams: Vec<Team>,
+                   cost: &dyn Fn(Student, Student) -> f64) {
+        possible_teams.sort();
+        possible_teams.reverse();
+        let solo = if students.len() % 2 == 1 {
+            Some(students.remove(0))
         } else {
-            options.pop()
+            None
+        };
+        while students.len() > 1 && !possible_teams.is_empty() {
+            let primary = students.pop().unwrap();
+            let team = possible_teams.pop().unwrap();
+            let mut options = students.clone();
+            options.sort_by(|&a, &b| cost(primary, a).total_cmp(&cost(primary, b)));
+            let secondary = self.pick_partner_from(day, primary, &mut options).unwrap();
+            students.retain(|&s| s != secondary);
+            self.days[day.id].insert(Pairing::Pair { primary, secondary, section, team });
+        }
+        if let Some(student) = solo.or_else(|| students.pop()) {
+            if let Some(team) = possible_teams.pop() {
+                self.days[day.id].insert(Pairing::Solo { student, team, section });
+            }
         }
     }
+    pub fn pins(&self, day: Day) -> Pins {
+        self.pins.get(&day.id).cloned().unwrap_or_default()
+    }
+    /// Pin (or unpin) a team, so that shuffles leave its students alone.
+    pub fn toggle_pin_team(&mut self, day: Day, team: Team) {
+        let pins = self.pins.entry(day.id).or_default();
+        if !pins.teams.remove(&team) {
+            pins.teams.insert(team);
+        }
+        if pins.teams.is_empty() && pins.students.is_empty() {
+            self.pins.remove(&day.id);
+        }
+    }
+    /// Pin (or unpin) a student, so that shuffles leave them on their
+    /// current team, though possibly with a new partner.
+    pub fn toggle_pin_student(&mut self, day: Day, student: Student) {
+        let pins = self.pins.entry(day.id).or_default();
+        if !pins.students.remove(&student) {
+            pins.students.insert(student);
+        }
+        if pins.teams.is_empty() && pins.students.is_empty() {
+            self.pins.remove(&day.id);
+        }
+    }
+    /// Is this pairing to be left alone by the shuffles?
+    pub fn is_pinned(&self, day: Day, p: &Pairing) -> bool {
+        let pins = match self.pins.get(&day.id) {
+            Some(pins) => pins,
+            None => return false,
+        };
+        match *p {
+            Pairing::Absent(_) => false,
+            _ => p.team().map(|t| pins.teams.contains(&t)).unwrap_or(false)
+                || p.allocated_students().iter().any(|s| pins.students.contains(s)),
+        }
+    }
+    /// The students in pinned pairings on `day`, in `section` or in
+    /// every section if `None`.
+    fn pinned_students(&self, day: Day, section: Option<Section>) -> Vec<Student> {
+        self.days[day.id].iter()
+            .filter(|p| section.is_none() || p.section() == section)
+            .filter(|p| self.is_pinned(day, p))
+            .flat_map(|p| p.allocated_students())
+            .collect()
+    }
+    /// Release the partners of pinned students whose team is not
+    /// pinned, returning the pinned students who are now alone.
+    fn split_pins(&mut self, day: Day, section: Option<Section>) -> Vec<Student> {
+        let pins = self.pins(day);
+        let split: Vec<Pairing> = self.days[day.id].iter().cloned()
+            .filter(|p| section.is_none() || p.section() == section)
+            .filter(|p| match *p {
+                Pairing::Pair { primary, secondary, team, .. } => !pins.teams.contains(&team)
+                    && pins.students.contains(&primary) != pins.students.contains(&secondary),
+                _ => false,
+            })
+            .collect();
+        let mut lonely = Vec::new();
+        for p in split {
+            if let Pairing::Pair { primary, secondary, team, section } = p {
+                let (pinned, other) = if pins.students.contains(&primary) {
+                    (primary, secondary)
+                } else {
+                    (secondary, primary)
+                };
+                self.days[day.id].remove(&p);
+                self.days[day.id].insert(Pairing::Solo { student: pinned, team, section });
+                self.days[day.id].insert(Pairing::Unassigned { student: other, section });
+                lonely.push(pinned);
+            }
+        }
+        lonely
+    }
+    /// Find partners from `students` for the pinned students who were
+    /// left alone by `split_pins`.
+    fn fill_pins(&mut self, day: Day, lonely: Vec<Student>, students: &mut Vec<Student>) {
+        for primary in lonely {
+            let solo = self.days[day.id].iter().cloned().find(|p| match *p {
+                Pairing::Solo { student, .. } => student == primary,
+                _ => false,
+            });
+            if let Some(Pairing::Solo { team, section, .. }) = solo {
+                if let Some(secondary) = self.pick_partner_from(day, primary, students) {
+                    self.unassign_student(day, secondary);
+                    self.days[day.id].remove(&Pairing::Solo { student: primary, team, section });
+                    self.days[day.id].insert(Pairing::Pair { primary, secondary, section, team });
+                }
+            }
+        }
+    }
+    /// Remove the pairings in `section` that are not pinned, returning
+    /// the students who are then free to be shuffled.
+    fn clear_unpinned(&mut self, day: Day, section: Section) -> Vec<Student> {
+        let lonely = self.split_pins(day, Some(section));
+        let mut students = self.students_present_in_section(day, section);
+        let pinned = self.pinned_students(day, Some(section));
+        students.retain(|s| !pinned.contains(s));
+        let kept: Vec<Pairing> = self.days[day.id].iter().cloned()
+            .filter(|p| p.section() == Some(section) && self.is_pinned(day, p))
+            .collect();
+        self.days[day.id].retain(|p| p.section() != Some(section) || kept.contains(p));
+        self.fill_pins(day, lonely, &mut students);
+        students
+    }
     pub fn students_present_in_section(&self, day: Day, section: Section)
                                        -> Vec<Student> {
         let mut students: Vec<Student> = self.student_sections.iter()
-            .filter(|(_,&sec)| sec == section)
+            .filter(|(s,&sec)| sec == section && self.is_active(**s))
             .map(|(&s,_)| s)
             .collect();
         for p in self.days[day.id].iter().cloned() {
@@ -277,54 +803,139 @@ impl Data {
         students.retain(|s| !absent.contains(s));
         students
     }
-    pub fn shuffle_sections(&mut self, day: Day) {
-        let mut sections: Vec<_> = self.sections.keys().cloned().collect();
-        sections.sort();
+    /// Distribute the teams of a grand shuffle among the sections.  Each
+    /// team goes to the section that most of its members prefer (or
+    /// else call home) if it has room, and otherwise to the section with
+    /// the fewest students that has room.  Returns a description of any
+    /// problems, such as sections over capacity.
+    pub fn shuffle_sections(&mut self, day: Day) -> Vec<String> {
+        self.touch_day(day);
+        let sections = self.list_sections();
+        let mut load: HashMap<Section, usize> = sections.iter().map(|&s| (s, 0)).collect();
         let mut pairings: Vec<_> = self.days[day.id].drain().collect();
-        println!("we have {} total pairings", pairings.len());
-        self.days[day.id].extend(pairings.iter().filter(|p| !p.team().is_some()).cloned());
+        for p in pairings.iter().filter(|p| p.team().is_none()) {
+            match *p {
+                Pairing::Unassigned { student, section } => {
+                    let section = self.student_section(student)
+                        .filter(|s| load.contains_key(s)).unwrap_or(section);
+                    *load.get_mut(&section).unwrap() += 1;
+                    self.days[day.id].insert(Pairing::Unassigned { student, section });
+                }
+                x => {
+                    self.days[day.id].insert(x);
+                }
+            }
+        }
         pairings.retain(|p| p.team().is_some());
-        println!("we have {} pairings that have a team", pairings.len());
-        pairings.sort_by_key(|p| p.team());
-        for (p, section) in split_evenly(&pairings, sections.len()).zip(sections.into_iter()) {
-            for pairing in p.iter().cloned() {
-                match pairing {
-                    Pairing::Pair { team, primary, secondary, .. } => {
-                        self.days[day.id].insert(Pairing::Pair { team, primary, secondary, section });
-                    }
-                    Pairing::Solo { team, student, ..  } => {
-                        self.days[day.id].insert(Pairing::Solo { team, student, section });
-                    }
-                    x => {
-                        self.days[day.id].insert(x);
+        for p in pairings.iter().filter(|p| self.is_pinned(day, p)) {
+            if let Some(section) = p.section().filter(|s| load.contains_key(s)) {
+                *load.get_mut(&section).unwrap() += p.present_students().len();
+            }
+        }
+        let (pinned, mut pairings): (Vec<_>, Vec<_>) =
+            pairings.into_iter().partition(|p| self.is_pinned(day, p));
+        self.days[day.id].extend(pinned);
+        let wishes = |p: &Pairing| -> HashMap<Section, usize> {
+            let mut wishes = HashMap::new();
+            for s in p.present_students() {
+                if let Some(home) = self.student_section(s) {
+                    *wishes.entry(home).or_insert(0) += 1;
+                }
+                // A preference outweighs the home section of both
+                // members of a pair.
+                for (&section, preferred) in self.preferred.iter() {
+                    if preferred.contains(&s) {
+                        *wishes.entry(section).or_insert(0) += 3;
                     }
                 }
             }
+            wishes
+        };
+        // The teams with the strongest wishes are placed first.
+        pairings.sort_by_key(|p| p.team());
+        pairings.sort_by_key(|p| ::std::cmp::Reverse(wishes(p).values().max().cloned()
+                                                    .unwrap_or(0)));
+        let mut away = 0;
+        let mut placed = Vec::new();
+        for pairing in pairings {
+            let n = pairing.present_students().len();
+            let wishes = wishes(&pairing);
+            let fits = |s: &Section| self.capacity(*s).map(|c| load[s] + n <= c).unwrap_or(true);
+            let roomiest = |s: &Section| self.capacity(*s).map(|c| c as isize - load[s] as isize);
+            let section = sections.iter().filter(|s| fits(s))
+                .max_by_key(|s| (wishes.get(s).cloned().unwrap_or(0),
+                                 ::std::cmp::Reverse(load[s])))
+                .or_else(|| sections.iter().max_by_key(|s| roomiest(s)))
+                .cloned().unwrap();
+            *load.get_mut(&section).unwrap() += n;
+            away += pairing.present_students().iter()
+                .filter(|&&s| self.student_section(s) != Some(section)).count();
+            placed.push(match pairing {
+                Pairing::Pair { team, primary, secondary, .. } => {
+                    Pairing::Pair { team, primary, secondary, section }
+                }
+                Pairing::Solo { team, student, ..  } => {
+                    Pairing::Solo { team, student, section }
+                }
+                x => x,
+            });
+        }
+        self.days[day.id].extend(placed);
+        let mut problems = self.capacity_problems(day);
+        if away > 0 {
+            problems.push(format!("{} students are away from their home section", away));
         }
+        problems
+    }
+    /// The sections which have more students on `day` than they have
+    /// room for.
+    pub fn capacity_problems(&self, day: Day) -> Vec<String> {
+        let mut problems = Vec::new();
+        for section in self.list_sections() {
+            let n = self.days[day.id].iter().filter(|p| p.section() == Some(section))
+                .map(|p| p.present_students().len()).sum::<usize>();
+            if let Some(capacity) = self.capacity(section) {
+                if n > capacity {
+                    problems.push(format!("Section {} is over capacity: {} students for {} places",
+                                          section, n, capacity));
+                }
+            }
+        }
+        problems
     }
-    pub fn grand_shuffle(&mut self, day: Day) {
+    pub fn grand_shuffle(&mut self, day: Day) -> Vec<String> {
+        self.touch_day(day);
         let section = self.sections.keys().cloned().next().expect("Oops, need a section");
+        let lonely = self.split_pins(day, None);
         let absent: Vec<_> = self.absent_students(day);
-        let students: Vec<_> = self.student_sections.keys().cloned()
-            .filter(|s| !absent.contains(s)).collect();
-        for student in students.into_iter() {
+        let pinned = self.pinned_students(day, None);
+        let mut students: Vec<_> = self.student_sections.keys().cloned()
+            .filter(|&s| self.is_active(s) && !absent.contains(&s) && !pinned.contains(&s))
+            .collect();
+        for &student in students.iter() {
             self.unassign_student(day, student);
             self.days[day.id].insert(Pairing::Unassigned { student, section });
         }
+        self.fill_pins(day, lonely, &mut students);
 
         self.shuffle(day, section);
-        self.shuffle_sections(day);
+        self.shuffle_sections(day)
     }
-    pub fn grand_shuffle_with_continuity(&mut self, day: Day) {
+    pub fn grand_shuffle_with_continuity(&mut self, day: Day) -> Vec<String> {
+        self.touch_day(day);
         let section = self.sections.keys().cloned().next().expect("Oops, need a section");
+        let lonely = self.split_pins(day, None);
         let absent: Vec<_> = self.absent_students(day);
+        let pinned = self.pinned_students(day, None);
         let mut students: Vec<_> = self.student_sections.keys().cloned()
-            .filter(|s| !absent.contains(s)).collect();
+            .filter(|&s| self.is_active(s) && !absent.contains(&s) && !pinned.contains(&s))
+            .collect();
         for &student in students.iter() {
             self.unassign_student(day, student);
             self.days[day.id].insert(Pairing::Unassigned { student, section });
         }
-        students.shuffle(&mut thread_rng());
+        self.fill_pins(day, lonely, &mut students);
+        self.shuffle_by_luck(day, &mut students);
         let mut last_week_pairs: Vec<_> =
             if day.id > 0 {
                 self.days[day.id-1].iter().cloned()
@@ -334,8 +945,13 @@ impl Data {
                 Vec::new()
             };
         last_week_pairs.shuffle(&mut thread_rng());
+        let kept: Vec<Pairing> = self.days[day.id].iter().cloned()
+            .filter(|p| self.is_pinned(day, p)).collect();
         self.days[day.id] = absent.into_iter().map(|s| Pairing::Absent(s)).collect();
-        let mut possible_teams: Vec<_> = self.teams.iter().cloned().collect();
+        self.days[day.id].extend(kept);
+        let mut possible_teams: Vec<_> = self.teams.iter().cloned()
+            .filter(|&t| !self.days[day.id].iter().any(|p| p.team() == Some(t))).collect();
+        last_week_pairs.retain(|p| possible_teams.contains(&p.team().unwrap()));
         let mut newpairings = Vec::new();
         for p in last_week_pairs.into_iter() {
             let team = p.team().unwrap();
@@ -355,47 +971,38 @@ impl Data {
             }
             self.days[day.id].insert(p);
         }
-        possible_teams.sort();
-        possible_teams.reverse();
-        while students.len() > 1 && possible_teams.len() > 0 {
-            let primary = students.pop().unwrap();
-            let team = possible_teams.pop().unwrap();
-            let secondary = self.pick_partner_from(day, primary, &mut students).unwrap();
-            self.days[day.id].insert(Pairing::Pair { primary, secondary, section, team });
-        }
-        if let Some(student) = students.pop() {
-            if let Some(team) = possible_teams.pop() {
-                self.days[day.id].insert(Pairing::Solo { student, team, section });
-            }
-        }
+        self.pair_remaining(day, section, students, possible_teams);
 
-        self.shuffle_sections(day);
+        self.shuffle_sections(day)
     }
     pub fn shuffle(&mut self, day: Day, section: Section) {
-        let mut students: Vec<Student> = self.students_present_in_section(day, section);
-        students.shuffle(&mut thread_rng());
-        self.days[day.id] = self.days[day.id].iter().cloned()
-            .filter(|p| p.section() != Some(section)).collect();
-        let mut possible_teams: Vec<_> =
+        self.touch_day(day);
+        let mut students: Vec<Student> = self.clear_unpinned(day, section);
+        self.shuffle_by_luck(day, &mut students);
+        let possible_teams: Vec<_> =
             self.teams.iter().cloned()
             .filter(|&t| !self.days[day.id].iter().any(|p| p.team() == Some(t))).collect();
-        possible_teams.sort();
-        possible_teams.reverse();
-        while students.len() > 1 && possible_teams.len() > 0 {
-            let primary = students.pop().unwrap();
-            let team = possible_teams.pop().unwrap();
-            let secondary = self.pick_partner_from(day, primary, &mut students).unwrap();
-            self.days[day.id].insert(Pairing::Pair { primary, secondary, section, team });
-        }
-        if let Some(student) = students.pop() {
-            if let Some(team) = possible_teams.pop() {
-                self.days[day.id].insert(Pairing::Solo { student, team, section });
-            }
-        }
+        self.pair_remaining(day, section, students, possible_teams);
+    }
+    /// Shuffle so as to balance (or group) students according to the
+    /// `balance_attribute`.  See `attributes::partner_cost`.
+    pub fn shuffle_by_attribute(&mut self, day: Day, section: Section, balance: bool) {
+        self.touch_day(day);
+        let mut students: Vec<Student> = self.clear_unpinned(day, section);
+        self.shuffle_by_luck(day, &mut students);
+        let possible_teams: Vec<_> =
+            self.teams.iter().cloned()
+            .filter(|&t| !self.days[day.id].iter().any(|p| p.team() == Some(t))).collect();
+        let cost = match self.balance_attribute {
+            Some(ref attribute) => attributes::partner_cost(self, &students, attribute, balance),
+            None => Box::new(|_, _| 0.0),
+        };
+        self.pair_ranked(day, section, students, possible_teams, &*cost);
     }
     pub fn shuffle_with_continuity(&mut self, day: Day, section: Section) {
-        let mut students: Vec<Student> = self.students_present_in_section(day, section);
-        students.shuffle(&mut thread_rng());
+        self.touch_day(day);
+        let mut students: Vec<Student> = self.clear_unpinned(day, section);
+        self.shuffle_by_luck(day, &mut students);
         let last_week_pairs: Vec<_> =
             if day.id > 0 {
                 self.days[day.id-1].iter().cloned()
@@ -404,7 +1011,6 @@ impl Data {
             } else {
                 Vec::new()
             };
-        self.days[day.id].retain(|p| p.section() != Some(section));
         let mut possible_teams: Vec<_> =
             self.teams.iter().cloned()
             .filter(|&t| !self.days[day.id].iter().any(|p| p.team() == Some(t))).collect();
@@ -433,33 +1039,26 @@ impl Data {
             }
             self.days[day.id].insert(p);
         }
-        possible_teams.sort();
-        possible_teams.reverse();
-        while students.len() > 1 && possible_teams.len() > 0 {
-            let primary = students.pop().unwrap();
-            let team = possible_teams.pop().unwrap();
-            let secondary = self.pick_partner_from(day, primary, &mut students).unwrap();
-            self.days[day.id].insert(Pairing::Pair { primary, secondary, section, team });
-        }
-        if let Some(student) = students.pop() {
-            if let Some(team) = possible_teams.pop() {
-                self.days[day.id].insert(Pairing::Solo { student, team, section });
-            }
-        }
+        self.pair_remaining(day, section, students, possible_teams);
     }
     pub fn repeat(&mut self, day: Day, section: Section) {
-        let mut students: Vec<Student> = self.students_present_in_section(day, section);
-        students.shuffle(&mut thread_rng());
+        self.touch_day(day);
+        self.repeat_from(day, day.previous(), section);
+    }
+    /// Repeat the teams from an earlier day, as far as attendance
+    /// allows.
+    pub fn repeat_from(&mut self, day: Day, source: Day, section: Section) {
+        self.touch_day(day);
+        let mut students: Vec<Student> = self.clear_unpinned(day, section);
+        self.shuffle_by_luck(day, &mut students);
         let last_week_pairs: Vec<_> =
-            if day.id > 0 {
-                self.days[day.id-1].iter().cloned()
+            if source.id < day.id {
+                self.days[source.id].iter().cloned()
                     .filter(|p| p.section() == Some(section) && p.team().is_some())
                     .collect()
             } else {
                 Vec::new()
             };
-        self.days[day.id] = self.days[day.id].iter().cloned()
-            .filter(|p| p.section() != Some(section)).collect();
         let mut possible_teams: Vec<_> =
             self.teams.iter().cloned()
             .filter(|&t| !self.days[day.id].iter().any(|p| p.team() == Some(t))).collect();
@@ -469,29 +1068,23 @@ impl Data {
             .collect();
         last_week_pairs.shuffle(&mut thread_rng());
         let mut newpairings = Vec::new();
-        for p in last_week_pairs.iter().cloned() {
-            println!("   {:?}", p);
-        }
         for p in last_week_pairs.into_iter() {
             let team = p.team().unwrap();
             match p {
                 Pairing::Pair { primary, secondary, .. } => {
                     if remove_student_from_vec(primary, &mut students).is_some() {
                         if remove_student_from_vec(secondary, &mut students).is_some() {
-                            println!("{} and {} are still in {}", primary, secondary, team);
                             newpairings.push(Pairing::Pair {
                                 primary, secondary, section, team });
                             possible_teams = possible_teams.iter().cloned()
                                 .filter(|&t| t != team).collect();
                         } else {
-                            println!("{} is alas alone in {}", primary, team);
                             newpairings.push(Pairing::Solo { student: primary, section, team });
                             possible_teams = possible_teams.iter().cloned()
                                 .filter(|&t| t != team).collect();
                         }
                     } else {
                         if remove_student_from_vec(secondary, &mut students).is_some() {
-                            println!(">>> {} is now alone in {} ({} dropped)", secondary, team, primary);
                             newpairings.push(Pairing::Solo { student: secondary,
                                                              section, team });
                             possible_teams = possible_teams.iter().cloned()
@@ -501,7 +1094,6 @@ impl Data {
                 }
                 Pairing::Solo { student, .. } => {
                     if let Some(student) = remove_student_from_vec(student, &mut students) {
-                        println!("{} is still alone in {}", student, team);
                         newpairings.push(Pairing::Solo { student, section, team });
                         possible_teams = possible_teams.iter().cloned()
                             .filter(|&t| t != team).collect();
@@ -513,25 +1105,106 @@ impl Data {
         for mut p in newpairings.into_iter() {
             if let Pairing::Solo { student, team, .. } = p {
                 if let Some(secondary) = self.pick_partner_from(day, student, &mut students) {
-                    println!("choosing {} to go with {} in {}",
-                             secondary, student, team);
                     p = Pairing::Pair { primary: student, secondary, section, team };
                 }
             }
             self.days[day.id].insert(p);
         }
-        possible_teams.sort();
-        possible_teams.reverse();
-        println!("still have remaining {} students", students.len());
-        while students.len() > 1 && possible_teams.len() > 0 {
-            let primary = students.pop().unwrap();
-            let team = possible_teams.pop().unwrap();
-            let secondary = self.pick_partner_from(day, primary, &mut students).unwrap();
-            self.days[day.id].insert(Pairing::Pair { primary, secondary, section, team });
+        self.pair_remaining(day, section, students, possible_teams);
+    }
+    /// Apply a shuffle mode to a day.  For the modes that work on a
+    /// single section, `None` means to apply it to every section.
+    /// Returns a description of any problems, such as sections over
+    /// capacity.
+    pub fn run_shuffle(&mut self, day: Day, mode: ShuffleMode, section: Option<Section>)
+                       -> Vec<String> {
+        self.touch_day(day);
+        if mode.is_grand() {
+            if self.sections.is_empty() {
+                return Vec::new();
+            }
+            let problems = match mode {
+                ShuffleMode::Grand => self.grand_shuffle(day),
+                _ => self.grand_shuffle_with_continuity(day),
+            };
+            self.assign_roles(day, None);
+            return problems;
         }
-        if let Some(student) = students.pop() {
-            if let Some(team) = possible_teams.pop() {
-                self.days[day.id].insert(Pairing::Solo { student, team, section });
+        let sections = match section {
+            Some(section) => vec![section],
+            None => self.list_sections(),
+        };
+        for section in sections {
+            match mode {
+                ShuffleMode::Shuffle => self.shuffle(day, section),
+                ShuffleMode::Continuity => self.shuffle_with_continuity(day, section),
+                ShuffleMode::Repeat => self.repeat(day, section),
+                ShuffleMode::Scheduled => self.scheduled_shuffle(day, section),
+                ShuffleMode::Balance => self.shuffle_by_attribute(day, section, true),
+                ShuffleMode::Group => self.shuffle_by_attribute(day, section, false),
+                _ => {
+                    let pinned = self.pinned_students(day, Some(section));
+                    for s in self.students_present_in_section(day, section) {
+                        if !pinned.contains(&s) {
+                            self.unpair_student(day, s);
+                        }
+                    }
+                }
+            }
+        }
+        self.assign_roles(day, section);
+        self.capacity_problems(day)
+    }
+    pub fn preview(&self, day: Day) -> Option<&Preview> {
+        self.previews.get(&day.id)
+    }
+    /// Remember `candidates` for `day`, to be accepted later if the day
+    /// does not change in the meantime.
+    pub fn set_preview(&mut self, day: Day, candidates: Vec<Candidate>) {
+        let base = self.days[day.id].clone();
+        self.previews.insert(day.id, Preview { base, candidates });
+    }
+    pub fn discard_preview(&mut self, day: Day) {
+        self.previews.remove(&day.id);
+    }
+    /// Replace the pairings of `day` with candidate `i` of its preview.
+    pub fn accept_candidate(&mut self, day: Day, i: usize) -> Result<(), String> {
+        self.touch_day(day);
+        let candidate = match self.previews.get(&day.id) {
+            None => return Err(format!("There is no preview for {}", day.pretty())),
+            Some(preview) if preview.base != self.days[day.id] => {
+                return Err(format!("The pairings for {} have changed since the preview",
+                                   day.pretty()));
+            }
+            Some(preview) => match preview.candidates.get(i) {
+                Some(candidate) => candidate.pairings.clone(),
+                None => return Err(format!("There is no candidate {}", i)),
+            },
+        };
+        self.days[day.id] = candidate;
+        self.previews.remove(&day.id);
+        Ok(())
+    }
+    /// Decide who drives (the primary) and who navigates (the
+    /// secondary) in each pair on `day`, giving each student the role
+    /// they have done less often.  `None` means every section.
+    pub fn assign_roles(&mut self, day: Day, section: Option<Section>) {
+        self.touch_day(day);
+        let balance = statistics::role_balance(self, day);
+        let balance = |s| balance.get(&s).cloned().unwrap_or(0);
+        let swaps: Vec<Pairing> = self.days[day.id].iter().cloned()
+            .filter(|p| section.is_none() || p.section() == section)
+            .filter(|p| !self.is_pinned(day, p))
+            .filter(|p| match *p {
+                Pairing::Pair { primary, secondary, .. } => balance(primary) > balance(secondary),
+                _ => false,
+            })
+            .collect();
+        for p in swaps {
+            self.days[day.id].remove(&p);
+            if let Pairing::Pair { primary, secondary, section, team } = p {
+                self.days[day.id].insert(Pairing::Pair {
+                    primary: secondary, secondary: primary, section, team });
             }
         }
     }
@@ -665,6 +1338,9 @@ impl Data {
             let mut options = Vec::new();
             for s in students.iter().cloned() {
                 let current_pairing = pairings.iter().filter(|p| p.has(s)).cloned().next();
+                if current_pairing.is_none() && !self.is_active(s) {
+                    continue;
+                }
                 let previous_team = if day.id > 0 {
                     self.days[day.id-1].iter()
                         .filter(|p| p.has(s) && p.full_pair())
@@ -722,7 +1398,7 @@ impl Data {
         }
         self.student_sections.keys()
             .cloned()
-            .filter(|&s| !self.days[day.id].iter()
+            .filter(|&s| self.is_active(s) && !self.days[day.id].iter()
                     .any(|p| p.assigned_students().contains(&s)))
             .collect()
     }
@@ -730,8 +1406,158 @@ impl Data {
     pub fn list_days(&self) -> Vec<Day> {
         (0..self.days.len()).map(|i| self.improve_day(Day::from(i))).collect()
     }
+    /// Add a day, which is filled in right away if the course follows
+    /// a rotation schedule.
     pub fn add_day(&mut self) {
         self.days.push(HashSet::new());
+        let day = Day::from(self.days.len() - 1);
+        self.emit(Event::DayCreated { day: day.id, name: self.improve_day(day).pretty() });
+        if self.block_start(day).is_some() && !self.teams.is_empty() {
+            self.run_shuffle(day, ShuffleMode::Scheduled, None);
+        }
+    }
+    pub fn rotation(&self) -> &[usize] {
+        &self.rotation
+    }
+    /// Set the lengths of the blocks of days during which students keep
+    /// the same partners.  The lengths are cycled, so `[3]` means a new
+    /// block every three days.
+    pub fn set_rotation(&mut self, blocks: Vec<usize>) {
+        self.rotation = blocks.into_iter().filter(|&n| n > 0).collect();
+    }
+    /// The first day of the rotation block containing `day`, or `None`
+    /// if the course has no rotation schedule.
+    pub fn block_start(&self, day: Day) -> Option<Day> {
+        if !self.rotation.iter().any(|&n| n > 0) {
+            return None;
+        }
+        let mut start = 0;
+        for &n in self.rotation.iter().cycle() {
+            if day.id < start + n {
+                break;
+            }
+            start += n;
+        }
+        Some(Day::from(start))
+    }
+    /// Follow the rotation schedule: repeat the pairings of the first
+    /// day of the block, or shuffle without repeats on the first day of
+    /// a new block.
+    pub fn scheduled_shuffle(&mut self, day: Day, section: Section) {
+        self.touch_day(day);
+        match self.block_start(day) {
+            Some(start) if start.id < day.id => self.repeat_from(day, start, section),
+            _ => self.shuffle(day, section),
+        }
+    }
+    /// Add `num_days` days with pairings planned in advance for the
+    /// current roster.  Within each section the days follow a
+    /// round-robin, so nobody works with the same partner twice during
+    /// the planned days, and of the many possible round-robins we pick
+    /// the one with the fewest repeats of earlier days.  At most
+    /// `MAX_PLANNED_DAYS` are planned.
+    pub fn plan_days(&mut self, num_days: usize) -> Vec<Day> {
+        let num_days = num_days.min(MAX_PLANNED_DAYS);
+        let first = self.days.len();
+        let days: Vec<Day> = (first..first+num_days).map(Day::from).collect();
+        for day in days.iter() {
+            self.days.push(HashSet::new());
+            self.days_unlocked.insert(day.id);
+            self.planned.insert(day.id);
+            self.emit(Event::DayCreated { day: day.id, name: day.pretty() });
+            self.touch_day(*day);
+        }
+        if num_days == 0 {
+            return days;
+        }
+        let luck = statistics::bad_luck(self, Day::from(first));
+        let mut teams: Vec<Team> = self.teams.iter().cloned().collect();
+        teams.sort();
+        let mut used_teams = vec![0; num_days];
+        for section in self.list_sections() {
+            let mut students = self.students_present_in_section(days[0], section);
+            let mut best: Option<(f64, Vec<Round>)> = None;
+            for _ in 0..PLANNING_ATTEMPTS {
+                students.shuffle(&mut thread_rng());
+                let rounds = round_robin(&students, num_days);
+                let badness: f64 = rounds.iter().flat_map(|r| r.iter()).map(|&(s1, s2)| match s2 {
+                    Some(s2) => {
+                        let repeat = if self.nonrepeat_partners_for_day(days[0], s1, s2) {
+                            0.0
+                        } else {
+                            2.0
+                        };
+                        repeat + 3.0*self.tag_cost(s1, s2)
+                    }
+                    None => 1.0 + luck.get(&s1).cloned().unwrap_or(0) as f64,
+                }).sum();
+                if best.as_ref().map(|b| badness < b.0).unwrap_or(true) {
+                    best = Some((badness, rounds));
+                }
+            }
+            let rounds = best.map(|b| b.1).unwrap_or_default();
+            for (i, round) in rounds.into_iter().enumerate() {
+                for (primary, secondary) in round {
+                    let pairing = match teams.get(used_teams[i]) {
+                        Some(&team) => {
+                            used_teams[i] += 1;
+                            match secondary {
+                                Some(secondary) => Pairing::Pair { primary, secondary, section, team },
+                                None => Pairing::Solo { student: primary, section, team },
+                            }
+                        }
+                        None => {
+                            if let Some(secondary) = secondary {
+                                self.days[first+i].insert(Pairing::Unassigned {
+                                    student: secondary, section });
+                            }
+                            Pairing::Unassigned { student: primary, section }
+                        }
+                    };
+                    self.days[first+i].insert(pairing);
+                }
+            }
+        }
+        for &day in days.iter() {
+            self.assign_roles(day, None);
+        }
+        days.into_iter().map(|d| self.improve_day(d)).collect()
+    }
+    pub fn is_planned(&self, day: Day) -> bool {
+        self.planned.contains(&day.id)
+    }
+    /// Whether `day` was paired automatically, by planning or by the
+    /// rotation schedule, and so is adjusted when attendance changes.
+    fn is_automatic(&self, day: Day) -> bool {
+        self.is_planned(day) || self.block_start(day).is_some()
+    }
+    /// Adjust an automatic day after attendance changes: the pairs that
+    /// are still intact are kept, and everyone left on their own in the
+    /// section is paired up again.
+    fn adjust_automatic_day(&mut self, day: Day, section: Section) {
+        let mut students = Vec::new();
+        let mut possible_teams: Vec<Team> = self.teams.iter().cloned()
+            .filter(|&t| !self.days[day.id].iter().any(|p| p.team() == Some(t))).collect();
+        for p in self.days[day.id].iter().filter(|p| !self.is_pinned(day, p)) {
+            match *p {
+                Pairing::Solo { student, team, section: s } if s == section => {
+                    students.push(student);
+                    possible_teams.push(team);
+                }
+                Pairing::Unassigned { student, section: s } if s == section => {
+                    students.push(student);
+                }
+                _ => (),
+            }
+        }
+        if students.is_empty() {
+            return;
+        }
+        self.days[day.id].retain(|p| p.full_pair() || p.section() != Some(section)
+                                 || !students.iter().any(|&s| p.has(s)));
+        self.shuffle_by_luck(day, &mut students);
+        self.pair_remaining(day, section, students, possible_teams);
+        self.assign_roles(day, Some(section));
     }
     pub fn list_students(&self) -> Vec<Student> {
         let mut list: Vec<_> = self.student_sections.keys().cloned().collect();
@@ -754,9 +1580,11 @@ impl Data {
     pub fn toggle_lock_day(&mut self, day: Day) {
         if self.day_unlocked(day) {
             self.days_unlocked.remove(&day.id);
+            self.planned.remove(&day.id);
         } else {
             self.days_unlocked.insert(day.id);
         }
+        self.emit(Event::DayLocked { day: day.id, locked: !self.day_unlocked(day) });
     }
     pub fn day_unlocked(&self, day: Day) -> bool {
         self.days_unlocked.contains(&day.id)
@@ -788,26 +1616,30 @@ impl Data {
     }
     pub fn assign_student(&mut self, day: Day, student: Student,
                           section: Section, team: Team) {
+        self.touch_day(day);
         if section == Section::from("".to_string()) {
-            println!("Marking {} as absent", student);
+            let old_section = self.days[day.id].iter()
+                .find(|p| p.has(student)).and_then(|p| p.section());
             self.unassign_student(day, student);
             self.days[day.id].insert(Pairing::Absent(student));
+            if let (true, Some(old_section)) = (self.is_automatic(day), old_section) {
+                self.adjust_automatic_day(day, old_section);
+            }
         } else if team == Team::from("".to_string()) {
-            println!("Marking {} as unassigned in section {}", student, section);
+            let was_absent = self.days[day.id].contains(&Pairing::Absent(student));
             self.unassign_student(day, student);
             self.days[day.id].insert(Pairing::Unassigned { student, section });
-            println!("Things: {:?}", self.days[day.id]);
+            if was_absent && self.is_automatic(day) {
+                self.adjust_automatic_day(day, section);
+            }
         } else {
-            println!("Should mark {} as on team {} in section {}", student, team, section);
             self.unassign_student(day, student);
             match self.days[day.id].iter().cloned().filter(|p| p.team() == Some(team)).next() {
                 Some(Pairing::Pair{ .. }) => {
-                    println!("Team is already full.  :(");
+                    // The team is already full.
                 }
                 Some(Pairing::Solo{ student: primary, team, section: oldsec }) => {
-                    if oldsec != section {
-                        println!("Not possible: sections do not match.");
-                    } else {
+                    if oldsec == section {
                         self.unassign_student(day, primary);
                         self.days[day.id].insert(Pairing::Pair {
                             primary: primary,
@@ -815,7 +1647,6 @@ impl Data {
                             team: team,
                             section: section,
                         });
-                        println!("Adding {} to team with {}", student, primary);
                     }
                 }
                 _ => {
@@ -825,6 +1656,7 @@ impl Data {
         }
     }
     pub fn unpair_student(&mut self, day: Day, student: Student) {
+        self.touch_day(day);
         let section = match self.days[day.id].iter().cloned().filter(|p| p.has(student)).next() {
             Some(Pairing::Pair { section, .. }) => section,
             Some(Pairing::Solo { section, .. }) => section,
@@ -836,6 +1668,7 @@ impl Data {
         self.days[day.id].insert(Pairing::Unassigned { student, section });
     }
     pub fn unpair_team(&mut self, day: Day, team: Team) {
+        self.touch_day(day);
         match self.days[day.id].iter().cloned().filter(|p| p.team() == Some(team)).next() {
             Some(Pairing::Pair { primary, secondary, .. }) => {
                 self.unpair_student(day, primary);
@@ -847,15 +1680,105 @@ impl Data {
             _ => (),
         };
     }
+    /// Move `student` on `day`, refusing moves that `assign_student`
+    /// cannot make, such as onto a full team.
+    pub fn move_student(&mut self, day: Day, student: Student,
+                        destination: Destination) -> Result<(), String> {
+        let home = self.student_section(student)
+            .ok_or_else(|| format!("No such student {:?}", student.name.as_str()))?;
+        if day.id >= self.days.len() {
+            return Err(format!("No such day {}", day.id));
+        }
+        let current = self.days[day.id].iter().cloned().find(|p| p.has(student));
+        match destination {
+            Destination::Team(section, team) => {
+                if !self.teams.contains(&team) {
+                    return Err(format!("No such team {:?}", team.name.as_str()));
+                }
+                if !self.sections.contains_key(&section) {
+                    return Err(format!("No such section {:?}", section.name.as_str()));
+                }
+                match self.days[day.id].iter().cloned().find(|p| p.team() == Some(team)) {
+                    Some(p) if p.has(student) => (),
+                    Some(Pairing::Pair { .. }) => {
+                        return Err(format!("{} is already full", team));
+                    }
+                    Some(Pairing::Solo { section: other, .. }) if other != section => {
+                        return Err(format!("{} is in section {}", team, other));
+                    }
+                    _ => self.assign_student(day, student, section, team),
+                }
+            }
+            Destination::NewTeam(section) => {
+                let team = self.unused_teams(day).into_iter().next()
+                    .ok_or_else(|| "Every team is in use".to_string())?;
+                return self.move_student(day, student, Destination::Team(section, team));
+            }
+            Destination::Unassigned => match current.and_then(|p| p.section()) {
+                Some(_) => self.unpair_student(day, student),
+                None => self.assign_student(day, student, home, Team::from("".to_string())),
+            },
+            Destination::Absent => {
+                self.assign_student(day, student, Section::from("".to_string()),
+                                    Team::from("".to_string()));
+            }
+        }
+        Ok(())
+    }
     pub fn new_student(&mut self, s: Student, section: Section) {
+        self.touch_roster();
         self.student_sections.insert(s, section);
     }
     pub fn delete_student(&mut self, s: Student) {
+        self.touch_roster();
+        self.inactive.remove(&s);
         self.student_sections.remove(&s);
+        self.attributes.remove(&s);
+        self.tags.remove(&s);
+        self.emails.remove(&s);
+        for preferred in self.preferred.values_mut() {
+            preferred.remove(&s);
+        }
+        for pins in self.pins.values_mut() {
+            pins.students.remove(&s);
+        }
     }
-    pub fn rename_student(&mut self, old_s: Student, new_s: Student, section: Section) {
+    /// Rename a student, or move them to another section if `new_s` is
+    /// `old_s`.  Fails if there is already a student named `new_s`.
+    pub fn rename_student(&mut self, old_s: Student, new_s: Student,
+                          section: Section) -> Result<(), String> {
         use database::Pairing::*;
+        if old_s != new_s && self.student_sections.contains_key(&new_s) {
+            return Err(format!("Student {:?} already exists", new_s.name.as_str()));
+        }
+        self.touch_roster();
+        if old_s != new_s {
+            self.changes.renamed.push((old_s, new_s));
+        }
+        self.student_sections.remove(&old_s);
         self.student_sections.insert(new_s, section);
+        if let Some(attributes) = self.attributes.remove(&old_s) {
+            self.attributes.insert(new_s, attributes);
+        }
+        if let Some(tags) = self.tags.remove(&old_s) {
+            self.tags.insert(new_s, tags);
+        }
+        if let Some(email) = self.emails.remove(&old_s) {
+            self.emails.insert(new_s, email);
+        }
+        if self.inactive.remove(&old_s) {
+            self.inactive.insert(new_s);
+        }
+        for preferred in self.preferred.values_mut() {
+            if preferred.remove(&old_s) {
+                preferred.insert(new_s);
+            }
+        }
+        for pins in self.pins.values_mut() {
+            if pins.students.remove(&old_s) {
+                pins.students.insert(new_s);
+            }
+        }
         for d in self.days.iter_mut() {
             let problems: Vec<_> = d.iter().cloned().filter(|&p| p.has(old_s)).collect();
             for mut p in problems {
@@ -881,6 +1804,165 @@ impl Data {
                 d.insert(p);
             }
         }
+        Ok(())
+    }
+
+    pub fn attribute(&self, s: Student, name: &str) -> Option<&str> {
+        self.attributes.get(&s).and_then(|a| a.get(name)).map(|v| v.as_str())
+    }
+    pub fn student_attributes(&self, s: Student) -> Vec<(String, String)> {
+        self.attributes.get(&s)
+            .map(|a| a.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+            .unwrap_or_default()
+    }
+    /// Set an attribute of a student, or remove it if `value` is empty.
+    pub fn set_attribute(&mut self, s: Student, name: &str, value: &str) {
+        if value.is_empty() {
+            if let Some(a) = self.attributes.get_mut(&s) {
+                a.remove(name);
+                if a.is_empty() {
+                    self.attributes.remove(&s);
+                }
+            }
+        } else {
+            self.attributes.entry(s).or_default().insert(name.to_string(), value.to_string());
+        }
+    }
+    /// The names of all attributes that any student has.
+    pub fn attribute_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.attributes.values()
+            .flat_map(|a| a.keys().cloned()).collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+    pub fn student_tags(&self, s: Student) -> Vec<String> {
+        self.tags.get(&s).map(|t| t.iter().cloned().collect()).unwrap_or_default()
+    }
+    pub fn set_tags<I: IntoIterator<Item = String>>(&mut self, s: Student, tags: I) {
+        let tags: BTreeSet<String> = tags.into_iter()
+            .map(|t| t.trim().to_string()).filter(|t| !t.is_empty()).collect();
+        if tags.is_empty() {
+            self.tags.remove(&s);
+        } else {
+            self.tags.insert(s, tags);
+        }
+    }
+    pub fn is_active(&self, s: Student) -> bool {
+        !self.inactive.contains(&s)
+    }
+    /// Mark a student as still taking the course, or as having dropped
+    /// it, in which case they keep their history but get no new
+    /// pairings.
+    pub fn set_active(&mut self, s: Student, active: bool) {
+        self.touch_roster();
+        if active {
+            self.inactive.remove(&s);
+        } else {
+            self.inactive.insert(s);
+        }
+    }
+    pub fn roster_source(&self) -> Option<&RosterSource> {
+        self.roster_source.as_ref()
+    }
+    pub fn set_roster_source(&mut self, source: Option<RosterSource>) {
+        self.roster_source = source;
+    }
+    pub fn roster_log(&self) -> &[RosterSync] {
+        &self.roster_log
+    }
+    pub fn log_roster_sync(&mut self, sync: RosterSync) {
+        self.roster_log.push(sync);
+    }
+    pub fn student_email(&self, s: Student) -> Option<&str> {
+        self.emails.get(&s).map(|e| e.as_str())
+    }
+    /// Set the email address of a student, or remove it if `email` is
+    /// empty.
+    pub fn set_email(&mut self, s: Student, email: &str) -> Result<(), String> {
+        let email = email.trim();
+        if email.is_empty() {
+            self.emails.remove(&s);
+        } else {
+            notify::check_email(email)?;
+            self.emails.insert(s, email.to_string());
+        }
+        Ok(())
+    }
+    /// The messages sent when publishing `day`, oldest first.
+    pub fn deliveries(&self, day: Day) -> &[Delivery] {
+        self.deliveries.get(&day.id).map(|d| d.as_slice()).unwrap_or(&[])
+    }
+    pub fn log_deliveries(&mut self, day: Day, deliveries: Vec<Delivery>) {
+        self.deliveries.entry(day.id).or_default().extend(deliveries);
+    }
+    pub fn webhooks(&self) -> &[String] {
+        &self.webhooks
+    }
+    /// Set the webhook endpoints, given one per line.
+    pub fn set_webhooks(&mut self, lines: &str) -> Result<(), String> {
+        let mut endpoints: Vec<String> = Vec::new();
+        for url in lines.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            if !links::is_web_link(url) {
+                return Err(format!("{:?} does not start with http:// or https://", url));
+            }
+            if !endpoints.iter().any(|e| e == url) {
+                endpoints.push(url.to_string());
+            }
+        }
+        self.webhooks = endpoints;
+        self.changes.endpoints = true;
+        Ok(())
+    }
+    /// The events for what has changed since the course was read or
+    /// last saved, see `webhooks`.
+    pub fn take_events(&mut self) -> Vec<Event> {
+        let roster = self.active_roster();
+        self.changes.take(&self.days, &roster)
+    }
+    /// The section of each student who is still taking the course.
+    fn active_roster(&self) -> HashMap<Student, Section> {
+        self.student_sections.iter().filter(|(&s, _)| self.is_active(s))
+            .map(|(&s, &section)| (s, section)).collect()
+    }
+    fn emit(&mut self, event: Event) {
+        self.changes.events.push(event);
+    }
+    /// Note the pairings of `day` before it is changed.
+    fn touch_day(&mut self, day: Day) {
+        if let Some(pairings) = self.days.get(day.id) {
+            self.changes.days.entry(day.id).or_insert_with(|| pairings.clone());
+        }
+    }
+    fn touch_all_days(&mut self) {
+        for id in 0..self.days.len() {
+            self.touch_day(Day::from(id));
+        }
+    }
+    /// Note the roster before it is changed.
+    fn touch_roster(&mut self) {
+        if self.changes.roster.is_none() {
+            self.changes.roster = Some(self.active_roster());
+        }
+    }
+    pub fn tag_objective(&self, section: Section) -> TagObjective {
+        self.tag_objectives.get(&section).cloned().unwrap_or_default()
+    }
+    pub fn set_tag_objective(&mut self, section: Section, objective: TagObjective) {
+        if objective == TagObjective::Ignore {
+            self.tag_objectives.remove(&section);
+        } else {
+            self.tag_objectives.insert(section, objective);
+        }
+    }
+    pub fn balance_attribute(&self) -> Option<&str> {
+        self.balance_attribute.as_deref()
+    }
+    pub fn set_balance_attribute(&mut self, attribute: Option<String>) {
+        self.balance_attribute = attribute.filter(|a| !a.is_empty());
+    }
+    pub fn student_section(&self, s: Student) -> Option<Section> {
+        self.student_sections.get(&s).cloned()
     }
 
     pub fn list_sections(&self) -> Vec<Section> {
@@ -897,15 +1979,34 @@ impl Data {
         self.sections.insert(s, zoom);
     }
     pub fn delete_section(&mut self, s: Section) {
+        self.touch_all_days();
         self.sections.remove(&s);
+        self.tag_objectives.remove(&s);
+        self.capacities.remove(&s);
+        self.preferred.remove(&s);
         for d in self.days.iter_mut() {
             d.retain(|p| p.section() != Some(s));
         }
     }
-    pub fn rename_section(&mut self, old_s: Section, new_s: Section, zoom: Zoom) {
+    /// Rename a section and set its zoom.  Fails if there is already a
+    /// section named `new_s`.
+    pub fn rename_section(&mut self, old_s: Section, new_s: Section,
+                          zoom: Zoom) -> Result<(), String> {
         use database::Pairing::*;
+        if old_s != new_s && self.sections.contains_key(&new_s) {
+            return Err(format!("Section {:?} already exists", new_s.name.as_str()));
+        }
         self.sections.remove(&old_s);
         self.sections.insert(new_s, zoom);
+        if let Some(objective) = self.tag_objectives.remove(&old_s) {
+            self.tag_objectives.insert(new_s, objective);
+        }
+        if let Some(capacity) = self.capacities.remove(&old_s) {
+            self.capacities.insert(new_s, capacity);
+        }
+        if let Some(preferred) = self.preferred.remove(&old_s) {
+            self.preferred.insert(new_s, preferred);
+        }
         for d in self.days.iter_mut() {
             let problems: Vec<_> = d.iter().cloned().filter(|&p| p.section() == Some(old_s)).collect();
             for mut p in problems {
@@ -925,29 +2026,106 @@ impl Data {
                 d.insert(p);
             }
         }
+        Ok(())
     }
 
+    pub fn capacity(&self, s: Section) -> Option<usize> {
+        self.capacities.get(&s).cloned()
+    }
+    pub fn set_capacity(&mut self, s: Section, capacity: Option<usize>) {
+        match capacity {
+            Some(c) => self.capacities.insert(s, c),
+            None => self.capacities.remove(&s),
+        };
+    }
+    pub fn preferred_students(&self, s: Section) -> Vec<Student> {
+        self.preferred.get(&s).map(|p| p.iter().cloned().collect()).unwrap_or_default()
+    }
+    pub fn set_preferred_students<I: IntoIterator<Item = Student>>(&mut self, s: Section,
+                                                                  students: I) {
+        let students: BTreeSet<Student> = students.into_iter().collect();
+        if students.is_empty() {
+            self.preferred.remove(&s);
+        } else {
+            self.preferred.insert(s, students);
+        }
+    }
+    pub fn section_zoom(&self, s: Section) -> Option<Zoom> {
+        self.sections.get(&s).cloned()
+    }
     pub fn get_zooms(&self) -> HashMap<Section, Zoom> {
         self.sections.clone()
     }
+    pub fn links(&self) -> &Links {
+        &self.links
+    }
+    pub fn set_links(&mut self, links: Links) {
+        self.links = links;
+    }
+    /// The link to the meeting of `section`, if it has one.
+    pub fn meeting_link(&self, section: Section) -> String {
+        self.sections.get(&section).map(|&z| self.links.meeting(section, z))
+            .unwrap_or_default()
+    }
+    /// The links for `team` when it is in `section`: those made from
+    /// the link templates, followed by its provisioned resources.
+    pub fn team_links(&self, section: Section, team: Team) -> Vec<(String, String)> {
+        let mut all = self.links.team(section, team);
+        all.extend(self.team_resources(team).into_iter()
+                   .filter(|(_, url)| links::is_web_link(url)));
+        all
+    }
+    pub fn team_resources(&self, team: Team) -> BTreeMap<String, String> {
+        self.resources.get(&team).cloned().unwrap_or_default()
+    }
+    pub fn set_team_resources(&mut self, team: Team, resources: BTreeMap<String, String>) {
+        if resources.is_empty() {
+            self.resources.remove(&team);
+        } else {
+            self.resources.insert(team, resources);
+        }
+    }
     pub fn list_teams(&self) -> Vec<Team> {
         let mut list: Vec<_> = self.teams.iter().cloned().collect();
         list.sort();
         list
     }
+    /// The teams that nobody is on for `day`.
+    pub fn unused_teams(&self, day: Day) -> Vec<Team> {
+        self.list_teams().into_iter()
+            .filter(|&t| !self.days[day.id].iter().any(|p| p.team() == Some(t)))
+            .collect()
+    }
     pub fn new_team(&mut self, s: Team) {
         self.teams.insert(s);
     }
     pub fn delete_team(&mut self, s: Team) {
+        self.touch_all_days();
         self.teams.remove(&s);
         for d in self.days.iter_mut() {
             d.retain(|p| p.team() != Some(s));
         }
+        for pins in self.pins.values_mut() {
+            pins.teams.remove(&s);
+        }
+        self.resources.remove(&s);
     }
-    pub fn rename_team(&mut self, old_s: Team, new_s: Team) {
+    /// Rename a team.  Fails if there is already a team named `new_s`.
+    pub fn rename_team(&mut self, old_s: Team, new_s: Team) -> Result<(), String> {
         use database::Pairing::*;
+        if old_s != new_s && self.teams.contains(&new_s) {
+            return Err(format!("Team {:?} already exists", new_s.name.as_str()));
+        }
         self.teams.insert(new_s);
         self.teams.remove(&old_s);
+        if let Some(resources) = self.resources.remove(&old_s) {
+            self.resources.insert(new_s, resources);
+        }
+        for pins in self.pins.values_mut() {
+            if pins.teams.remove(&old_s) {
+                pins.teams.insert(new_s);
+            }
+        }
         for d in self.days.iter_mut() {
             let problems: Vec<_> = d.iter().cloned().filter(|&p| p.team() == Some(old_s)).collect();
             for mut p in problems {
@@ -964,6 +2142,68 @@ impl Data {
                 d.insert(p);
             }
         }
+        Ok(())
+    }
+
+    /// Look for inconsistencies in the course, returning a description
+    /// of each problem found.
+    pub fn check(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut students = self.list_students();
+        students.retain(|s| !self.sections.contains_key(&self.student_sections[s]));
+        for s in students {
+            problems.push(format!("{} is in nonexistent section {}",
+                                  s.name, self.student_sections[&s].name));
+        }
+        for (id, pairings) in self.days.iter().enumerate() {
+            let day = self.improve_day(Day::from(id));
+            let mut seen_students = HashSet::new();
+            let mut seen_teams = HashSet::new();
+            let mut pairings: Vec<_> = pairings.iter().cloned().collect();
+            pairings.sort();
+            for p in pairings {
+                for s in p.allocated_students() {
+                    if !self.student_sections.contains_key(&s) {
+                        problems.push(format!("{}: unknown student {}", day.pretty(), s.name));
+                    }
+                    if !seen_students.insert(s) {
+                        problems.push(format!("{}: {} appears more than once",
+                                              day.pretty(), s.name));
+                    }
+                }
+                if let Some(section) = p.section() {
+                    if !self.sections.contains_key(&section) {
+                        problems.push(format!("{}: unknown section {}",
+                                              day.pretty(), section.name));
+                    }
+                }
+                if let Some(team) = p.team() {
+                    if !self.teams.contains(&team) {
+                        problems.push(format!("{}: unknown team {}", day.pretty(), team.name));
+                    }
+                    if !seen_teams.insert(team) {
+                        problems.push(format!("{}: team {} is used more than once",
+                                              day.pretty(), team.name));
+                    }
+                }
+                if let Pairing::Pair { primary, secondary, team, .. } = p {
+                    if primary == secondary {
+                        problems.push(format!("{}: {} is paired with self in {}",
+                                              day.pretty(), primary.name, team.name));
+                    }
+                }
+            }
+        }
+        let mut ids: Vec<usize> = self.daynames.keys().cloned()
+            .chain(self.days_unlocked.iter().cloned())
+            .filter(|&id| id >= self.days.len())
+            .collect();
+        ids.sort();
+        ids.dedup();
+        for id in ids {
+            problems.push(format!("Day {} is named or unlocked but does not exist", id));
+        }
+        problems
     }
 }
 
@@ -1017,6 +2257,42 @@ impl StudentOptions {
     }
 }
 
+/// One day of a round-robin: each student with their partner, if any.
+type Round = Vec<(Student, Option<Student>)>;
+
+/// How many random orderings of a section to try when planning days.
+const PLANNING_ATTEMPTS: usize = 50;
+/// The most days `plan_days` will plan at once, about a term of class
+/// days.
+pub const MAX_PLANNED_DAYS: usize = 30;
+
+/// The first `num_rounds` rounds of a round-robin among `students`,
+/// using the circle method.  Each round pairs everyone up, with one
+/// student left out (paired with `None`) if there is an odd number.
+/// Nobody meets the same partner twice until every pair has met.
+fn round_robin(students: &[Student], num_rounds: usize) -> Vec<Round> {
+    let mut circle: Vec<Option<Student>> = students.iter().cloned().map(Some).collect();
+    if circle.len() % 2 == 1 {
+        circle.push(None);
+    }
+    let n = circle.len();
+    let mut rounds = Vec::new();
+    for _ in 0..num_rounds {
+        let mut round = Vec::new();
+        for i in 0..n/2 {
+            match (circle[i], circle[n-1-i]) {
+                (Some(a), b) | (b, Some(a)) => round.push((a, b)),
+                (None, None) => (),
+            }
+        }
+        rounds.push(round);
+        if n > 2 {
+            circle[1..].rotate_right(1);
+        }
+    }
+    rounds
+}
+
 fn remove_student_from_vec(s: Student, options: &mut Vec<Student>) -> Option<Student> {
     if !options.contains(&s) {
         return None;
@@ -1077,78 +2353,398 @@ pub struct TeamOptions {
 //     }
 // }
 
-fn split_evenly<T>(slice: &[T], n: usize) -> impl Iterator<Item = &[T]> {
-    struct Iter<'a, I> {
-        pub slice: &'a [I],
-        pub n: usize,
-        pub am_odd: bool,
-    }
-    impl<'a, I> Iterator for Iter<'a, I> {
-        type Item = &'a [I];
-        fn next(&mut self) -> Option<&'a [I]> {
-            if self.slice.len() == 0 {
-                return None;
-            }
-            let leftovers = self.slice.len() % self.n;
-            let extra = if leftovers == 0 {
-                0
-            } else if rand::random::<usize>() % self.n < leftovers {
-                1
-            } else {
-                0
-            };
-            let (first, rest) = self.slice.split_at(self.slice.len() / self.n + extra);
-            self.slice = rest;
-            self.n -= 1;
-            Some(first)
-        }
+
+#[test]
+fn test_shuffle_mode_names() {
+    for mode in ShuffleMode::all() {
+        assert_eq!(ShuffleMode::from_str(mode.name()), Ok(mode));
+        assert_eq!(ShuffleMode::from_str(mode.label()), Ok(mode));
     }
-    Iter { slice, n, am_odd: slice.len() & 1 == 1 }
+    assert!(ShuffleMode::from_str("Reshuffle").is_err());
 }
 
 #[test]
-fn test_split_evenly() {
-    let eight: [usize; 8] = [1,2,3,4,5,6,7,8];
-    let chunks: Vec<_> = split_evenly(&eight, 3).collect();
-    assert_eq!(&eight[..], &chunks.iter().flat_map(|x| *x).cloned().collect::<Vec<usize>>()[..]);
-    assert_eq!(chunks.iter().map(|c| c.len()).max(), Some(3));
-    assert_eq!(chunks.iter().map(|c| c.len()).min(), Some(2));
+fn test_check() {
+    let mut data = Data::empty("testcourse", "testcourse.yaml");
+    let section = Section::from("A".to_string());
+    data.new_section(section, Zoom::from("".to_string()));
+    for name in &["ann", "bob", "cat"] {
+        data.new_student(Student::from(name.to_string()), section);
+    }
+    for name in &["red", "blue"] {
+        data.new_team(Team::from(name.to_string()));
+    }
+    data.add_day();
+    data.run_shuffle(Day::from(0), ShuffleMode::Shuffle, None);
+    assert_eq!(data.check(), Vec::<String>::new());
 
-    let chunks: Vec<_> = split_evenly(&eight, 5).collect();
-    assert_eq!(&eight[..], &chunks.iter().flat_map(|x| *x).cloned().collect::<Vec<usize>>()[..]);
-    assert_eq!(chunks.iter().map(|c| c.len()).max(), Some(2));
-    assert_eq!(chunks.iter().map(|c| c.len()).min(), Some(1));
+    data.delete_team(Team::from("red".to_string()));
+    data.name_day(3, "Someday".to_string());
+    data.delete_section(section);
+    let problems = data.check();
+    assert_eq!(problems.len(), 4, "{:?}", problems);
+    assert!(problems.iter().any(|p| p.contains("Day 3")));
+}
+
+#[test]
+fn test_clone_structure() {
+    let mut data = Data::empty("testcourse", "testcourse.yaml");
+    let section = Section::from("A".to_string());
+    data.new_section(section, Zoom::from("123 456".to_string()));
+    let s: Vec<_> = ["ann", "bob"].iter().map(|n| Student::from(n.to_string())).collect();
+    for &st in s.iter() {
+        data.new_student(st, section);
+    }
+    data.set_email(s[0], "ann@example.com").unwrap();
+    data.set_email(s[1], "bob@example.com").unwrap();
+    data.new_team(Team::from("red".to_string()));
+    data.set_attribute(s[0], "year", "2");
+    data.set_attribute(s[1], "year", "3");
+    data.set_tags(s[0], vec!["lefty".to_string()]);
+    data.set_active(s[1], false);
+    data.add_day();
+    data.name_day(0, "Monday".to_string());
+    let mut links = Links::default();
+    links.set_meeting("https://zoom.example.com/j/{zoom}").unwrap();
+    data.set_links(links);
 
-    let seven = [1,2,3,4,5,6,7];
-    let chunks: Vec<_> = split_evenly(&seven, 3).collect();
-    assert_eq!(&seven[..], &chunks.iter().flat_map(|x| *x).cloned().collect::<Vec<usize>>()[..]);
-    assert_eq!(chunks.iter().map(|c| c.len()).max(), Some(3));
-    assert_eq!(chunks.iter().map(|c| c.len()).min(), Some(2));
+    let info = CourseInfo::new("Physics", "Fall", "me");
+    let copy = data.clone_structure("/courses", "newcourse", info.clone(), false);
+    assert_eq!(copy.path(), "newcourse");
+    assert_eq!(copy.info(), &info);
+    assert_eq!(copy.zoom_sections(), data.zoom_sections());
+    assert_eq!(copy.list_teams(), data.list_teams());
+    assert_eq!(copy.meeting_link(section), "https://zoom.example.com/j/123456");
+    assert!(copy.list_students().is_empty());
+    assert!(copy.attribute_names().is_empty());
+    assert!(copy.student_tags(s[0]).is_empty());
+    assert!(copy.list_days().is_empty());
+    assert_eq!(copy.check(), Vec::<String>::new());
 
-    let chunks: Vec<_> = split_evenly(&seven, 5).collect();
-    assert_eq!(&seven[..], &chunks.iter().flat_map(|x| *x).cloned().collect::<Vec<usize>>()[..]);
-    assert_eq!(chunks.iter().map(|c| c.len()).max(), Some(2));
-    assert_eq!(chunks.iter().map(|c| c.len()).min(), Some(1));
+    let copy = data.clone_structure("/courses", "newcourse", info, true);
+    assert_eq!(copy.list_students(), vec![s[0]]);
+    assert_eq!(copy.student_email(s[0]), Some("ann@example.com"));
+    assert_eq!(copy.student_email(s[1]), None);
+    assert_eq!(copy.student_attributes(s[0]), vec![("year".to_string(), "2".to_string())]);
+    assert!(copy.student_attributes(s[1]).is_empty());
+    assert_eq!(copy.student_tags(s[0]), vec!["lefty".to_string()]);
+    assert!(copy.is_active(s[1]));
+    assert!(copy.list_days().is_empty());
+    assert_eq!(copy.check(), Vec::<String>::new());
+}
 
+#[test]
+fn test_partner_history() {
+    let mut data = Data::empty("testcourse", "testcourse.yaml");
+    let section = Section::from("A".to_string());
+    data.new_section(section, Zoom::from("".to_string()));
+    let ann = Student::from("ann".to_string());
+    let bob = Student::from("bob".to_string());
+    let red = Team::from("red".to_string());
+    data.new_student(ann, section);
+    data.new_student(bob, section);
+    data.new_team(red);
+    for id in 0..3 {
+        data.add_day();
+        if id != 1 {
+            data.assign_student(Day::from(id), ann, section, red);
+            data.assign_student(Day::from(id), bob, section, red);
+        }
+    }
+    assert!(data.partner_history(Day::from(0), ann, bob).is_empty());
+    let history: Vec<_> = data.partner_history(Day::from(3), bob, ann).into_iter()
+        .map(|(d, t)| (d.id, t)).collect();
+    assert_eq!(history, vec![(0, red), (2, red)]);
+    assert!(!data.nonrepeat_partners_for_day(Day::from(1), ann, bob));
+}
 
-    let nineteen: [usize; 19] = [1,2,3,4,5,6,7,8,9,10,11,12,13,14,15,16,17,18,19];
+#[test]
+fn test_rotation() {
+    let mut data = Data::empty("testcourse", "testcourse.yaml");
+    let section = Section::from("A".to_string());
+    data.new_section(section, Zoom::from("".to_string()));
+    let s: Vec<_> = ["ann", "bob", "cat", "dan"].iter()
+        .map(|n| Student::from(n.to_string())).collect();
+    for &st in s.iter() {
+        data.new_student(st, section);
+    }
+    data.new_team(Team::from("red".to_string()));
+    data.new_team(Team::from("blue".to_string()));
+    data.set_rotation(vec![2, 0, 1]);
+    assert_eq!(data.rotation(), &[2, 1]);
+    let starts: Vec<_> = (0..7).map(|i| data.block_start(Day::from(i)).unwrap().id).collect();
+    assert_eq!(starts, vec![0, 0, 2, 3, 3, 5, 6]);
 
-    let chunks: Vec<_> = split_evenly(&nineteen, 5).collect();
-    assert_eq!(&nineteen[..], &chunks.iter().flat_map(|x| *x).cloned().collect::<Vec<usize>>()[..]);
-    assert_eq!(chunks.iter().map(|c| c.len()).max(), Some(4));
-    assert_eq!(chunks.iter().map(|c| c.len()).min(), Some(3));
+    for _ in 0..3 {
+        data.add_day();
+    }
+    let pairs = |data: &Data, id: usize| {
+        let mut pairs: Vec<_> = data.pairings()[id].iter().filter(|p| p.full_pair())
+            .map(|p| { let mut v = p.present_students(); v.sort(); v }).collect();
+        pairs.sort();
+        pairs
+    };
+    assert_eq!(pairs(&data, 0).len(), 2);
+    assert_eq!(pairs(&data, 0), pairs(&data, 1));
+    assert_eq!(pairs(&data, 2).len(), 2);
+    for p in pairs(&data, 2) {
+        assert!(!pairs(&data, 0).contains(&p));
+    }
 
-    let mut last_was_small = false;
-    for _ in 0..60 {
-        let chunks: Vec<_> = split_evenly(&nineteen, 5).collect();
-        assert_eq!(&nineteen[..], &chunks.iter().flat_map(|x| *x).cloned().collect::<Vec<usize>>()[..]);
-        assert_eq!(chunks.iter().map(|c| c.len()).max(), Some(4));
-        assert_eq!(chunks.iter().map(|c| c.len()).min(), Some(3));
+    // Someone is absent mid-block, so their partner is left on their own.
+    data.set_rotation(vec![3]);
+    data.assign_student(Day::from(1), s[0], Section::from("".to_string()),
+                        Team::from("".to_string()));
+    data.run_shuffle(Day::from(1), ShuffleMode::Scheduled, None);
+    assert_eq!(pairs(&data, 1).len(), 1);
+    assert!(pairs(&data, 0).contains(&pairs(&data, 1)[0]));
+    // When someone in the other pair is absent too, the two left on
+    // their own are paired up without another shuffle.
+    let absent = pairs(&data, 1)[0][0];
+    data.assign_student(Day::from(1), absent, Section::from("".to_string()),
+                        Team::from("".to_string()));
+    let mut present: Vec<_> = s[1..].iter().cloned().filter(|&st| st != absent).collect();
+    present.sort();
+    assert_eq!(pairs(&data, 1), vec![present]);
+    assert!(data.check().is_empty());
+}
 
-        println!("  {:?}", chunks.iter().map(|c| c.len()).collect::<Vec<_>>());
-        if chunks[4].len() == 3 {
-            last_was_small = true;
+#[test]
+fn test_plan_days() {
+    let mut data = Data::empty("testcourse", "testcourse.yaml");
+    let section = Section::from("A".to_string());
+    data.new_section(section, Zoom::from("".to_string()));
+    let s: Vec<_> = ["ann", "bob", "cat", "dan", "eve", "fay"].iter()
+        .map(|n| Student::from(n.to_string())).collect();
+    for &st in s.iter() {
+        data.new_student(st, section);
+    }
+    for t in ["red", "blue", "green"].iter() {
+        data.new_team(Team::from(t.to_string()));
+    }
+    data.add_day();
+    data.run_shuffle(Day::from(0), ShuffleMode::Shuffle, None);
+    let days = data.plan_days(4);
+    assert_eq!(days.len(), 4);
+    assert_eq!(data.clone().plan_days(1000).len(), MAX_PLANNED_DAYS);
+    assert!(data.check().is_empty());
+    let mut pairs = HashSet::new();
+    for day in days.iter() {
+        assert!(data.is_planned(*day) && data.day_unlocked(*day) && day.unlocked);
+        let day_pairs: Vec<_> = data.pairings()[day.id].iter()
+            .filter(|p| p.full_pair()).collect();
+        assert_eq!(day_pairs.len(), 3);
+        for p in day_pairs {
+            let mut v = p.present_students();
+            v.sort();
+            assert!(pairs.insert(v), "repeated pair on {}", day.id);
         }
     }
-    assert!(last_was_small);
+
+    // When two students from different teams are absent, their
+    // partners end up working together.
+    let day = days[1];
+    let partner = |data: &Data, st: Student| data.pairings()[day.id].iter()
+        .find(|p| p.full_pair() && p.present_students().contains(&st))
+        .map(|p| p.present_students().into_iter().find(|&x| x != st).unwrap());
+    let a = s[0];
+    let b = data.pairings()[day.id].iter()
+        .find(|p| p.full_pair() && !p.present_students().contains(&a)).unwrap().present_students()[0];
+    let (pa, pb) = (partner(&data, a).unwrap(), partner(&data, b).unwrap());
+    let nobody = Section::from("".to_string());
+    let noteam = Team::from("".to_string());
+    data.assign_student(day, a, nobody, noteam);
+    data.assign_student(day, b, nobody, noteam);
+    assert_eq!(partner(&data, pa), Some(pb));
+    assert!(data.check().is_empty());
+
+    // When one of them comes back, they get a team again.
+    data.assign_student(day, a, section, noteam);
+    assert_eq!(data.unassigned_students(day), vec![b]);
+
+    data.toggle_lock_day(day);
+    assert!(!data.is_planned(day));
+}
+
+#[test]
+fn test_tag_objectives() {
+    let mut data = Data::empty("testcourse", "testcourse.yaml");
+    let section = Section::from("A".to_string());
+    data.new_section(section, Zoom::from("".to_string()));
+    let s: Vec<_> = ["ann", "bob", "cat", "dan"].iter()
+        .map(|n| Student::from(n.to_string())).collect();
+    for &st in s.iter() {
+        data.new_student(st, section);
+    }
+    data.new_team(Team::from("red".to_string()));
+    data.new_team(Team::from("blue".to_string()));
+    data.set_tags(s[0], vec!["physics".to_string()]);
+    data.set_tags(s[1], vec!["physics".to_string(), " ".to_string()]);
+    data.set_tags(s[2], vec!["math".to_string()]);
+    data.set_tags(s[3], vec!["math".to_string()]);
+    assert_eq!(data.student_tags(s[1]), vec!["physics".to_string()]);
+    assert_eq!("avoid-same".parse(), Ok(TagObjective::AvoidSame));
+
+    let mixed = |data: &Data, day: usize| data.pairings()[day].iter()
+        .filter(|p| p.full_pair())
+        .all(|p| { let v = p.present_students(); data.tag_cost(v[0], v[1]) == 0.0 });
+    // Every shuffle mode mixes the tags, and with AvoidSame the mixing
+    // wins out over avoiding repeats.
+    data.set_tag_objective(section, TagObjective::AvoidSame);
+    for (id, &mode) in [ShuffleMode::Shuffle, ShuffleMode::Continuity, ShuffleMode::Grand,
+                        ShuffleMode::GrandContinuity, ShuffleMode::Balance,
+                        ShuffleMode::Shuffle, ShuffleMode::Shuffle].iter().enumerate() {
+        data.add_day();
+        data.run_shuffle(Day::from(id), mode, None);
+        assert!(mixed(&data, id), "{} on day {}", mode, id);
+    }
+    let planned = data.plan_days(1);
+    assert!(mixed(&data, planned[0].id));
+
+    // With Mix, avoiding a repeat comes first.
+    data.set_tag_objective(section, TagObjective::Mix);
+    data.add_day();
+    let day = data.list_days().len() - 1;
+    data.run_shuffle(Day::from(day), ShuffleMode::Shuffle, None);
+    assert!(!mixed(&data, day));
+
+    data.rename_section(section, Section::from("B".to_string()), Zoom::from("".to_string()))
+        .unwrap();
+    assert_eq!(data.tag_objective(Section::from("B".to_string())), TagObjective::Mix);
+}
+
+#[test]
+fn test_section_capacity() {
+    let mut data = Data::empty("testcourse", "testcourse.yaml");
+    let (a, b) = (Section::from("A".to_string()), Section::from("B".to_string()));
+    data.new_section(a, Zoom::from("".to_string()));
+    data.new_section(b, Zoom::from("".to_string()));
+    let s: Vec<_> = ["ann", "bob", "cat", "dan", "eve", "fay"].iter()
+        .map(|n| Student::from(n.to_string())).collect();
+    for (i, &st) in s.iter().enumerate() {
+        data.new_student(st, if i < 4 { a } else { b });
+    }
+    for t in ["red", "blue", "green"].iter() {
+        data.new_team(Team::from(t.to_string()));
+    }
+    let count = |data: &Data, day: usize, section: Section| data.pairings()[day].iter()
+        .filter(|p| p.section() == Some(section))
+        .map(|p| p.present_students().len()).sum::<usize>();
+
+    // Without capacities, a team is only moved if its members come
+    // from different sections.
+    data.add_day();
+    let problems = data.run_shuffle(Day::from(0), ShuffleMode::Grand, None);
+    assert!(data.check().is_empty());
+    let away = data.pairings()[0].iter()
+        .flat_map(|p| p.section().map(|sec| (sec, p.present_students())))
+        .filter(|(sec, st)| st.iter().all(|&x| data.student_section(x) != Some(*sec)))
+        .count();
+    assert_eq!(away, 0);
+    assert!(problems.iter().all(|p| p.contains("home")), "{:?}", problems);
+
+    // Section A has room for only one team, and fay prefers it.
+    data.set_capacity(a, Some(2));
+    data.set_preferred_students(a, vec![s[5]]);
+    data.add_day();
+    let problems = data.run_shuffle(Day::from(1), ShuffleMode::Grand, None);
+    assert_eq!(count(&data, 1, a), 2);
+    assert_eq!(count(&data, 1, b), 4);
+    assert!(data.pairings()[1].iter().any(|p| p.section() == Some(a)
+                                          && p.present_students().contains(&s[5])));
+    assert!(!problems.iter().any(|p| p.contains("capacity")));
+
+    data.set_capacity(b, Some(2));
+    data.add_day();
+    let problems = data.run_shuffle(Day::from(2), ShuffleMode::GrandContinuity, None);
+    assert_eq!(count(&data, 2, a) + count(&data, 2, b), 6);
+    assert!(problems.iter().any(|p| p.contains("over capacity")), "{:?}", problems);
+
+    data.rename_student(s[5], Student::from("fran".to_string()), b).unwrap();
+    data.rename_section(a, Section::from("C".to_string()), Zoom::from("".to_string())).unwrap();
+    let c = Section::from("C".to_string());
+    assert_eq!(data.capacity(c), Some(2));
+    assert_eq!(data.preferred_students(c), vec![Student::from("fran".to_string())]);
+}
+
+#[test]
+fn test_pins() {
+    let mut data = Data::empty("testcourse", "testcourse.yaml");
+    let section = Section::from("A".to_string());
+    data.new_section(section, Zoom::from("".to_string()));
+    let s: Vec<_> = ["ann", "bob", "cat", "dan", "eve", "fay", "gus", "hal"].iter()
+        .map(|n| Student::from(n.to_string())).collect();
+    for &st in s.iter() {
+        data.new_student(st, section);
+    }
+    let t: Vec<_> = ["red", "blue", "green", "gold", "pink"].iter()
+        .map(|n| Team::from(n.to_string())).collect();
+    for &team in t.iter() {
+        data.new_team(team);
+    }
+    data.add_day();
+    data.add_day();
+    let day = Day::from(1);
+    data.run_shuffle(Day::from(0), ShuffleMode::Shuffle, None);
+    data.run_shuffle(day, ShuffleMode::Shuffle, None);
+    let team_of = |data: &Data, st: Student| data.pairings()[1].iter()
+        .find(|p| p.has(st)).and_then(|p| p.team());
+    let pinned_team = team_of(&data, s[0]).unwrap();
+    data.toggle_pin_team(day, pinned_team);
+    let pinned_pair = *data.pairings()[1].iter().find(|p| p.has(s[0])).unwrap();
+    let student = *s.iter().find(|&&st| !pinned_pair.has(st)).unwrap();
+    let student_team = team_of(&data, student);
+    data.toggle_pin_student(day, student);
+    assert!(data.is_pinned(day, &pinned_pair));
+    for mode in ShuffleMode::all() {
+        data.run_shuffle(day, mode, None);
+        assert!(data.check().is_empty(), "{}: {:?}", mode, data.check());
+        assert!(data.pairings()[1].contains(&pinned_pair), "{} moved a pinned team", mode);
+        assert_eq!(team_of(&data, student), student_team, "{} moved a pinned student", mode);
+    }
+    data.toggle_pin_team(day, pinned_team);
+    data.toggle_pin_student(day, student);
+    assert_eq!(data.pins(day), Pins::default());
+}
+
+#[test]
+fn test_move_student() {
+    let mut data = Data::empty("testcourse", "testcourse.yaml");
+    let (a, b) = (Section::from("A".to_string()), Section::from("B".to_string()));
+    data.new_section(a, Zoom::from("".to_string()));
+    data.new_section(b, Zoom::from("".to_string()));
+    let s: Vec<_> = ["ann", "bob", "cat", "dan"].iter()
+        .map(|n| Student::from(n.to_string())).collect();
+    for &st in s.iter() {
+        data.new_student(st, a);
+    }
+    let (red, blue) = (Team::from("red".to_string()), Team::from("blue".to_string()));
+    data.new_team(red);
+    data.new_team(blue);
+    data.add_day();
+    let day = Day::from(0);
+    let placed = |data: &Data, st: Student| *data.pairings()[0].iter().find(|p| p.has(st)).unwrap();
+
+    data.move_student(day, s[0], Destination::Team(a, red)).unwrap();
+    data.move_student(day, s[1], Destination::Team(a, red)).unwrap();
+    assert!(placed(&data, s[0]).full_pair());
+    assert!(data.move_student(day, s[2], Destination::Team(a, red)).is_err());
+    data.move_student(day, s[2], Destination::NewTeam(b)).unwrap();
+    assert_eq!(placed(&data, s[2]).team(), Some(blue));
+    assert_eq!(placed(&data, s[2]).section(), Some(b));
+    assert!(data.move_student(day, s[3], Destination::Team(a, blue)).is_err());
+    assert!(data.move_student(day, s[3], Destination::NewTeam(a)).is_err());
+
+    data.move_student(day, s[0], Destination::Absent).unwrap();
+    assert_eq!(placed(&data, s[0]), Pairing::Absent(s[0]));
+    assert_eq!(placed(&data, s[1]).team(), Some(red));
+    assert!(!placed(&data, s[1]).full_pair());
+    data.move_student(day, s[0], Destination::Unassigned).unwrap();
+    assert_eq!(placed(&data, s[0]), Pairing::Unassigned { student: s[0], section: a });
+    data.move_student(day, s[2], Destination::Unassigned).unwrap();
+    assert_eq!(placed(&data, s[2]), Pairing::Unassigned { student: s[2], section: b });
+    assert_eq!(data.unused_teams(day), vec![blue]);
+    assert!(data.move_student(day, Student::from("zed".to_string()),
+                              Destination::Absent).is_err());
+    assert!(data.check().is_empty(), "{:?}", data.check());
 }
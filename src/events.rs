@@ -0,0 +1,53 @@
+//! Change events for each course.  Every time a course is saved its
+//! version goes up, and pages that show the course long-poll for a new
+//! version so that they can update themselves.  The server handles each
+//! request on its own thread, so a waiting poll only holds up itself.
+
+use std::collections::BTreeMap;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// The version of each course, by its secret path.  Versions start
+/// from zero when the server starts.
+static VERSIONS: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+static CHANGED: Condvar = Condvar::new();
+
+/// Note that the course with the given secret path has changed.
+pub fn publish(course: &str) {
+    let mut versions = VERSIONS.lock().unwrap();
+    *versions.entry(course.to_string()).or_insert(0) += 1;
+    CHANGED.notify_all();
+}
+
+pub fn version(course: &str) -> u64 {
+    VERSIONS.lock().unwrap().get(course).cloned().unwrap_or(0)
+}
+
+/// Wait until the version of `course` differs from `since`, giving up
+/// after `timeout`, and return the version.  Versions are compared for
+/// difference rather than order, so that a page from before a restart
+/// of the server updates too.
+pub fn wait(course: &str, since: u64, timeout: Duration) -> u64 {
+    let deadline = Instant::now() + timeout;
+    let mut versions = VERSIONS.lock().unwrap();
+    loop {
+        let version = versions.get(course).cloned().unwrap_or(0);
+        let now = Instant::now();
+        if version != since || now >= deadline {
+            return version;
+        }
+        versions = CHANGED.wait_timeout(versions, deadline - now).unwrap().0;
+    }
+}
+
+#[test]
+fn test_wait() {
+    let course = "testeventscourse";
+    let since = version(course);
+    assert_eq!(wait(course, since, Duration::from_millis(10)), since);
+    let waiter = ::std::thread::spawn(move || wait(course, since, Duration::from_secs(10)));
+    ::std::thread::sleep(Duration::from_millis(20));
+    publish(course);
+    assert_eq!(waiter.join().unwrap(), since + 1);
+    assert_eq!(wait(course, since, Duration::from_secs(10)), since + 1);
+}
@@ -0,0 +1,138 @@
+//! The pair-history matrix: who has worked with whom, and when.
+
+use database::{Data, Day, Section, Student, Team};
+
+/// One cell of the grid, describing the history of a pair of students.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct GridCell {
+    /// A unique html id for the details of this cell.
+    pub id: String,
+    pub partner: Student,
+    pub days: Vec<(Day, Team)>,
+    /// A css class describing how recently they worked together.
+    pub recency: String,
+}
+
+impl GridCell {
+    pub fn count(&self) -> usize {
+        self.days.len()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SectionGrid {
+    pub section: Section,
+    pub students: Vec<Student>,
+    pub rows: Vec<(Student, Vec<GridCell>)>,
+}
+
+/// The history of every pair of students in each section, up to and
+/// including `today`.
+pub fn section_grids(data: &Data, today: Day) -> Vec<SectionGrid> {
+    let mut grids = Vec::new();
+    for (n, (section, students)) in data.list_students_by_section().into_iter().enumerate() {
+        let mut rows = Vec::new();
+        for (i, &s1) in students.iter().enumerate() {
+            let mut cells = Vec::new();
+            for (j, &s2) in students.iter().enumerate() {
+                let days = if i == j {
+                    Vec::new()
+                } else {
+                    data.partner_history(today.next(), s1, s2)
+                };
+                let recency = if i == j {
+                    "self"
+                } else if let Some(&(last, _)) = days.last() {
+                    recency(today.id - last.id)
+                } else {
+                    "never"
+                };
+                cells.push(GridCell {
+                    id: format!("pair-{}-{}-{}", n, i, j),
+                    partner: s2,
+                    days,
+                    recency: recency.to_string(),
+                });
+            }
+            rows.push((s1, cells));
+        }
+        grids.push(SectionGrid { section, students, rows });
+    }
+    grids
+}
+
+fn recency(days_ago: usize) -> &'static str {
+    match days_ago {
+        0 => "today",
+        1 => "recent",
+        2 | 3 => "older",
+        _ => "old",
+    }
+}
+
+#[test]
+fn test_section_grids() {
+    use database::Zoom;
+    let mut data = Data::empty("testcourse", "testcourse.yaml");
+    let (a, b) = (Section::from("A".to_string()), Section::from("B".to_string()));
+    data.new_section(a, Zoom::from("".to_string()));
+    data.new_section(b, Zoom::from("".to_string()));
+    let s: Vec<_> = ["ann", "bob", "cat", "dan", "eve"].iter()
+        .map(|n| Student::from(n.to_string())).collect();
+    for &st in s.iter() {
+        data.new_student(st, a);
+    }
+    data.new_team(Team::from("red".to_string()));
+    data.new_team(Team::from("blue".to_string()));
+    let (ann, bob, cat, dan, eve) = (s[0], s[1], s[2], s[3], s[4]);
+    data.new_student(Student::from("hal".to_string()), b);
+    let (red, blue) = (Team::from("red".to_string()), Team::from("blue".to_string()));
+    let absent = Section::from("".to_string());
+    // Who works with whom on each day, with everyone else absent: eve
+    // never comes, and cat is away on day 2, when dan works alone.
+    let days = [[(ann, bob), (cat, dan)],
+                [(ann, cat), (bob, dan)],
+                [(ann, bob), (dan, dan)],
+                [(ann, dan), (bob, cat)],
+                [(ann, cat), (bob, dan)]];
+    for (d, pairs) in days.iter().enumerate() {
+        data.add_day();
+        let day = Day::from(d);
+        for &student in [ann, bob, cat, dan, eve].iter() {
+            data.assign_student(day, student, absent, red);
+        }
+        for (&(s1, s2), &team) in pairs.iter().zip([red, blue].iter()) {
+            data.assign_student(day, s1, a, team);
+            data.assign_student(day, s2, a, team);
+        }
+    }
+
+    let grids = section_grids(&data, Day::from(4));
+    assert_eq!(grids.iter().map(|g| g.section).collect::<Vec<_>>(), vec![a, b]);
+    assert_eq!(grids[1].rows.len(), 1);
+    let grid = &grids[0];
+    assert_eq!(grid.students, vec![ann, bob, cat, dan, eve]);
+    let cell = |s1: Student, s2: Student| {
+        let row = &grid.rows.iter().find(|r| r.0 == s1).unwrap().1;
+        row.iter().find(|c| c.partner == s2).unwrap().clone()
+    };
+    let days_of = |c: &GridCell| c.days.iter().map(|&(d, t)| (d.id, t)).collect::<Vec<_>>();
+    assert_eq!(days_of(&cell(ann, bob)), vec![(0, red), (2, red)]);
+    assert_eq!(days_of(&cell(bob, ann)), days_of(&cell(ann, bob)));
+    assert_eq!(days_of(&cell(cat, dan)), vec![(0, blue)]);
+    assert_eq!((cell(ann, cat).count(), cell(bob, dan).count()), (2, 2));
+    assert_eq!(cell(dan, eve).count(), 0);
+    let recency = |s1, s2| cell(s1, s2).recency;
+    assert_eq!(recency(ann, ann), "self");
+    assert_eq!(recency(ann, cat), "today");
+    assert_eq!(recency(ann, dan), "recent");
+    assert_eq!(recency(ann, bob), "older");
+    assert_eq!(recency(cat, dan), "old");
+    assert_eq!(recency(ann, eve), "never");
+
+    // Looking back from day 2, the later days do not count yet.
+    let grids = section_grids(&data, Day::from(2));
+    let row = &grids[0].rows[0].1;
+    assert_eq!(row.iter().map(|c| c.count()).collect::<Vec<_>>(), vec![0, 2, 1, 0, 0]);
+    assert_eq!(row[1].recency, "today");
+}
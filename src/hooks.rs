@@ -0,0 +1,317 @@
+//! Provisioning of each team's shared resources, such as a whiteboard,
+//! doc, repository or breakout room.  The server's configuration may
+//! name a hook, which is either a local command or a web service, to
+//! be run when a team is created, renamed, or first used on a day.
+//! The hook reports the links to the team's resources, which are kept
+//! with the course and shown alongside the team from then on.  The
+//! server runs the hook from the course's outbox (see `outbox`), so a
+//! slow or broken hook holds up no request, and is retried with growing
+//! delays rather than on every change to a day.
+//!
+//! A command is given the event in environment variables (`PAIRS_EVENT`,
+//! `PAIRS_COURSE`, `PAIRS_TEAM`, `PAIRS_OLD_TEAM`, `PAIRS_SECTION` and
+//! `PAIRS_DAY`) and prints one "label: link" per line.  A web service
+//! is posted the event as JSON and answers with a JSON object of links
+//! by label, or with lines as a command would.
+
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use serde_json;
+use ureq;
+
+use database::{Data, Day, Team};
+use links;
+use outbox::{self, Job};
+
+/// How long a hook may take before it is abandoned.
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Hook {
+    /// A program and its arguments.
+    Command(Vec<String>),
+    /// The URL of a web service.
+    Url(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TeamChange {
+    Created,
+    Renamed,
+    Used,
+}
+
+impl TeamChange {
+    pub fn name(self) -> &'static str {
+        match self {
+            TeamChange::Created => "created",
+            TeamChange::Renamed => "renamed",
+            TeamChange::Used => "used",
+        }
+    }
+}
+
+/// What happened to a team, as given to the hook.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TeamEvent {
+    pub event: TeamChange,
+    /// The secret path of the course.
+    pub course: String,
+    pub team: String,
+    /// The team's name before it was renamed.
+    pub old_team: Option<String>,
+    /// The section and day on which the team was first used.
+    pub section: Option<String>,
+    pub day: Option<usize>,
+    /// The links the team already has, by label.
+    pub resources: BTreeMap<String, String>,
+}
+
+impl TeamEvent {
+    fn new(data: &Data, event: TeamChange, team: Team) -> Self {
+        TeamEvent {
+            event,
+            course: data.path().to_string(),
+            team: team.name.to_string(),
+            old_team: None,
+            section: None,
+            day: None,
+            resources: data.team_resources(team),
+        }
+    }
+}
+
+impl Hook {
+    /// Run the hook for `event`, returning the links it reports.
+    pub fn run(&self, event: &TeamEvent) -> Result<BTreeMap<String, String>, String> {
+        let output = match *self {
+            Hook::Command(ref argv) => run_command(argv, event)?,
+            Hook::Url(ref url) => post(url, event)?,
+        };
+        parse_resources(&output)
+    }
+}
+
+fn run_command(argv: &[String], event: &TeamEvent) -> Result<String, String> {
+    let program = argv.first().ok_or("The provisioning command is empty")?;
+    let mut child = Command::new(program)
+        .args(&argv[1..])
+        .env("PAIRS_EVENT", event.event.name())
+        .env("PAIRS_COURSE", &event.course)
+        .env("PAIRS_TEAM", &event.team)
+        .env("PAIRS_OLD_TEAM", event.old_team.clone().unwrap_or_default())
+        .env("PAIRS_SECTION", event.section.clone().unwrap_or_default())
+        .env("PAIRS_DAY", event.day.map(|d| d.to_string()).unwrap_or_default())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Unable to run {:?}: {}", program, e))?;
+    // Read the output as it comes, so that the command cannot fill
+    // the pipe and wait for us forever.
+    let mut stdout = child.stdout.take().ok_or("No output from the command")?;
+    let reader = ::std::thread::spawn(move || {
+        let mut output = String::new();
+        stdout.read_to_string(&mut output).map(|_| output)
+    });
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait().map_err(|e| format!("Error running {:?}: {}", program, e))? {
+            Some(status) => break status,
+            None if started.elapsed() > TIMEOUT => {
+                child.kill().ok();
+                child.wait().ok();
+                return Err(format!("{:?} took too long", program));
+            }
+            None => ::std::thread::sleep(Duration::from_millis(20)),
+        }
+    };
+    let output = reader.join().map_err(|_| format!("Error reading from {:?}", program))?
+        .map_err(|e| format!("Error reading from {:?}: {}", program, e))?;
+    if !status.success() {
+        return Err(format!("{:?} failed: {}", program, status));
+    }
+    Ok(output)
+}
+
+fn post(url: &str, event: &TeamEvent) -> Result<String, String> {
+    let body = serde_json::to_string(event).map_err(|e| e.to_string())?;
+    ureq::post(url)
+        .timeout(TIMEOUT)
+        .set("Content-Type", "application/json")
+        .send_string(&body)
+        .map_err(|e| format!("Error posting to {}: {}", url, e))?
+        .into_string()
+        .map_err(|e| format!("Error reading from {}: {}", url, e))
+}
+
+/// Read the links reported by a hook, either as a JSON object or as
+/// lines of "label: link".  Only web links are accepted.
+fn parse_resources(output: &str) -> Result<BTreeMap<String, String>, String> {
+    let resources: BTreeMap<String, String> = if output.trim_start().starts_with('{') {
+        serde_json::from_str(output).map_err(|e| format!("Invalid links {:?}: {}", output, e))?
+    } else {
+        output.lines().filter(|l| !l.trim().is_empty()).map(|line| {
+            let i = line.find(": ")
+                .ok_or_else(|| format!("Expected \"label: link\", not {:?}", line))?;
+            Ok((line[..i].trim().to_string(), line[i+2..].trim().to_string()))
+        }).collect::<Result<_, String>>()?
+    };
+    if let Some((_, url)) = resources.iter().find(|(_, url)| !links::is_web_link(url)) {
+        return Err(format!("{:?} is not a web link", url));
+    }
+    Ok(resources)
+}
+
+/// Keep the links reported for the team of `event` along with those
+/// it already has, unless the team has since been deleted or renamed.
+pub fn keep_resources(data: &mut Data, event: &TeamEvent, resources: BTreeMap<String, String>) {
+    let team = Team::from(event.team.clone());
+    if !data.list_teams().contains(&team) {
+        return;
+    }
+    let mut all = data.team_resources(team);
+    all.extend(resources);
+    data.set_team_resources(team, all);
+}
+
+/// Run `hook` for `event` now, keeping the links it reports.
+pub fn provision(hook: &Hook, data: &mut Data, event: &TeamEvent) -> Result<(), String> {
+    let resources = hook.run(event)?;
+    keep_resources(data, event, resources);
+    Ok(())
+}
+
+/// Queue `events` for `hook` in the outbox of the course, except for
+/// teams which are already waiting for it.
+pub fn queue(hook: &Hook, data: &Data, events: Vec<TeamEvent>) -> Result<(), String> {
+    let waiting: Vec<String> = outbox::pending(data.file()).into_iter()
+        .filter_map(|p| match p.job {
+            Job::Provision { event, .. } => Some(event.team),
+            _ => None,
+        })
+        .collect();
+    let jobs = events.into_iter()
+        .filter(|e| !waiting.contains(&e.team))
+        .map(|event| Job::Provision { hook: hook.clone(), event })
+        .collect();
+    outbox::enqueue(data.file(), jobs)
+}
+
+/// The event for a team which has just been created.
+pub fn created(data: &Data, team: Team) -> TeamEvent {
+    TeamEvent::new(data, TeamChange::Created, team)
+}
+
+/// The event for a team which has been renamed (in `data`) from `old`.
+pub fn renamed(data: &Data, old: Team, team: Team) -> TeamEvent {
+    let mut event = TeamEvent::new(data, TeamChange::Renamed, team);
+    event.old_team = Some(old.name.to_string());
+    event
+}
+
+/// The events for each team on `day` which has no resources yet.
+pub fn used(data: &Data, day: Day) -> Vec<TeamEvent> {
+    data.pairings().get(day.id).into_iter()
+        .flat_map(|pairings| pairings.iter())
+        .filter_map(|p| match (p.section(), p.team()) {
+            (Some(section), Some(team)) if data.team_resources(team).is_empty() => {
+                let mut event = TeamEvent::new(data, TeamChange::Used, team);
+                event.section = Some(section.name.to_string());
+                event.day = Some(day.id);
+                Some(event)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_command_hook() {
+    use database::{Section, ShuffleMode, Student, Zoom};
+    let mut data = Data::empty("testcourse", "testcourse.yaml");
+    let section = Section::from("A".to_string());
+    data.new_section(section, Zoom::from("".to_string()));
+    for name in &["ann", "bob"] {
+        data.new_student(Student::from(name.to_string()), section);
+    }
+    let hook = Hook::Command(vec!["sh".to_string(), "-c".to_string(),
+                                 "echo \"Board: https://wb.example.com/$PAIRS_TEAM\"; \
+                                  echo \"Event: https://x.example.com/$PAIRS_EVENT$PAIRS_DAY\"; \
+                                  test \"$PAIRS_TEAM\" != broken"
+                                 .to_string()]);
+    let red = Team::from("red".to_string());
+    data.new_team(red);
+    let event = created(&data, red);
+    provision(&hook, &mut data, &event).unwrap();
+    assert_eq!(data.team_resources(red)["Board"], "https://wb.example.com/red");
+    assert_eq!(data.team_resources(red)["Event"], "https://x.example.com/created");
+
+    let blue = Team::from("blue".to_string());
+    data.rename_team(red, blue).unwrap();
+    let event = renamed(&data, red, blue);
+    provision(&hook, &mut data, &event).unwrap();
+    assert!(data.team_resources(red).is_empty());
+    assert_eq!(data.team_resources(blue)["Board"], "https://wb.example.com/blue");
+    assert_eq!(data.team_resources(blue)["Event"], "https://x.example.com/renamed");
+
+    let gold = Team::from("gold".to_string());
+    data.new_team(gold);
+    data.delete_team(blue);
+    data.add_day();
+    data.run_shuffle(Day::from(0), ShuffleMode::Shuffle, None);
+    let events = used(&data, Day::from(0));
+    assert_eq!(events.len(), 1);
+    provision(&hook, &mut data, &events[0]).unwrap();
+    assert_eq!(data.team_resources(gold)["Event"], "https://x.example.com/used0");
+    assert_eq!(data.team_links(section, gold).len(), 2);
+    // Teams which already have resources are reused, not provisioned again.
+    data.set_team_resources(gold, vec![("Doc".to_string(), "https://d.example.com".to_string())]
+                            .into_iter().collect());
+    assert!(used(&data, Day::from(0)).is_empty());
+
+    let broken = Team::from("broken".to_string());
+    data.new_team(broken);
+    let event = created(&data, broken);
+    assert!(provision(&hook, &mut data, &event).is_err());
+    assert!(data.team_resources(broken).is_empty());
+}
+
+#[test]
+fn test_queued_hook() {
+    let dir = ::tempfile::TempDir::new().unwrap();
+    let mut data = Data::new(dir.path(), "testcourse1234");
+    let hook = Hook::Command(vec!["sh".to_string(), "-c".to_string(),
+                                 "test \"$PAIRS_TEAM\" != broken && \
+                                  echo \"Board: https://wb.example.com/$PAIRS_TEAM\"".to_string()]);
+    let (red, broken) = (Team::from("red".to_string()), Team::from("broken".to_string()));
+    data.new_team(red);
+    data.new_team(broken);
+    data.save();
+    queue(&hook, &data, vec![created(&data, broken), created(&data, red)]).unwrap();
+    // Nothing is run until the outbox is delivered.
+    assert!(data.team_resources(red).is_empty());
+    let start = outbox::now();
+    outbox::deliver_all(dir.path(), start);
+    // The broken team does not hold up the others, and is retried later
+    // rather than queued again.
+    let data = Data::open(data.file()).unwrap();
+    assert_eq!(data.team_resources(red)["Board"], "https://wb.example.com/red");
+    queue(&hook, &data, vec![created(&data, broken)]).unwrap();
+    let left = outbox::pending(data.file());
+    assert_eq!(left.len(), 1);
+    assert_eq!((left[0].attempts, left[0].next_attempt), (1, start + outbox::FIRST_RETRY));
+}
+
+#[test]
+fn test_parse_resources() {
+    assert_eq!(parse_resources("{\"Repo\": \"https://git.example.com/red\"}").unwrap()["Repo"],
+               "https://git.example.com/red");
+    assert_eq!(parse_resources("\n").unwrap(), BTreeMap::new());
+    assert!(parse_resources("Repo: javascript:alert(1)").is_err());
+    assert!(parse_resources("https://git.example.com/red").is_err());
+}
@@ -0,0 +1,183 @@
+//! Links for the sections and teams of a course, made from templates
+//! that each course configures, so that courses can use whichever
+//! meeting and whiteboard systems they like.  A template is a web link
+//! in which placeholders such as `{team}` are replaced, e.g.
+//! `https://example.com/boards/pair-{team}`.
+
+use database::{Section, Team, Zoom};
+
+/// The placeholders allowed in the link for a section's meeting.  The
+/// zoom of the section (with spaces and dashes removed) is inserted
+/// as is, since it may itself be a link.
+pub const MEETING_PLACEHOLDERS: &[&str] = &["zoom", "section"];
+/// The placeholders allowed in the links for a team.
+pub const TEAM_PLACEHOLDERS: &[&str] = &["team", "section"];
+
+/// A link shown for each team, such as a shared whiteboard.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TeamLink {
+    pub label: String,
+    pub template: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Links {
+    /// The template for the link to a section's meeting.
+    #[serde(default = "default_meeting")]
+    pub meeting: String,
+    #[serde(default)]
+    pub team: Vec<TeamLink>,
+}
+
+fn default_meeting() -> String {
+    "{zoom}".to_string()
+}
+
+impl Default for Links {
+    fn default() -> Self {
+        Links { meeting: default_meeting(), team: Vec::new() }
+    }
+}
+
+impl Links {
+    pub fn set_meeting(&mut self, template: &str) -> Result<(), String> {
+        let template = template.trim();
+        check_template(template, MEETING_PLACEHOLDERS)?;
+        self.meeting = template.to_string();
+        Ok(())
+    }
+    /// Set the template of the team link called `label`, adding it if
+    /// it is new, or remove it if `template` is empty.
+    pub fn set_team_link(&mut self, label: &str, template: &str) -> Result<(), String> {
+        let (label, template) = (label.trim(), template.trim());
+        if label.is_empty() {
+            return Err("A team link needs a label".to_string());
+        }
+        if template.is_empty() {
+            self.team.retain(|l| l.label != label);
+            return Ok(());
+        }
+        check_template(template, TEAM_PLACEHOLDERS)?;
+        let link = TeamLink { label: label.to_string(), template: template.to_string() };
+        match self.team.iter_mut().find(|l| l.label == label) {
+            Some(old) => *old = link,
+            None => self.team.push(link),
+        }
+        Ok(())
+    }
+    /// Replace every team link with those given one per line, as
+    /// "label: template".
+    pub fn set_team_links(&mut self, lines: &str) -> Result<(), String> {
+        let mut links = Links { meeting: self.meeting.clone(), team: Vec::new() };
+        for line in lines.lines().filter(|l| !l.trim().is_empty()) {
+            match line.find(": ") {
+                Some(i) => links.set_team_link(&line[..i], &line[i+2..])?,
+                None => return Err(format!("Expected \"label: link\", not {:?}", line.trim())),
+            }
+        }
+        *self = links;
+        Ok(())
+    }
+    /// The team links one per line, as read by `set_team_links`.
+    pub fn team_lines(&self) -> String {
+        self.team.iter().map(|l| format!("{}: {}\n", l.label, l.template)).collect()
+    }
+    /// The link to the meeting of `section`, or nothing if it does not
+    /// make a web link.
+    pub fn meeting(&self, section: Section, zoom: Zoom) -> String {
+        let zoom = zoom.id.replace(&[' ', '-'][..], "");
+        web_link(fill(&self.meeting, &[("zoom", zoom),
+                                       ("section", url_encode(&section.name))]))
+    }
+    /// The labelled links for `team` when it is in `section`.
+    pub fn team(&self, section: Section, team: Team) -> Vec<(String, String)> {
+        self.team.iter().map(|l| {
+            (l.label.clone(), web_link(fill(&l.template, &[("team", url_encode(&team.name)),
+                                                          ("section", url_encode(&section.name))])))
+        }).filter(|(_, url)| !url.is_empty()).collect()
+    }
+}
+
+/// Check that `template` uses only the given placeholders, and makes a
+/// web link (unless it starts with the zoom, which may be one).
+pub fn check_template(template: &str, placeholders: &[&str]) -> Result<(), String> {
+    let mut rest = template;
+    while let Some(open) = rest.find(['{', '}']) {
+        if rest[open..].starts_with('}') {
+            return Err(format!("Unmatched \"}}\" in {:?}", template));
+        }
+        let close = rest[open..].find('}')
+            .ok_or_else(|| format!("Unmatched \"{{\" in {:?}", template))?;
+        let name = &rest[open+1..open+close];
+        if !placeholders.contains(&name) {
+            let known: Vec<_> = placeholders.iter().map(|p| format!("{{{}}}", p)).collect();
+            return Err(format!("Unknown placeholder {{{}}} in {:?}: use {}",
+                               name, template, known.join(" or ")));
+        }
+        rest = &rest[open+close+1..];
+    }
+    if !template.starts_with("{zoom}") && !is_web_link(template) {
+        return Err(format!("{:?} does not start with http:// or https://", template));
+    }
+    Ok(())
+}
+
+fn fill(template: &str, values: &[(&str, String)]) -> String {
+    let mut link = template.to_string();
+    for (name, value) in values {
+        link = link.replace(&format!("{{{}}}", name), value);
+    }
+    link
+}
+
+pub fn is_web_link(link: &str) -> bool {
+    let lower = link.to_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+/// `link` if it is a web link, so that a zoom or name cannot make a
+/// javascript: url, or nothing otherwise.
+fn web_link(link: String) -> String {
+    if is_web_link(&link) { link } else { String::new() }
+}
+
+/// Percent-encode `s` for use in a link.
+pub fn url_encode(s: &str) -> String {
+    s.bytes().map(|b| match b {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+            (b as char).to_string()
+        }
+        _ => format!("%{:02X}", b),
+    }).collect()
+}
+
+#[test]
+fn test_links() {
+    let (section, team) = (Section::from("A 1".to_string()), Team::from("red/x".to_string()));
+    let mut links = Links::default();
+    assert_eq!(links.meeting(section, Zoom::from("https://zoom.us/j/12-34".to_string())),
+               "https://zoom.us/j/1234");
+    assert_eq!(links.meeting(section, Zoom::from("123 456".to_string())), "");
+    assert_eq!(links.meeting(section, Zoom::from("javascript:alert(1)".to_string())), "");
+
+    links.set_meeting("https://example.zoom.us/j/{zoom}?s={section}").unwrap();
+    assert_eq!(links.meeting(section, Zoom::from("123 456".to_string())),
+               "https://example.zoom.us/j/123456?s=A%201");
+    assert!(links.set_meeting("https://x/{team}").is_err());
+    assert!(links.set_meeting("ftp://x/{zoom}").is_err());
+    assert!(links.set_meeting("https://x/{zoom").is_err());
+    assert!(links.set_meeting("https://x/zoom}").is_err());
+
+    links.set_team_links("Whiteboard: https://wb.example.com/pair-{team}\n\
+                          Repo: https://git.example.com/{section}/{team}\n").unwrap();
+    assert_eq!(links.team(section, team),
+               vec![("Whiteboard".to_string(), "https://wb.example.com/pair-red%2Fx".to_string()),
+                    ("Repo".to_string(), "https://git.example.com/A%201/red%2Fx".to_string())]);
+    assert_eq!(links.team_lines(), "Whiteboard: https://wb.example.com/pair-{team}\n\
+                                    Repo: https://git.example.com/{section}/{team}\n");
+    assert!(links.set_team_links("Doc https://docs.example.com/{team}").is_err());
+    assert!(links.set_team_links("Doc: https://docs.example.com/{zoom}").is_err());
+    assert_eq!(links.team.len(), 2, "a failed update changed the links");
+    links.set_team_link("Repo", "").unwrap();
+    assert_eq!(links.team.len(), 1);
+}
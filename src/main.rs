@@ -5,17 +5,41 @@ extern crate askama;
 extern crate serde_derive;
 extern crate serde;
 extern crate serde_yaml;
+extern crate serde_json;
 extern crate tempfile;
 extern crate internment;
 extern crate rand;
+extern crate ureq;
 
 mod atomicfile;
+mod attributes;
 pub mod database;
+mod cli;
+mod config;
+mod events;
+mod hooks;
+mod links;
+mod notify;
+mod outbox;
+mod registry;
+mod roster;
+mod session;
+mod sheet;
+mod grid;
+mod preview;
+mod statistics;
+mod webhooks;
 
-use rouille::{Response};
+use std::collections::{BTreeSet, HashMap};
+
+use rouille::{Request, Response};
 use askama::Template;
 
-use database::{Student, Day, Team, Section, Zoom, StudentOptions, TeamOptions};
+use config::Config;
+use registry::{CourseInfo, CourseSummary};
+
+use database::{Student, Day, Team, Section, Zoom, StudentOptions, TeamOptions, ShuffleMode,
+               TagObjective, Pairing, Pins, Destination};
 
 #[derive(Template, Serialize, Deserialize, Clone)]
 #[template(path = "edit-day.html")]
@@ -25,6 +49,21 @@ struct EditDay {
     absent: Vec<Student>,
     all: Vec<StudentOptions>,
     path: String,
+    base: String,
+    csrf: String,
+}
+impl EditDay {
+    fn new(data: &database::Data, path: &str, base: &str, csrf: &str, today: Day) -> Self {
+        EditDay {
+            path: path.to_string(),
+            base: base.to_string(),
+            csrf: csrf.to_string(),
+            today,
+            unassigned: data.unassigned_students(today),
+            absent: data.absent_students(today),
+            all: data.student_options(today).into_iter().flat_map(|(_,v)| v).collect(),
+        }
+    }
 }
 
 #[derive(Template, Serialize, Deserialize, Clone)]
@@ -34,7 +73,62 @@ struct TeamView {
     unassigned: Vec<Student>,
     absent: Vec<Student>,
     all: Vec<(Section, Vec<TeamOptions>)>,
+    has_rotation: bool,
+    balance_attribute: String,
+    warnings: Vec<String>,
+    roles: HashMap<Student, (usize, usize)>,
+    pins: Pins,
+    team_links: HashMap<Team, Vec<(String, String)>>,
+    /// The version of the course shown, see `events`.
+    version: u64,
     path: String,
+    base: String,
+    csrf: String,
+}
+impl TeamView {
+    fn new(data: &database::Data, path: &str, base: &str, csrf: &str, today: Day,
+           warnings: Vec<String>) -> Self {
+        TeamView {
+            path: path.to_string(),
+            base: base.to_string(),
+            csrf: csrf.to_string(),
+            today,
+            unassigned: data.unassigned_students(today),
+            absent: data.absent_students(today),
+            all: data.team_options(today),
+            roles: statistics::student_stats_before(data, today.next()).into_iter()
+                .map(|s| (s.student, (s.driver, s.navigator))).collect(),
+            has_rotation: !data.rotation().is_empty(),
+            balance_attribute: data.balance_attribute().unwrap_or("").to_string(),
+            warnings,
+            pins: data.pins(today),
+            team_links: team_links(data, today),
+            version: 0,
+        }
+    }
+    fn links_of(&self, t: &TeamOptions) -> &[(String, String)] {
+        self.team_links.get(&t.team).map(|l| l.as_slice()).unwrap_or(&[])
+    }
+    fn team_pinned(&self, t: &TeamOptions) -> bool {
+        self.pins.teams.contains(&t.team)
+    }
+    fn student_pinned(&self, s: &Student) -> bool {
+        self.pins.students.contains(s)
+    }
+    /// Who drives and who navigates, with how often each has done each
+    /// role up to and including today.
+    fn roles_of(&self, t: &TeamOptions) -> String {
+        let counts = |s: Student| {
+            let (d, n) = self.roles.get(&s).cloned().unwrap_or((0, 0));
+            format!("{} ({}D/{}N)", s.name, d, n)
+        };
+        match t.current_pairing {
+            Pairing::Pair { primary, secondary, .. } => {
+                format!("driver {}, navigator {}", counts(primary), counts(secondary))
+            }
+            _ => String::new(),
+        }
+    }
 }
 
 #[derive(Template, Serialize, Deserialize, Clone)]
@@ -43,34 +137,470 @@ struct SectionView {
     today: Day,
     unassigned: Vec<Student>,
     absent: Vec<Student>,
-    all: Vec<(Section, Vec<TeamOptions>, Zoom)>,
+    /// The teams of each section, with the link to its meeting.
+    all: Vec<(Section, Vec<TeamOptions>, String)>,
+    team_links: HashMap<Team, Vec<(String, String)>>,
+    /// The version of the course shown, see `events`.
+    version: u64,
     path: String,
+    base: String,
+}
+impl SectionView {
+    fn new(data: &database::Data, path: &str, base: &str, today: Day) -> Self {
+        let mut unassigned = data.unassigned_students(today);
+        let absent = data.absent_students(today);
+        unassigned.retain(|s| !absent.contains(s));
+        SectionView {
+            path: path.to_string(),
+            base: base.to_string(),
+            today,
+            unassigned,
+            absent,
+            all: data.team_options(today).into_iter()
+                .map(|(sec,stu)| (sec, stu, data.meeting_link(sec)))
+                .collect(),
+            team_links: team_links(data, today),
+            version: 0,
+        }
+    }
+    fn links_of(&self, t: &TeamOptions) -> &[(String, String)] {
+        self.team_links.get(&t.team).map(|l| l.as_slice()).unwrap_or(&[])
+    }
+}
+
+/// The links of each team on `today`, in its section that day.
+fn team_links(data: &database::Data, today: Day) -> HashMap<Team, Vec<(String, String)>> {
+    data.pairings().get(today.id).into_iter().flat_map(|day| day.iter())
+        .filter_map(|p| match (p.section(), p.team()) {
+            (Some(section), Some(team)) => Some((team, data.team_links(section, team))),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The teams of a day as they stand, which the board shows and which
+/// its endpoint returns as JSON after each move.
+#[derive(Serialize, Deserialize, Clone)]
+struct BoardState {
+    /// Why the last move was refused, if it was.
+    error: Option<String>,
+    team_options: Vec<(Section, Vec<TeamOptions>)>,
+    unassigned: Vec<Student>,
+    absent: Vec<Student>,
+}
+impl BoardState {
+    fn new(data: &database::Data, today: Day) -> Self {
+        let absent = data.absent_students(today);
+        let mut unassigned = data.unassigned_students(today);
+        unassigned.retain(|s| !absent.contains(s));
+        BoardState {
+            error: None,
+            team_options: data.team_options(today),
+            unassigned,
+            absent,
+        }
+    }
+}
+
+#[derive(Template, Serialize, Deserialize, Clone)]
+#[template(path = "board.html")]
+struct Board {
+    today: Day,
+    state: BoardState,
+    /// The version of the course shown, see `events`.
+    version: u64,
+    path: String,
+    base: String,
+    csrf: String,
+}
+impl Board {
+    fn new(data: &database::Data, path: &str, base: &str, csrf: &str, today: Day) -> Self {
+        Board {
+            path: path.to_string(),
+            base: base.to_string(),
+            csrf: csrf.to_string(),
+            today,
+            state: BoardState::new(data, today),
+            version: 0,
+        }
+    }
+}
+
+#[derive(Template, Serialize, Deserialize, Clone)]
+#[template(path = "print.html")]
+struct PrintView {
+    sheet: sheet::Sheet,
+    path: String,
+    base: String,
+}
+
+#[derive(Template, Serialize, Deserialize, Clone)]
+#[template(path = "projector.html")]
+struct ProjectorView {
+    sheet: sheet::Sheet,
+    /// The version of the course shown, see `events`.
+    version: u64,
+    path: String,
+    base: String,
+}
+
+#[derive(Template, Serialize, Deserialize, Clone)]
+#[template(path = "publish.html")]
+struct PublishPage {
+    today: Day,
+    /// The messages each student would be sent.
+    messages: Vec<notify::Message>,
+    deliveries: Vec<notify::Delivery>,
+    /// How many messages are waiting to be sent.
+    waiting: usize,
+    /// The kinds of channel the server sends messages through.
+    channels: Vec<String>,
+    path: String,
+    base: String,
+    csrf: String,
+}
+impl PublishPage {
+    fn new(data: &database::Data, path: &str, base: &str, csrf: &str, today: Day,
+           channels: &[notify::Channel]) -> Self {
+        let mut channels: Vec<String> = channels.iter().map(|c| match *c {
+            notify::Channel::Smtp { .. } => "email".to_string(),
+            notify::Channel::Webhook(_) => "webhook".to_string(),
+        }).collect();
+        channels.dedup();
+        PublishPage {
+            today,
+            messages: notify::messages(data, today),
+            deliveries: data.deliveries(today).to_vec(),
+            waiting: notify::waiting(data, today),
+            channels,
+            path: path.to_string(),
+            base: base.to_string(),
+            csrf: csrf.to_string(),
+        }
+    }
+    fn channels_string(&self) -> String {
+        self.channels.join(" and ")
+    }
+    fn published(&self) -> bool {
+        !self.deliveries.is_empty() || self.waiting > 0
+    }
+    fn email_of<'a>(&self, m: &'a notify::Message) -> &'a str {
+        m.email.as_deref().unwrap_or("")
+    }
+    fn time_of(&self, d: &notify::Delivery) -> String {
+        registry::format_time(d.time)
+    }
+    fn delivered(&self, d: &notify::Delivery) -> bool {
+        d.error.is_none()
+    }
+    fn error_of<'a>(&self, d: &'a notify::Delivery) -> &'a str {
+        d.error.as_deref().unwrap_or("")
+    }
+}
+
+#[derive(Template, Serialize, Deserialize, Clone)]
+#[template(path = "grid.html")]
+struct GridView {
+    today: Day,
+    grids: Vec<grid::SectionGrid>,
+    path: String,
+    base: String,
+}
+
+/// One candidate in a preview, as a column of the comparison.
+#[derive(Serialize, Deserialize, Clone)]
+struct PreviewColumn {
+    label: String,
+    score: preview::Score,
+    warnings: Vec<String>,
+}
+
+/// A student's current placement and their placement in each
+/// candidate, marked if it differs.
+#[derive(Serialize, Deserialize, Clone)]
+struct PreviewRow {
+    student: Student,
+    current: String,
+    cells: Vec<(String, bool)>,
+}
+
+#[derive(Template, Serialize, Deserialize, Clone)]
+#[template(path = "preview.html")]
+struct PreviewPage {
+    today: Day,
+    modes: Vec<ShuffleMode>,
+    sections: Vec<Section>,
+    current: preview::Score,
+    columns: Vec<PreviewColumn>,
+    rows: Vec<PreviewRow>,
+    path: String,
+    base: String,
+    csrf: String,
+}
+impl PreviewPage {
+    fn new(data: &database::Data, path: &str, base: &str, csrf: &str, today: Day) -> Self {
+        let current = &data.pairings()[today.id];
+        let candidates = data.preview(today).map(|p| p.candidates.clone()).unwrap_or_default();
+        let students: BTreeSet<Student> = current.iter()
+            .chain(candidates.iter().flat_map(|c| c.pairings.iter()))
+            .flat_map(|p| p.allocated_students())
+            .collect();
+        PreviewPage {
+            today,
+            modes: ShuffleMode::all().into_iter().filter(|&m| m != ShuffleMode::Clear).collect(),
+            sections: data.list_sections(),
+            current: preview::score(data, today, current, current),
+            columns: candidates.iter().map(|c| PreviewColumn {
+                label: c.label(),
+                score: preview::score(data, today, current, &c.pairings),
+                warnings: c.warnings.clone(),
+            }).collect(),
+            rows: students.into_iter().map(|s| {
+                let now = preview::placement(current, s);
+                let cells = candidates.iter().map(|c| {
+                    let then = preview::placement(&c.pairings, s);
+                    let changed = then != now;
+                    (then, changed)
+                }).collect();
+                PreviewRow { student: s, current: now, cells }
+            }).collect(),
+            path: path.to_string(),
+            base: base.to_string(),
+            csrf: csrf.to_string(),
+        }
+    }
+}
+
+#[derive(Template, Serialize, Deserialize)]
+#[template(path = "statistics.html")]
+struct Statistics {
+    sections: Vec<statistics::SectionStats>,
+    students: Vec<statistics::StudentStats>,
+    columns: Vec<String>,
+    sort: String,
+    path: String,
+    base: String,
+}
+impl Statistics {
+    fn is_sorted_by(&self, column: &str) -> bool {
+        self.sort == column
+    }
+    fn num_flagged(&self) -> usize {
+        self.students.iter().filter(|s| s.flagged).count()
+    }
 }
 
 #[derive(Template, Serialize, Deserialize)]
 #[template(path = "index.html")]
 struct Index {
+    info: CourseInfo,
     days: Vec<Day>,
+    rotation: String,
+    block_starts: Vec<usize>,
+    planned: Vec<usize>,
     path: String,
+    base: String,
+    csrf: String,
+}
+impl Index {
+    fn max_planned_days(&self) -> usize {
+        database::MAX_PLANNED_DAYS
+    }
+    fn new(data: &database::Data, path: &str, base: &str, csrf: &str) -> Self {
+        let days = data.list_days();
+        let mut block_starts: Vec<usize> = days.iter()
+            .filter_map(|&d| data.block_start(d)).map(|d| d.id).collect();
+        block_starts.dedup();
+        Index {
+            info: data.info().clone(),
+            rotation: data.rotation().iter().map(|n| n.to_string())
+                .collect::<Vec<_>>().join(" "),
+            block_starts,
+            planned: days.iter().filter(|&&d| data.is_planned(d)).map(|d| d.id).collect(),
+            days,
+            path: path.to_string(),
+            base: base.to_string(),
+            csrf: csrf.to_string(),
+        }
+    }
+    fn starts_block(&self, day: &Day) -> bool {
+        self.block_starts.contains(&day.id)
+    }
+    fn is_planned(&self, day: &Day) -> bool {
+        self.planned.contains(&day.id)
+    }
 }
 
 #[derive(Template, Serialize, Deserialize)]
 #[template(path = "style.html")]
 struct Css;
 
+#[derive(Template, Serialize, Deserialize)]
+#[template(path = "dashboard.html")]
+struct Dashboard {
+    courses: Vec<CourseSummary>,
+    archived: Vec<CourseSummary>,
+    path: String,
+    base: String,
+    csrf: String,
+}
+impl Dashboard {
+    fn new(config: &Config, csrf: &str) -> Self {
+        let (archived, courses) = registry::list_courses(&config.data_dir).into_iter()
+            .partition(|c| c.info.archived);
+        Dashboard {
+            courses,
+            archived,
+            path: "admin".to_string(),
+            base: config.base.clone(),
+            csrf: csrf.to_string(),
+        }
+    }
+}
+
+/// Check that the request comes from an instructor, returning their
+/// login name.
+fn instructor(config: &Config, request: &Request) -> Result<String, Response> {
+    match rouille::input::basic_http_auth(request) {
+        Some(ref auth) if config.instructors.get(&auth.login) == Some(&auth.password) => {
+            Ok(auth.login.clone())
+        }
+        _ => Err(Response::basic_http_auth_login_required("pairs")),
+    }
+}
+
 #[derive(Template, Serialize, Deserialize)]
 #[template(path = "students.html")]
 struct Students {
     sections: Vec<(Section, Vec<Student>)>,
     focus_section: Section,
+    attributes: HashMap<Student, String>,
+    tags: HashMap<Student, String>,
+    emails: HashMap<Student, String>,
+    inactive: Vec<Student>,
+    attribute_names: Vec<String>,
+    balance_attribute: String,
+    roster_source: Option<roster::RosterSource>,
+    /// The latest roster syncs, newest first.
+    syncs: Vec<roster::RosterSync>,
     path: String,
+    base: String,
+    csrf: String,
+}
+impl Students {
+    fn new(data: &database::Data, path: &str, base: &str, csrf: &str,
+           focus_section: Section) -> Self {
+        Students {
+            path: path.to_string(),
+            base: base.to_string(),
+            csrf: csrf.to_string(),
+            sections: data.list_students_by_section(),
+            focus_section,
+            attributes: data.list_students().into_iter().map(|s| {
+                let a: Vec<_> = data.student_attributes(s).into_iter()
+                    .map(|(k, v)| format!("{}: {}", k, v)).collect();
+                (s, a.join(", "))
+            }).collect(),
+            tags: data.list_students().into_iter()
+                .map(|s| (s, data.student_tags(s).join(", "))).collect(),
+            emails: data.list_students().into_iter()
+                .filter_map(|s| data.student_email(s).map(|e| (s, e.to_string()))).collect(),
+            inactive: data.list_students().into_iter().filter(|&s| !data.is_active(s)).collect(),
+            attribute_names: data.attribute_names(),
+            balance_attribute: data.balance_attribute().unwrap_or("").to_string(),
+            roster_source: data.roster_source().cloned(),
+            syncs: data.roster_log().iter().rev().take(5).cloned().collect(),
+        }
+    }
+    fn attributes_of(&self, s: &Student) -> &str {
+        self.attributes.get(s).map(|a| a.as_str()).unwrap_or("")
+    }
+    fn tags_of(&self, s: &Student) -> &str {
+        self.tags.get(s).map(|t| t.as_str()).unwrap_or("")
+    }
+    fn email_of(&self, s: &Student) -> &str {
+        self.emails.get(s).map(|e| e.as_str()).unwrap_or("")
+    }
+    fn is_active(&self, s: &Student) -> bool {
+        !self.inactive.contains(s)
+    }
+    fn source_kind(&self) -> &str {
+        self.roster_source.as_ref().map(|r| r.kind()).unwrap_or("")
+    }
+    fn source_location(&self) -> String {
+        self.roster_source.as_ref().map(|r| r.location()).unwrap_or_default()
+    }
+    fn source_section(&self) -> &str {
+        match self.roster_source {
+            Some(roster::RosterSource::Lti { ref section, .. }) => section,
+            _ => "",
+        }
+    }
+    fn source_string(&self) -> String {
+        self.roster_source.as_ref().map(|r| r.to_string()).unwrap_or_default()
+    }
+    fn time_of(&self, sync: &roster::RosterSync) -> String {
+        registry::format_time(sync.time)
+    }
 }
 
 #[derive(Template, Serialize, Deserialize)]
 #[template(path = "sections.html")]
 struct Sections {
-    sections: Vec<(Section, Zoom)>,
+    sections: Vec<SectionRow>,
+    objectives: Vec<TagObjective>,
+    links: links::Links,
+    webhooks: Vec<String>,
+    /// The events waiting to be posted to the webhooks.
+    pending: Vec<outbox::Pending>,
     path: String,
+    base: String,
+    csrf: String,
+}
+impl Sections {
+    fn new(data: &database::Data, path: &str, base: &str, csrf: &str) -> Self {
+        Sections {
+            path: path.to_string(),
+            base: base.to_string(),
+            csrf: csrf.to_string(),
+            sections: data.zoom_sections().into_iter().map(|(section, zoom)| {
+                let preferred: Vec<_> = data.preferred_students(section).iter()
+                    .map(|s| s.name.to_string()).collect();
+                SectionRow {
+                    section,
+                    zoom,
+                    objective: data.tag_objective(section),
+                    capacity: data.capacity(section).map(|c| c.to_string()).unwrap_or_default(),
+                    preferred: preferred.join(", "),
+                }
+            }).collect(),
+            objectives: TagObjective::all(),
+            links: data.links().clone(),
+            webhooks: data.webhooks().to_vec(),
+            pending: webhooks::pending(data.file()),
+        }
+    }
+    fn webhook_lines(&self) -> String {
+        self.webhooks.iter().map(|w| format!("{}\n", w)).collect()
+    }
+    /// Why the oldest waiting event has not been posted, if it failed.
+    fn webhook_error(&self) -> &str {
+        self.pending.iter().filter_map(|p| p.error.as_deref()).next().unwrap_or("")
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SectionRow {
+    section: Section,
+    zoom: Zoom,
+    objective: TagObjective,
+    capacity: String,
+    preferred: String,
+}
+impl SectionRow {
+    fn has_objective(&self, objective: &TagObjective) -> bool {
+        self.objective == *objective
+    }
 }
 
 #[derive(Template, Serialize, Deserialize)]
@@ -78,6 +608,8 @@ struct Sections {
 struct Teams {
     teams: Vec<Team>,
     path: String,
+    base: String,
+    csrf: String,
 }
 impl Teams {
     fn random(&self) -> String {
@@ -85,370 +617,1201 @@ impl Teams {
     }
 }
 
+/// A flash message describing a shuffle of `section` (or of every
+/// section), e.g. "Shuffled section A: 12 teams, 1 repeat".
+fn shuffled(data: &database::Data, day: Day, mode: ShuffleMode,
+            section: Option<Section>) -> String {
+    let section = section.filter(|_| !mode.is_grand());
+    let what = match section {
+        Some(section) => format!("section {}", section),
+        None => "every section".to_string(),
+    };
+    format!("{}: {}", mode.done(&what), preview::summary(data, day, section))
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 struct NewStudent {
     name: String,
 }
+/// Queue the provisioning of the teams used on `day` which have no
+/// resources yet, if the server has a provisioning hook.  The course
+/// must be saved first, since the hook runs in the background.
+fn provision_day(config: &Config, data: &database::Data, day: Day) -> Result<(), String> {
+    match config.provision {
+        Some(ref hook) => hooks::queue(hook, data, hooks::used(data, day)),
+        None => Ok(()),
+    }
+}
+
 fn main() {
-    println!("I am running now!!! and listening on port 8088");
-    rouille::start_server("0.0.0.0:8088", move |request| {
-        let is_css = router!{
-            request,
-            (GET) ["/pairs/style.css"] => {
-                Some(Response::from_data("text/css", Css.render().unwrap()))
-            },
-            _ => {
-                None
-            },
+    let args: Vec<String> = ::std::env::args().skip(1).collect();
+    if args.is_empty() || args[0] == "serve" || args[0].starts_with("--") {
+        let flags = if !args.is_empty() && args[0] == "serve" {
+            &args[1..]
+        } else {
+            &args[..]
         };
-        if let Some(response) = is_css {
-            return response;
+        match Config::from_args(flags) {
+            Ok(config) => serve(config),
+            Err(e) => {
+                eprintln!("{}", e);
+                ::std::process::exit(1);
+            }
         }
-        router!{
-            request,
-            (GET) (/) => {
-                rouille::Response::redirect_303(format!("/pairs/{}/", memorable_wordlist::camel_case(44)))
-            },
-            (GET) (/pairs/) => {
-                rouille::Response::redirect_303(format!("/pairs/{}/", memorable_wordlist::camel_case(44)))
-            },
-            (GET) (/pairs/{path: String}/) => {
-                let data = database::Data::new(&path);
-                let page = Index {
-                    path: path.to_string(),
-                    days: data.list_days(),
-                };
-                Response::html(page.render().unwrap())
-            },
-            (POST) (/pairs/{path: String}/) => {
-                let mut data = database::Data::new(&path);
-                match post_input!(request, {
-                    id: usize,
-                    name: String,
-                }) {
-                    Ok(input) => {
-                        if input.id == data.list_days().len() {
-                            data.add_day();
+    } else if let Err(e) = cli::run(&args) {
+        eprintln!("{}", e);
+        ::std::process::exit(1);
+    }
+}
+
+fn serve(config: Config) {
+    println!("I am running now!!! and listening on {} under {}/",
+             config.address, config.base);
+    let address = config.address.clone();
+    let sessions = session::Sessions::default();
+    outbox::start(config.data_dir.clone());
+    rouille::start_server(address, move |request| handle(&config, &sessions, request));
+}
+
+/// Answer a request to the server.
+fn handle(config: &Config, sessions: &session::Sessions, request: &Request) -> Response {
+    let base = &config.base;
+    if request.method() == "GET" && request.url() == "/" {
+        return Response::redirect_303(format!("{}/{}/", base,
+                                              registry::new_course_path()));
+    }
+    let request = match request.remove_prefix(base) {
+        Some(r) => r,
+        None => {
+            return Response::empty_404();
+        }
+    };
+    let request = &request;
+    if let Some(ref assets) = config.assets {
+        if let Some(r) = request.remove_prefix("/assets") {
+            return rouille::match_assets(&r, assets);
+        }
+    }
+    let is_css = router!{
+        request,
+        (GET) ["/style.css"] => {
+            Some(match config.stylesheet {
+                Some(ref f) => match ::std::fs::read_to_string(f) {
+                    Ok(css) => Response::from_data("text/css", css),
+                    Err(e) => Response::text(format!("Error reading {:?}: {}", f, e))
+                        .with_status_code(500),
+                },
+                None => Response::from_data("text/css", Css.render().unwrap()),
+            })
+        },
+        _ => {
+            None
+        },
+    };
+    if let Some(response) = is_css {
+        return response;
+    }
+    rouille::session::session(request, session::COOKIE, session::TIMEOUT, |session| {
+    // The session is only started (and its cookie set) by a page with a
+    // form, or by a flash message.
+    let sid = || if session.client_has_sid() { Some(session.id()) } else { None };
+    let posted;
+    let request = if request.method() == "POST" {
+        posted = match sessions.check(sid(), request) {
+            Ok(posted) => posted,
+            Err(r) => return r,
+        };
+        &posted
+    } else {
+        request
+    };
+    let csrf = || sessions.token(session.id());
+    let html = |page: String| sessions.page(sid(), config.decorate(page));
+    let flash = |message: String| sessions.flash(session.id(), message);
+    router!{
+        request,
+        (GET) (/) => {
+            rouille::Response::redirect_303(format!("{}/{}/", base, registry::new_course_path()))
+        },
+        (GET) (/admin/) => {
+            if let Err(r) = instructor(config, request) {
+                return r;
+            }
+            html(Dashboard::new(config, &csrf()).render().unwrap())
+        },
+        (POST) (/admin/) => {
+            let user = match instructor(config, request) {
+                Ok(user) => user,
+                Err(r) => return r,
+            };
+            match post_input!(request, {
+                action: String,
+                course: String,
+                title: String,
+                term: String,
+                roster: bool,
+            }) {
+                Ok(input) => {
+                    if input.action == "Create" {
+                        let path = registry::new_course_path();
+                        let mut data = database::Data::new(&config.data_dir, &path);
+                        let info = CourseInfo::new(&input.title, &input.term, &user);
+                        flash(format!("Created {}", info.pretty()));
+                        data.set_info(info);
+                        data.save();
+                    } else if let Ok(mut data) = registry::open_course(&config.data_dir,
+                                                                       &input.course) {
+                        if input.action == "Clone" {
+                            let info = CourseInfo::new(&input.title, &input.term, &user);
+                            flash(format!("Cloned {} as {}", data.info().pretty(),
+                                          info.pretty()));
+                            registry::clone_course(&config.data_dir, &data, info,
+                                                   input.roster);
+                        } else {
+                            let mut info = data.info().clone();
+                            info.archived = input.action == "Archive";
+                            flash(format!("{} {}", if info.archived { "Archived" }
+                                          else { "Restored" }, info.pretty()));
+                            data.set_info(info);
+                            data.save();
+                        }
+                    } else {
+                        flash(format!("No such course {:?}", input.course));
+                    }
+                }
+                Err(e) => {
+                    return Response::text(format!("Post admin error: {:?}\n\n{:?}",
+                                                  request, e));
+                }
+            }
+            Response::redirect_303(format!("{}/admin/", base))
+        },
+        (GET) (/{path: String}/) => {
+            let data = database::Data::new(&config.data_dir, &path);
+            let page = Index::new(&data, &path, base, &csrf());
+            html(page.render().unwrap())
+        },
+        (POST) (/{path: String}/) => {
+            let mut data = database::Data::new(&config.data_dir, &path);
+            match post_input!(request, {
+                id: usize,
+                name: String,
+            }) {
+                Ok(input) => {
+                    if input.id == data.list_days().len() {
+                        data.add_day();
+                        flash(format!("Added day {}", input.id));
+                    }
+                    if data.existing_day(Day::from(input.id)).is_none() {
+                        flash(format!("There is no day {}", input.id));
+                    } else if input.name != "" {
+                        flash(format!("Named day {} {:?}", input.id, input.name));
+                        data.name_day(input.id, input.name);
+                    } else {
+                        // By process of elimination, the check
+                        // button must have been hit.
+                        let day = data.improve_day(Day::from(input.id));
+                        data.toggle_lock_day(day);
+                        flash(format!("{} {}", if day.unlocked { "Locked" }
+                                      else { "Unlocked" }, day.pretty()));
+                    }
+                }
+                Err(e) => {
+                    return Response::text(format!("Post / error: {:?}\n\n{:?}",
+                                                  request, e));
+                }
+            }
+            data.save();
+            Response::redirect_303(format!("{}/{}/", base, path))
+        },
+        (POST) (/{path: String}/rotation) => {
+            let mut data = database::Data::new(&config.data_dir, &path);
+            match post_input!(request, {
+                rotation: String,
+            }) {
+                Ok(input) => {
+                    let blocks: Result<Vec<usize>, _> = input.rotation
+                        .split(|c: char| c == ',' || c.is_whitespace())
+                        .filter(|w| !w.is_empty())
+                        .map(|w| w.parse()).collect();
+                    match blocks {
+                        Ok(blocks) => {
+                            data.set_rotation(blocks);
+                            let blocks: Vec<_> = data.rotation().iter()
+                                .map(|n| n.to_string()).collect();
+                            flash(if blocks.is_empty() {
+                                "Cleared the rotation".to_string()
+                            } else {
+                                format!("Set the rotation to {}", blocks.join(", "))
+                            });
+                        }
+                        Err(_) => flash(format!("Invalid rotation {:?}", input.rotation)),
+                    }
+                }
+                Err(e) => {
+                    return Response::text(format!("Post rotation error: {:?}\n\n{:?}",
+                                                  request, e));
+                }
+            }
+            data.save();
+            Response::redirect_303(format!("{}/{}/", base, path))
+        },
+        (POST) (/{path: String}/plan) => {
+            let mut data = database::Data::new(&config.data_dir, &path);
+            match post_input!(request, {
+                days: usize,
+            }) {
+                Ok(input) => {
+                    let count = input.days.clamp(1, database::MAX_PLANNED_DAYS);
+                    let days = data.plan_days(count);
+                    flash(match (days.first(), days.last()) {
+                        (Some(first), Some(last)) => {
+                            format!("Planned {} to {}", first.pretty(), last.pretty())
                         }
-                        if input.name != "" {
-                            data.name_day(input.id, input.name);
+                        _ => "Planned no days".to_string(),
+                    });
+                }
+                Err(e) => {
+                    return Response::text(format!("Post plan error: {:?}\n\n{:?}",
+                                                  request, e));
+                }
+            }
+            data.save();
+            Response::redirect_303(format!("{}/{}/", base, path))
+        },
+        (GET) (/{path: String}/day/{today: Day}) => {
+            let data = database::Data::new(&config.data_dir, &path);
+            let today = match data.existing_day(today) {
+                Some(today) => today,
+                None => return Response::empty_404(),
+            };
+            let page = EditDay::new(&data, &path, base, &csrf(), today);
+            html(page.render().unwrap())
+        },
+        (POST) (/{path: String}/day/{today: Day}) => {
+            let mut data = database::Data::new(&config.data_dir, &path);
+            let today = match data.existing_day(today) {
+                Some(today) => today,
+                None => return Response::empty_404(),
+            };
+            if !data.day_unlocked(today) {
+                return Response::text(format!("Cannot modify locked day: {}",
+                                              today.pretty()));
+            }
+            match post_input!(request, {
+                team: String,
+                section: String,
+                student: String,
+                action: String,
+            }) {
+                Ok(input) => {
+                    let section = Section::from(input.section);
+                    if input.action == "student" {
+                        flash(if section.name.is_empty() {
+                            format!("Marked {} absent", input.student)
+                        } else if input.team.is_empty() {
+                            format!("Moved {} to section {}", input.student, section)
                         } else {
-                            // By process of elimination, the check
-                            // button must have been hit.
-                            data.toggle_lock_day(Day::from(input.id));
+                            format!("Put {} on {} in section {}", input.student,
+                                    input.team, section)
+                        });
+                        data.assign_student(today,
+                                            Student::from(input.student),
+                                            section,
+                                            Team::from(input.team));
+                    } else if let Ok(mode) = input.action.parse::<ShuffleMode>() {
+                        let problems = data.run_shuffle(today, mode, Some(section));
+                        flash(shuffled(&data, today, mode, Some(section)));
+                        for p in problems {
+                            flash(format!("Warning: {}", p));
                         }
+                    } else {
+                        println!("What do I do with action {:?}?", input.action);
                     }
-                    Err(e) => {
-                        return Response::text(format!("Post / error: {:?}\n\n{:?}",
-                                                      request, e));
+                }
+                Err(e) => {
+                    return Response::text(format!("Post students error: {:?}\n\n{:?}",
+                                                  request, e));
+                }
+            }
+            data.save();
+            if let Err(e) = provision_day(config, &data, today) {
+                flash(e);
+            }
+            Response::redirect_303(format!("{}/{}/day/{}", base, path, today.id))
+        },
+        (GET) (/{path: String}/preview/{today: Day}) => {
+            let data = database::Data::new(&config.data_dir, &path);
+            let today = match data.existing_day(today) {
+                Some(today) => today,
+                None => return Response::empty_404(),
+            };
+            let page = PreviewPage::new(&data, &path, base, &csrf(), today);
+            html(page.render().unwrap())
+        },
+        (POST) (/{path: String}/preview/{today: Day}) => {
+            let mut data = database::Data::new(&config.data_dir, &path);
+            let today = match data.existing_day(today) {
+                Some(today) => today,
+                None => return Response::empty_404(),
+            };
+            if !data.day_unlocked(today) {
+                return Response::text(format!("Cannot modify locked day: {}",
+                                              today.pretty()));
+            }
+            match post_input!(request, {
+                action: String,
+                mode: String,
+                section: String,
+                count: usize,
+                candidate: usize,
+            }) {
+                Ok(input) => {
+                    if input.action == "accept" {
+                        let label = data.preview(today)
+                            .and_then(|p| p.candidates.get(input.candidate))
+                            .map(|c| c.label());
+                        match data.accept_candidate(today, input.candidate) {
+                            Ok(()) => {
+                                flash(format!("Accepted {}: {}", label.unwrap_or_default(),
+                                              preview::summary(&data, today, None)));
+                                data.save();
+                                if let Err(e) = provision_day(config, &data, today) {
+                                    flash(e);
+                                }
+                                return Response::redirect_303(
+                                    format!("{}/{}/pairs/{}", base, path, today.id));
+                            }
+                            Err(e) => flash(e),
+                        }
+                    } else if input.action == "discard" {
+                        data.discard_preview(today);
+                        flash("Discarded the preview".to_string());
+                    } else {
+                        let mode = input.mode.parse::<ShuffleMode>().ok();
+                        let section = if input.section.is_empty() {
+                            None
+                        } else {
+                            Some(Section::from(input.section))
+                        };
+                        let count = input.count.clamp(1, 5);
+                        let candidates = preview::candidates(&data, today, mode,
+                                                             section, count);
+                        flash(format!("Made {} candidates", candidates.len()));
+                        data.set_preview(today, candidates);
                     }
                 }
-                let page = Index {
-                    path: path.to_string(),
-                    days: data.list_days(),
-                };
-                data.save();
-                Response::html(page.render().unwrap())
-            },
-            (GET) (/pairs/{path: String}/day/{today: Day}) => {
-                let data = database::Data::new(&path);
-                let today = data.improve_day(today);
-                let all: Vec<_> = data.student_options(today).into_iter()
-                    .flat_map(|(_,v)| v).collect();
-                let page = EditDay {
-                    path: path.to_string(),
-                    today: today,
-                    unassigned: data.unassigned_students(today),
-                    absent: data.absent_students(today),
-                    all,
-                };
-                Response::html(page.render().unwrap())
-            },
-            (POST) (/pairs/{path: String}/day/{today: Day}) => {
-                let mut data = database::Data::new(&path);
-                let today = data.improve_day(today);
-                if !data.day_unlocked(today) {
-                    return Response::text(format!("Cannot modify locked day: {}",
-                                                  today.pretty()));
+                Err(e) => {
+                    return Response::text(format!("Post preview error: {:?}\n\n{:?}",
+                                                  request, e));
                 }
+            }
+            data.save();
+            Response::redirect_303(format!("{}/{}/preview/{}", base, path, today.id))
+        },
+        (GET) (/{path: String}/pairs/{today: Day}) => {
+            let version = events::version(&path);
+            let data = database::Data::new(&config.data_dir, &path);
+            let today = match data.existing_day(today) {
+                Some(today) => today,
+                None => return Response::empty_404(),
+            };
+            let mut page = TeamView::new(&data, &path, base, &csrf(), today,
+                                         data.capacity_problems(today));
+            page.version = version;
+            html(page.render().unwrap())
+        },
+        (GET) (/{path: String}/board/{today: Day}) => {
+            let version = events::version(&path);
+            let data = database::Data::new(&config.data_dir, &path);
+            let today = match data.existing_day(today) {
+                Some(today) => today,
+                None => return Response::empty_404(),
+            };
+            let mut page = Board::new(&data, &path, base, &csrf(), today);
+            page.version = version;
+            html(page.render().unwrap())
+        },
+        (POST) (/{path: String}/board/{today: Day}) => {
+            // Posted by the board's script rather than a form, so it
+            // answers with JSON instead of redirecting.
+            let mut data = database::Data::new(&config.data_dir, &path);
+            let today = match data.existing_day(today) {
+                Some(today) => today,
+                None => return Response::empty_404(),
+            };
+            let result = if !data.day_unlocked(today) {
+                Err(format!("Cannot modify locked day: {}", today.pretty()))
+            } else {
                 match post_input!(request, {
-                    team: String,
-                    section: String,
                     student: String,
-                    action: String,
+                    section: String,
+                    team: String,
+                    pool: String,
                 }) {
                     Ok(input) => {
                         let section = Section::from(input.section);
-                        if input.action == "student" {
-                            println!("assigning {} to {:?} {:?}", input.student,
-                                     section, input.team);
+                        let destination = match input.pool.as_str() {
+                            "absent" => Destination::Absent,
+                            "unassigned" => Destination::Unassigned,
+                            _ if input.team.is_empty() => Destination::NewTeam(section),
+                            _ => Destination::Team(section, Team::from(input.team)),
+                        };
+                        data.move_student(today, Student::from(input.student), destination)
+                    }
+                    Err(e) => Err(format!("Post board error: {:?}", e)),
+                }
+            };
+            let result = result.and_then(|()| {
+                data.save();
+                provision_day(config, &data, today)
+            });
+            let mut state = BoardState::new(&data, today);
+            state.error = result.err();
+            Response::json(&state)
+        },
+        (GET) (/{path: String}/publish/{today: Day}) => {
+            let data = database::Data::new(&config.data_dir, &path);
+            let today = match data.existing_day(today) {
+                Some(today) => today,
+                None => return Response::empty_404(),
+            };
+            let page = PublishPage::new(&data, &path, base, &csrf(), today, &config.notify);
+            html(page.render().unwrap())
+        },
+        (POST) (/{path: String}/publish/{today: Day}) => {
+            let mut data = database::Data::new(&config.data_dir, &path);
+            let today = match data.existing_day(today) {
+                Some(today) => today,
+                None => return Response::empty_404(),
+            };
+            let again = match post_input!(request, {
+                again: bool,
+            }) {
+                Ok(input) => input.again,
+                Err(e) => {
+                    return Response::text(format!("Post publish error: {:?}\n\n{:?}",
+                                                  request, e));
+                }
+            };
+            match notify::publish_day(&config.notify, &mut data, today, again) {
+                Ok(queued) => flash(format!("Sending {} messages", queued)),
+                Err(e) => flash(e),
+            }
+            if let Err(e) = provision_day(config, &data, today) {
+                flash(e);
+            }
+            Response::redirect_303(format!("{}/{}/publish/{}", base, path, today.id))
+        },
+        (GET) (/{path: String}/print/{today: Day}) => {
+            let data = database::Data::new(&config.data_dir, &path);
+            let today = match data.existing_day(today) {
+                Some(today) => today,
+                None => return Response::empty_404(),
+            };
+            let page = PrintView {
+                path: path.to_string(),
+                base: base.to_string(),
+                sheet: sheet::day_sheet(&data, today),
+            };
+            html(page.render().unwrap())
+        },
+        (GET) (/{path: String}/projector/{today: Day}) => {
+            let version = events::version(&path);
+            let data = database::Data::new(&config.data_dir, &path);
+            let today = match data.existing_day(today) {
+                Some(today) => today,
+                None => return Response::empty_404(),
+            };
+            let page = ProjectorView {
+                path: path.to_string(),
+                base: base.to_string(),
+                sheet: sheet::day_sheet(&data, today),
+                version,
+            };
+            html(page.render().unwrap())
+        },
+        (GET) (/{path: String}/export/{today: Day}) => {
+            let data = database::Data::new(&config.data_dir, &path);
+            let today = match data.existing_day(today) {
+                Some(today) => today,
+                None => return Response::empty_404(),
+            };
+            let sheet = sheet::day_sheet(&data, today);
+            let (mime, extension, text) = match request.get_param("format").as_deref() {
+                Some("text") => ("text/plain; charset=utf-8", "txt", sheet::to_text(&sheet)),
+                _ => ("text/markdown; charset=utf-8", "md", sheet::to_markdown(&sheet)),
+            };
+            Response::from_data(mime, text)
+                .with_unique_header("Content-Disposition",
+                                    format!("attachment; filename=\"day-{}.{}\"",
+                                            today.id, extension))
+        },
+        (GET) (/{path: String}/grid/{today: Day}) => {
+            let data = database::Data::new(&config.data_dir, &path);
+            let today = match data.existing_day(today) {
+                Some(today) => today,
+                None => return Response::empty_404(),
+            };
+            let page = GridView {
+                path: path.to_string(),
+                base: base.to_string(),
+                today,
+                grids: grid::section_grids(&data, today),
+            };
+            html(page.render().unwrap())
+        },
+        (GET) (/{path: String}/statistics) => {
+            let data = database::Data::new(&config.data_dir, &path);
+            let sort = request.get_param("sort").unwrap_or_else(|| "student".to_string());
+            let mut students = statistics::student_stats(&data);
+            statistics::sort_students(&mut students, &sort);
+            let page = Statistics {
+                path: path.to_string(),
+                base: base.to_string(),
+                sections: statistics::section_stats(&data, &students),
+                students,
+                columns: statistics::COLUMNS.iter().map(|c| c.to_string()).collect(),
+                sort,
+            };
+            html(page.render().unwrap())
+        },
+        (GET) (/{path: String}/statistics/csv) => {
+            let data = database::Data::new(&config.data_dir, &path);
+            let students = statistics::student_stats(&data);
+            Response::from_data("text/csv", statistics::to_csv(&students))
+                .with_unique_header("Content-Disposition",
+                                    "attachment; filename=\"statistics.csv\"")
+        },
+        (GET) (/{path: String}/sections/{today: Day}) => {
+            let version = events::version(&path);
+            let data = database::Data::new(&config.data_dir, &path);
+            let today = match data.existing_day(today) {
+                Some(today) => today,
+                None => return Response::empty_404(),
+            };
+            let mut page = SectionView::new(&data, &path, base, today);
+            page.version = version;
+            html(page.render().unwrap())
+        },
+        (GET) (/{path: String}/changes) => {
+            // Long-polled by pages that update themselves.
+            let since = request.get_param("since").and_then(|v| v.parse().ok())
+                .unwrap_or(0);
+            let version = events::wait(&path, since, ::std::time::Duration::from_secs(25));
+            Response::text(version.to_string())
+                .with_unique_header("Cache-Control", "no-store")
+        },
+        (POST) (/{path: String}/pairs/{today: Day}) => {
+            let mut data = database::Data::new(&config.data_dir, &path);
+            let today = match data.existing_day(today) {
+                Some(today) => today,
+                None => return Response::empty_404(),
+            };
+            if !data.day_unlocked(today) {
+                return Response::text(format!("Cannot modify locked day: {}",
+                                              today.pretty()));
+            }
+            match post_input!(request, {
+                team: String,
+                section: String,
+                primary: String,
+                secondary: String,
+                action: String,
+            }) {
+                Ok(input) => {
+                    let section = Section::from(input.section);
+                    if input.action == "team" {
+                        let team = Team::from(input.team);
+                        data.unpair_team(today, team);
+                        if input.primary != "" {
                             data.assign_student(today,
-                                                Student::from(input.student),
-                                                section,
-                                                Team::from(input.team));
-                        } else if input.action == "Shuffle" {
-                            println!("Shuffling {}...", section);
-                            data.shuffle(today, section);
-                        } else if input.action == "Shuffle with continuity" {
-                            println!("Shuffling with continuity {}...", section);
-                            data.shuffle_with_continuity(today, section);
-                        } else if input.action == "Repeat" {
-                            println!("Repeating {}...", section);
-                            data.repeat(today, section);
-                        } else if input.action == "Clear all" {
-                            println!("Clearing all...");
-                            for s in data.students_present_in_section(today, section) {
-                                data.unpair_student(today, s);
-                            }
-                        } else if input.action == "Grand shuffle" {
-                            println!("Grand shuffle...");
-                            data.grand_shuffle(today);
-                        } else if input.action == "Grand shuffle with continuity" {
-                            println!("Grand shuffle with continuity...");
-                            data.grand_shuffle_with_continuity(today);
+                                                Student::from(input.primary),
+                                                section, team);
+                        }
+                        if input.secondary != "" {
+                            data.assign_student(today,
+                                                Student::from(input.secondary),
+                                                section, team);
+                        }
+                        flash(format!("Updated {}", team));
+                    } else if input.action == "pin" {
+                        let team = Team::from(input.team);
+                        data.toggle_pin_team(today, team);
+                        let pinned = data.pins(today).teams.contains(&team);
+                        flash(format!("{} {}", if pinned { "Pinned" } else { "Unpinned" },
+                                      team));
+                    } else if input.action == "pin-student" {
+                        let student = Student::from(input.primary);
+                        data.toggle_pin_student(today, student);
+                        let pinned = data.pins(today).students.contains(&student);
+                        flash(format!("{} {}", if pinned { "Pinned" } else { "Unpinned" },
+                                      student));
+                    } else if let Ok(mode) = input.action.parse::<ShuffleMode>() {
+                        let problems = data.run_shuffle(today, mode, Some(section));
+                        flash(shuffled(&data, today, mode, Some(section)));
+                        // Capacity problems are shown on the page anyway.
+                        let shown = data.capacity_problems(today);
+                        for p in problems.into_iter().filter(|p| !shown.contains(p)) {
+                            flash(format!("Warning: {}", p));
+                        }
+                    } else {
+                        println!("What do I do with foolish action {:?}?", input.action);
+                    }
+                }
+                Err(e) => {
+                    return Response::text(format!("Post students error: {:?}\n\n{:?}",
+                                                  request, e));
+                }
+            }
+            data.save();
+            if let Err(e) = provision_day(config, &data, today) {
+                flash(e);
+            }
+            Response::redirect_303(format!("{}/{}/pairs/{}", base, path, today.id))
+        },
+        (GET) (/{path: String}/students) => {
+            let data = database::Data::new(&config.data_dir, &path);
+            let focus_section = request.get_param("section").unwrap_or_default();
+            let page = Students::new(&data, &path, base, &csrf(), Section::from(focus_section));
+            html(page.render().unwrap())
+        },
+        (POST) (/{path: String}/students) => {
+            let mut data = database::Data::new(&config.data_dir, &path);
+            let focus_section;
+            match post_input!(request, {
+                section: String,
+                oldname: String,
+                newname: String,
+                tags: String,
+                email: String,
+            }) {
+                Ok(input) => {
+                    focus_section = Section::from(input.section.clone());
+                    let tags = input.tags.split(',').map(|t| t.to_string());
+                    if input.oldname == "" {
+                        let student = Student::from(input.newname);
+                        flash(format!("Added {} to section {}", student, input.section));
+                        data.new_student(student, Section::from(input.section));
+                        data.set_tags(student, tags);
+                        if let Err(e) = data.set_email(student, &input.email) {
+                            flash(e);
+                        }
+                    } else if input.newname == "" {
+                        flash(format!("Deleted {}", input.oldname));
+                        data.delete_student(Student::from(input.oldname));
+                    } else {
+                        let student = Student::from(input.newname);
+                        let message = if input.oldname == student.name.as_str() {
+                            format!("Updated {}", student)
                         } else {
-                            println!("What do I do with action {:?}?", input.action);
+                            format!("Renamed {} to {}", input.oldname, student)
+                        };
+                        if let Err(e) = data.rename_student(Student::from(input.oldname),
+                                                            student,
+                                                            Section::from(input.section)) {
+                            flash(e);
+                            return Response::redirect_303(
+                                format!("{}/{}/students?section={}", base, path,
+                                        links::url_encode(&focus_section.name)));
+                        }
+                        flash(message);
+                        data.set_tags(student, tags);
+                        if let Err(e) = data.set_email(student, &input.email) {
+                            flash(e);
                         }
                     }
-                    Err(e) => {
-                        return Response::text(format!("Post students error: {:?}\n\n{:?}",
-                                                      request, e));
+                }
+                Err(e) => {
+                    return Response::text(format!("Post students error: {:?}\n\n{:?}",
+                                                  request, e));
+                }
+            }
+            data.save();
+            Response::redirect_303(format!("{}/{}/students?section={}", base, path,
+                                           links::url_encode(&focus_section.name)))
+        },
+        (POST) (/{path: String}/attributes) => {
+            let mut data = database::Data::new(&config.data_dir, &path);
+            match post_input!(request, {
+                csv: String,
+                balance: String,
+                action: String,
+            }) {
+                Ok(input) => {
+                    if input.action == "import" {
+                        match attributes::import_csv(&mut data, &input.csv) {
+                            Ok(n) => flash(format!("Imported attributes of {} students", n)),
+                            Err(e) => flash(e),
+                        }
+                    } else {
+                        data.set_balance_attribute(Some(input.balance));
+                        flash(match data.balance_attribute() {
+                            Some(a) => format!("Balancing and grouping by {}", a),
+                            None => "Not balancing or grouping".to_string(),
+                        });
                     }
                 }
-                let all: Vec<_> = data.student_options(today).into_iter()
-                    .flat_map(|(_,v)| v).collect();
-                let page = EditDay {
-                    path: path.to_string(),
-                    today: today,
-                    unassigned: data.unassigned_students(today),
-                    absent: data.absent_students(today),
-                    all,
-                };
-                data.save();
-                Response::html(page.render().unwrap())
-            },
-            (GET) (/pairs/{path: String}/pairs/{today: Day}) => {
-                let data = database::Data::new(&path);
-                let today = data.improve_day(today);
-                let page = TeamView {
-                    path: path.to_string(),
-                    today: today,
-                    unassigned: data.unassigned_students(today),
-                    absent: data.absent_students(today),
-                    all: data.team_options(today),
-                };
-                Response::html(page.render().unwrap())
-            },
-            (GET) (/pairs/{path: String}/sections/{today: Day}) => {
-                let data = database::Data::new(&path);
-                let today = data.improve_day(today);
-                let mut unassigned = data.unassigned_students(today);
-                let absent = data.absent_students(today);
-                unassigned.retain(|s| !absent.contains(s));
-                let zooms = data.get_zooms();
-                let page = SectionView {
-                    path: path.to_string(),
-                    today: today,
-                    unassigned,
-                    absent,
-                    all: data.team_options(today).into_iter()
-                        .map(|(sec,stu)| (sec, stu, zooms[&sec]))
-                        .collect(),
-                };
-                Response::html(page.render().unwrap())
-            },
-            (POST) (/pairs/{path: String}/pairs/{today: Day}) => {
-                let mut data = database::Data::new(&path);
-                let today = data.improve_day(today);
-                if !data.day_unlocked(today) {
-                    return Response::text(format!("Cannot modify locked day: {}",
-                                                  today.pretty()));
+                Err(e) => {
+                    return Response::text(format!("Post attributes error: {:?}\n\n{:?}",
+                                                  request, e));
                 }
-                match post_input!(request, {
-                    team: String,
-                    section: String,
-                    primary: String,
-                    secondary: String,
-                    action: String,
-                }) {
-                    Ok(input) => {
-                        let section = Section::from(input.section);
-                        if input.action == "team" {
-                            let team = Team::from(input.team);
-                            data.unpair_team(today, team);
-                            if input.primary != "" {
-                                data.assign_student(today,
-                                                    Student::from(input.primary),
-                                                    section, team);
+            }
+            data.save();
+            Response::redirect_303(format!("{}/{}/students", base, path))
+        },
+        (POST) (/{path: String}/active) => {
+            let mut data = database::Data::new(&config.data_dir, &path);
+            match post_input!(request, {
+                student: String,
+                active: String,
+            }) {
+                Ok(input) => {
+                    let student = Student::from(input.student);
+                    let active = !input.active.is_empty();
+                    data.set_active(student, active);
+                    flash(if active {
+                        format!("Reactivated {}", student)
+                    } else {
+                        format!("Marked {} as dropped", student)
+                    });
+                }
+                Err(e) => {
+                    return Response::text(format!("Post active error: {:?}\n\n{:?}",
+                                                  request, e));
+                }
+            }
+            data.save();
+            Response::redirect_303(format!("{}/{}/students", base, path))
+        },
+        (POST) (/{path: String}/roster) => {
+            let mut data = database::Data::new(&config.data_dir, &path);
+            match post_input!(request, {
+                csv: String,
+                kind: String,
+                location: String,
+                section: String,
+                token: String,
+                action: String,
+            }) {
+                Ok(input) => {
+                    if input.action == "source" {
+                        let current = data.roster_source().cloned();
+                        let mut token = input.token;
+                        if let Some(roster::RosterSource::Lti { token: ref old, .. }) = current {
+                            if token.is_empty() {
+                                token = old.clone();
+                            }
+                        }
+                        match roster::RosterSource::new(&input.kind, &input.location,
+                                                        &input.section, &token) {
+                            // A file on the server may only be chosen by its
+                            // administrator, from the command line.
+                            Ok(Some(ref source)) if source.kind() == "csv"
+                                && current.as_ref() != Some(source) => {
+                                flash("A csv file can only be set from the command line"
+                                      .to_string());
                             }
-                            if input.secondary != "" {
-                                data.assign_student(today,
-                                                    Student::from(input.secondary),
-                                                    section, team);
+                            // Nor may a course point the server at any
+                            // host it has not been allowed to contact.
+                            Ok(Some(ref source)) if current.as_ref().map(|c| c.location())
+                                != Some(source.location()) => {
+                                match config.check_outbound(&source.location()) {
+                                    Ok(()) => {
+                                        flash(format!("Syncing the roster from {}", source));
+                                        data.set_roster_source(Some(source.clone()));
+                                    }
+                                    Err(e) => flash(e),
+                                }
                             }
-                        } else if input.action == "Shuffle" {
-                            println!("Shuffling {}...", section);
-                            data.shuffle(today, section);
-                        } else if input.action == "Shuffle with continuity" {
-                            println!("Shuffling with continuity {}...", section);
-                            data.shuffle_with_continuity(today, section);
-                        } else if input.action == "Repeat" {
-                            println!("Repeating {}...", section);
-                            data.repeat(today, section);
-                        } else if input.action == "Clear all" {
-                            println!("I should be clearing all...");
-                            for s in data.students_present_in_section(today, section) {
-                                data.unpair_student(today, s);
+                            Ok(source) => {
+                                flash(match source {
+                                    Some(ref source) => format!("Syncing the roster from {}",
+                                                                source),
+                                    None => "Not syncing the roster".to_string(),
+                                });
+                                data.set_roster_source(source);
                             }
-                        } else if input.action == "Grand shuffle" {
-                            println!("I should be doing grand shuffle...");
-                            data.grand_shuffle(today);
-                        } else if input.action == "Grand shuffle with continuity" {
-                            println!("I should be doing grand shuffle with continuity...");
-                            data.grand_shuffle_with_continuity(today);
+                            Err(e) => flash(e),
+                        }
+                    } else {
+                        let dry_run = input.action == "preview";
+                        let result = if input.csv.trim() != "" {
+                            roster::sync(&mut data, &roster::CsvText(input.csv),
+                                         "pasted csv", dry_run)
+                        } else if let Some(source) = data.roster_source().cloned() {
+                            roster::sync(&mut data, &*source.provider(),
+                                         &source.to_string(), dry_run)
                         } else {
-                            println!("What do I do with foolish action {:?}?", input.action);
+                            Err("There is no roster to sync from".to_string())
+                        };
+                        match result {
+                            Ok(ref sync) if dry_run => {
+                                flash(format!("A sync would have {}", sync.summary()))
+                            }
+                            Ok(sync) => {
+                                flash(format!("Synced the roster: {}", sync.summary()));
+                                for w in sync.warnings {
+                                    flash(w);
+                                }
+                            }
+                            Err(e) => flash(e),
                         }
                     }
-                    Err(e) => {
-                        return Response::text(format!("Post students error: {:?}\n\n{:?}",
-                                                      request, e));
-                    }
                 }
-                let page = TeamView {
-                    path: path.to_string(),
-                    today: today,
-                    unassigned: data.unassigned_students(today),
-                    absent: data.absent_students(today),
-                    all: data.team_options(today),
-                };
-                data.save();
-                Response::html(page.render().unwrap())
-            },
-            (GET) (/pairs/{path: String}/students) => {
-                let data = database::Data::new(&path);
-                let page = Students {
-                    path: path.to_string(),
-                    sections: data.list_students_by_section(),
-                    focus_section: Section::from("".to_string()),
-                };
-                Response::html(page.render().unwrap())
-            },
-            (POST) (/pairs/{path: String}/students) => {
-                let mut data = database::Data::new(&path);
-                let focus_section;
-                match post_input!(request, {
-                    section: String,
-                    oldname: String,
-                    newname: String,
-                }) {
-                    Ok(input) => {
-                        focus_section = Section::from(input.section.clone());
-                        if input.oldname == "" {
-                            data.new_student(Student::from(input.newname),
-                                             Section::from(input.section));
-                        } else if input.newname == "" {
-                            data.delete_student(Student::from(input.oldname));
+                Err(e) => {
+                    return Response::text(format!("Post roster error: {:?}\n\n{:?}",
+                                                  request, e));
+                }
+            }
+            data.save();
+            Response::redirect_303(format!("{}/{}/students", base, path))
+        },
+        (GET) (/{path: String}/sections) => {
+            let data = database::Data::new(&config.data_dir, &path);
+            let page = Sections::new(&data, &path, base, &csrf());
+            html(page.render().unwrap())
+        },
+        (POST) (/{path: String}/sections) => {
+            let mut data = database::Data::new(&config.data_dir, &path);
+            match post_input!(request, {
+                oldname: String,
+                newname: String,
+                newzoom: String,
+                objective: String,
+                capacity: String,
+                preferred: String,
+            }) {
+                Ok(input) => {
+                    println!("posted to sections...");
+                    let zoom = Zoom::from(input.newzoom);
+                    let objective = input.objective.parse().unwrap_or_default();
+                    let capacity = input.capacity.trim().parse().ok();
+                    let preferred: Vec<_> = input.preferred.split(',')
+                        .map(|s| s.trim()).filter(|s| !s.is_empty())
+                        .map(|s| Student::from(s.to_string())).collect();
+                    if let Some(s) = preferred.iter()
+                        .find(|&&s| data.student_section(s).is_none())
+                    {
+                        flash(format!("No such student {:?}", s.name));
+                        return Response::redirect_303(format!("{}/{}/sections",
+                                                              base, path));
+                    }
+                    let section = Section::from(input.newname.clone());
+                    if input.oldname == "" {
+                        flash(format!("Added section {}", section));
+                        data.new_section(section, zoom);
+                    } else if input.newname == "" {
+                        flash(format!("Deleted section {}", input.oldname));
+                        data.delete_section(Section::from(input.oldname));
+                    } else {
+                        let message = if input.oldname == input.newname {
+                            format!("Updated section {}", section)
                         } else {
-                            data.rename_student(Student::from(input.oldname),
-                                                Student::from(input.newname),
-                                                Section::from(input.section));
+                            format!("Renamed section {} to {}", input.oldname, section)
+                        };
+                        if let Err(e) = data.rename_section(Section::from(input.oldname),
+                                                            section, zoom) {
+                            flash(e);
+                            return Response::redirect_303(format!("{}/{}/sections",
+                                                                  base, path));
                         }
+                        flash(message);
                     }
-                    Err(e) => {
-                        return Response::text(format!("Post students error: {:?}\n\n{:?}",
-                                                      request, e));
+                    if !input.newname.is_empty() {
+                        data.set_tag_objective(section, objective);
+                        data.set_capacity(section, capacity);
+                        data.set_preferred_students(section, preferred);
                     }
                 }
-                let page = Students {
-                    path: path.to_string(),
-                    sections: data.list_students_by_section(),
-                    focus_section: focus_section,
-                };
-                data.save();
-                Response::html(page.render().unwrap())
-            },
-            (GET) (/pairs/{path: String}/sections) => {
-                let data = database::Data::new(&path);
-                let page = Sections {
-                    path: path.to_string(),
-                    sections: data.zoom_sections(),
-                };
-                Response::html(page.render().unwrap())
-            },
-            (POST) (/pairs/{path: String}/sections) => {
-                let mut data = database::Data::new(&path);
-                match post_input!(request, {
-                    oldname: String,
-                    newname: String,
-                    newzoom: String,
-                }) {
-                    Ok(input) => {
-                        println!("posted to sections...");
-                        let zoom = Zoom::from(input.newzoom);
-                        if input.oldname == "" {
-                            data.new_section(Section::from(input.newname), zoom);
-                        } else if input.newname == "" {
-                            data.delete_section(Section::from(input.oldname));
-                        } else {
-                            println!("renaming... {} {} {:?}",
-                                     input.oldname, input.newname, zoom);
-                            data.rename_section(Section::from(input.oldname),
-                                                Section::from(input.newname),
-                                                zoom);
+                Err(e) => {
+                    return Response::text(format!("Post sections error: {:?}\n\n{:?}",
+                                                  request, e));
+                }
+            }
+            data.save();
+            Response::redirect_303(format!("{}/{}/sections", base, path))
+        },
+        (POST) (/{path: String}/links) => {
+            let mut data = database::Data::new(&config.data_dir, &path);
+            match post_input!(request, {
+                meeting: String,
+                team: String,
+            }) {
+                Ok(input) => {
+                    let mut links = data.links().clone();
+                    match links.set_meeting(&input.meeting)
+                        .and_then(|()| links.set_team_links(&input.team))
+                    {
+                        Ok(()) => {
+                            data.set_links(links);
+                            flash("Updated the links".to_string());
                         }
+                        Err(e) => flash(e),
                     }
-                    Err(e) => {
-                        return Response::text(format!("Post sections error: {:?}\n\n{:?}",
-                                                      request, e));
+                }
+                Err(e) => {
+                    return Response::text(format!("Post links error: {:?}\n\n{:?}",
+                                                  request, e));
+                }
+            }
+            data.save();
+            Response::redirect_303(format!("{}/{}/sections", base, path))
+        },
+        (POST) (/{path: String}/webhooks) => {
+            let mut data = database::Data::new(&config.data_dir, &path);
+            match post_input!(request, {
+                webhooks: String,
+            }) {
+                Ok(input) => {
+                    let outbound = input.webhooks.lines().map(|l| l.trim())
+                        .filter(|l| !l.is_empty() && !data.webhooks().iter().any(|w| w == l))
+                        .try_for_each(|url| config.check_outbound(url));
+                    match outbound.and_then(|()| data.set_webhooks(&input.webhooks)) {
+                        Ok(()) => flash("Updated the webhooks".to_string()),
+                        Err(e) => flash(e),
                     }
                 }
-                let page = Sections {
-                    path: path.to_string(),
-                    sections: data.zoom_sections(),
-                };
-                data.save();
-                Response::html(page.render().unwrap())
-            },
-            (GET) (/pairs/{path: String}/teams) => {
-                let data = database::Data::new(&path);
-                let page = Teams {
-                    path: path.to_string(),
-                    teams: data.list_teams(),
-                };
-                Response::html(page.render().unwrap())
-            },
-            (POST) (/pairs/{path: String}/teams) => {
-                let mut data = database::Data::new(&path);
-                match post_input!(request, {
-                    oldname: String,
-                    newname: String,
-                }) {
-                    Ok(input) => {
-                        if input.oldname == "" {
-                            data.new_team(Team::from(input.newname));
-                        } else if input.newname == "" {
-                            data.delete_team(Team::from(input.oldname));
-                        } else {
-                            data.rename_team(Team::from(input.oldname),
-                                                Team::from(input.newname));
+                Err(e) => {
+                    return Response::text(format!("Post webhooks error: {:?}\n\n{:?}",
+                                                  request, e));
+                }
+            }
+            data.save();
+            Response::redirect_303(format!("{}/{}/sections", base, path))
+        },
+        (GET) (/{path: String}/teams) => {
+            let data = database::Data::new(&config.data_dir, &path);
+            let page = Teams {
+                path: path.to_string(),
+                base: base.to_string(),
+                csrf: csrf(),
+                teams: data.list_teams(),
+            };
+            html(page.render().unwrap())
+        },
+        (POST) (/{path: String}/teams) => {
+            let mut data = database::Data::new(&config.data_dir, &path);
+            let provision = match post_input!(request, {
+                oldname: String,
+                newname: String,
+            }) {
+                Ok(input) => {
+                    if input.oldname == "" {
+                        flash(format!("Added {}", input.newname));
+                        let team = Team::from(input.newname);
+                        data.new_team(team);
+                        Some(hooks::created(&data, team))
+                    } else if input.newname == "" {
+                        flash(format!("Deleted {}", input.oldname));
+                        data.delete_team(Team::from(input.oldname));
+                        None
+                    } else {
+                        let message = format!("Renamed {} to {}", input.oldname, input.newname);
+                        let (old, team) = (Team::from(input.oldname),
+                                           Team::from(input.newname));
+                        match data.rename_team(old, team) {
+                            Ok(()) => {
+                                flash(message);
+                                Some(hooks::renamed(&data, old, team))
+                            }
+                            Err(e) => {
+                                flash(e);
+                                None
+                            }
                         }
                     }
-                    Err(e) => {
-                        return Response::text(format!("Post teams error: {:?}\n\n{:?}",
-                                                      request, e));
-                    }
                 }
-                let page = Teams {
-                    path: path.to_string(),
-                    teams: data.list_teams(),
-                };
-                data.save();
-                Response::html(page.render().unwrap())
-            },
-            _ => {
-                Response::html(format!("Error: {:?}", request))
-            },
+                Err(e) => {
+                    return Response::text(format!("Post teams error: {:?}\n\n{:?}",
+                                                  request, e));
+                }
+            };
+            data.save();
+            if let (Some(hook), Some(event)) = (config.provision.as_ref(), provision) {
+                if let Err(e) = hooks::queue(hook, &data, vec![event]) {
+                    flash(format!("Unable to provision the team: {}", e));
+                }
+            }
+            Response::redirect_303(format!("{}/{}/teams", base, path))
+        },
+        _ => {
+            Response::html(format!("Error: {:?}", request))
+        },
+    }
+    })
+}
+
+#[test]
+fn test_hostile_names() {
+    let hostile = "<script>alert('x')</script>&";
+    let mut data = database::Data::empty("testcourse", "testcourse.yaml");
+    let section = Section::from(format!("{}sec", hostile));
+    data.new_section(section, Zoom::from("javascript:alert(1)".to_string()));
+    for i in 0..3 {
+        data.new_student(Student::from(format!("{}{}", hostile, i)), section);
+    }
+    data.new_team(Team::from(format!("{}team", hostile)));
+    data.new_team(Team::from("plain".to_string()));
+    data.add_day();
+    data.name_day(0, hostile.to_string());
+    let today = data.improve_day(Day::from(0));
+    data.toggle_lock_day(today);
+    let today = data.improve_day(today);
+    data.run_shuffle(today, ShuffleMode::Shuffle, None);
+    data.set_preview(today, preview::candidates(&data, today, None, None, 1));
+
+    let (path, base) = ("testcourse", "/pairs");
+    let pages = vec![
+        Index::new(&data, path, base, "").render().unwrap(),
+        EditDay::new(&data, path, base, "", today).render().unwrap(),
+        TeamView::new(&data, path, base, "", today, data.capacity_problems(today)).render().unwrap(),
+        SectionView::new(&data, path, base, today).render().unwrap(),
+        GridView {
+            today,
+            grids: grid::section_grids(&data, today),
+            path: path.to_string(),
+            base: base.to_string(),
+        }.render().unwrap(),
+        PreviewPage::new(&data, path, base, "", today).render().unwrap(),
+        Board::new(&data, path, base, "", today).render().unwrap(),
+        PublishPage::new(&data, path, base, "", today, &[]).render().unwrap(),
+        PrintView {
+            sheet: sheet::day_sheet(&data, today),
+            path: path.to_string(),
+            base: base.to_string(),
+        }.render().unwrap(),
+        ProjectorView {
+            sheet: sheet::day_sheet(&data, today),
+            version: 0,
+            path: path.to_string(),
+            base: base.to_string(),
+        }.render().unwrap(),
+        Students::new(&data, path, base, "", section).render().unwrap(),
+        Sections::new(&data, path, base, "").render().unwrap(),
+        Teams { teams: data.list_teams(), path: path.to_string(), base: base.to_string(),
+                csrf: String::new() }
+            .render().unwrap(),
+    ];
+    for page in pages {
+        assert!(!page.contains("<script>alert"), "unescaped name in\n{}", page);
+        assert!(!page.contains("href=\"javascript"), "unsafe link in\n{}", page);
+        assert!(page.contains("&lt;script&gt;"), "missing name in\n{}", page);
+        // Escaped exactly once, so that forms post back the real name.
+        assert!(!page.contains("&amp;lt;"), "doubly escaped name in\n{}", page);
+    }
+}
+
+/// The only values rendered with `|safe` must be templates themselves,
+/// which escape their own contents.  Anything else needs auditing.
+#[test]
+fn test_safe_only_on_fragments() {
+    const FRAGMENTS: &[&str] = &["today", "t", "s", "primary", "secondary"];
+    for entry in ::std::fs::read_dir("templates").unwrap() {
+        let file = entry.unwrap().path();
+        let text = ::std::fs::read_to_string(&file).unwrap();
+        for expr in text.split("{{").skip(1).filter_map(|e| e.split("}}").next()) {
+            let mut parts = expr.split('|').map(|p| p.trim());
+            let value = parts.next().unwrap();
+            if parts.any(|f| f == "safe") {
+                assert!(FRAGMENTS.contains(&value),
+                        "{}: {:?} is not known to be escaped", file.display(), value);
+            }
         }
-    });
+    }
+}
+
+#[test]
+fn test_missing_day() {
+    let dir = ::tempfile::TempDir::new().unwrap();
+    let config = Config { data_dir: dir.path().to_path_buf(), ..Config::default() };
+    let sessions = session::Sessions::default();
+    let path = "abcdefghijklmnop";
+    let mut data = database::Data::empty(path, dir.path().join(format!("{}.yaml", path)));
+    for _ in 0..3 {
+        data.add_day();
+    }
+    data.save();
+    let get = |url: String| {
+        let request = Request::fake_http("GET", format!("/pairs/{}/{}", path, url),
+                                         vec![], vec![]);
+        handle(&config, &sessions, &request).status_code
+    };
+    for route in ["day", "preview", "pairs", "board", "publish", "print", "projector",
+                  "export", "grid", "sections"].iter() {
+        assert_eq!(get(format!("{}/2", route)), 200, "{} of an existing day", route);
+        assert_eq!(get(format!("{}/99", route)), 404, "{} of a missing day", route);
+    }
+    assert!(preview::candidates(&data, Day::from(99), None, None, 1).is_empty());
+}
+
+#[test]
+fn test_outbound_hosts() {
+    let dir = ::tempfile::TempDir::new().unwrap();
+    let config = Config {
+        data_dir: dir.path().to_path_buf(),
+        outbound_hosts: vec!["*.example.com".to_string()],
+        ..Config::default()
+    };
+    let sessions = session::Sessions::default();
+    let token = sessions.token("s");
+    let path = "abcdefghijklmnop";
+    let post = |url: &str, form: String| {
+        let request = Request::fake_http("POST", format!("/pairs/{}/{}", path, url),
+                                         vec![("Cookie".to_string(),
+                                               format!("{}=s", session::COOKIE)),
+                                              ("Content-Type".to_string(),
+                                               "application/x-www-form-urlencoded".to_string())],
+                                         format!("{}&csrf={}", form, token).into_bytes());
+        assert_eq!(handle(&config, &sessions, &request).status_code, 303);
+        database::Data::new(dir.path(), path)
+    };
+    let data = post("webhooks", "webhooks=http://169.254.169.254/latest".to_string());
+    assert!(data.webhooks().is_empty());
+    let data = post("webhooks", "webhooks=https://hooks.example.com/pairs".to_string());
+    assert_eq!(data.webhooks(), &["https://hooks.example.com/pairs".to_string()]);
+    let source = |url: &str| format!("action=source&kind=json&location={}&section=&token=&csv=",
+                                     url);
+    assert!(post("roster", source("http://localhost:8088/")).roster_source().is_none());
+    assert!(post("roster", source("https://lms.example.com/")).roster_source().is_some());
+}
+
+#[test]
+fn test_rename_onto_existing() {
+    let dir = ::tempfile::TempDir::new().unwrap();
+    let config = Config { data_dir: dir.path().to_path_buf(), ..Config::default() };
+    let sessions = session::Sessions::default();
+    let token = sessions.token("s");
+    let path = "abcdefghijklmnop";
+    let mut data = database::Data::empty(path, dir.path().join(format!("{}.yaml", path)));
+    let (a, b) = (Section::from("A".to_string()), Section::from("B".to_string()));
+    data.new_section(a, Zoom::from("".to_string()));
+    data.new_section(b, Zoom::from("".to_string()));
+    let (ann, bob) = (Student::from("ann".to_string()), Student::from("bob".to_string()));
+    data.new_student(ann, a);
+    data.new_student(bob, b);
+    let (red, blue) = (Team::from("red".to_string()), Team::from("blue".to_string()));
+    data.new_team(red);
+    data.new_team(blue);
+    data.save();
+    let post = |url: &str, form: &str| {
+        let request = Request::fake_http("POST", format!("/pairs/{}/{}", path, url),
+                                         vec![("Cookie".to_string(),
+                                               format!("{}=s", session::COOKIE)),
+                                              ("Content-Type".to_string(),
+                                               "application/x-www-form-urlencoded".to_string())],
+                                         format!("{}&csrf={}", form, token).into_bytes());
+        assert_eq!(handle(&config, &sessions, &request).status_code, 303);
+        database::Data::new(dir.path(), path)
+    };
+    let data = post("students", "section=A&oldname=ann&newname=bob&tags=&email=");
+    assert_eq!(data.list_students(), vec![ann, bob]);
+    assert_eq!(data.student_section(bob), Some(b));
+    let data = post("sections", "oldname=A&newname=B&newzoom=&objective=&capacity=&preferred=");
+    assert_eq!(data.list_sections(), vec![a, b]);
+    assert_eq!(data.student_section(ann), Some(a));
+    let data = post("teams", "oldname=red&newname=blue");
+    assert_eq!(data.list_teams(), vec![blue, red]);
+    let data = post("teams", "oldname=red&newname=gold");
+    assert_eq!(data.list_teams(), vec![blue, Team::from("gold".to_string())]);
 }
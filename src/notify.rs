@@ -0,0 +1,439 @@
+//! Publishing a day: telling each student which team they are on, with
+//! whom, and where to find their meeting and the team's links.  The
+//! messages go out through the channels in the server's configuration,
+//! each either an SMTP server or a web service.  They are sent from the
+//! course's outbox (see `outbox`), which retries a channel that is down,
+//! and every delivery is logged with the day once it is made or given
+//! up on.
+//!
+//! A web service is posted each `Message` as JSON, so that it can pass
+//! it on by chat, text or whatever the course uses.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::time::Duration;
+
+use rand;
+use serde_json;
+use ureq;
+
+use database::{Data, Day, Student};
+use outbox::{self, Job};
+use registry;
+use sheet;
+
+/// How long a channel may take to answer before we give up.
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    /// Email through an SMTP server (as "host:port"), which must
+    /// accept mail from this server without logging in.
+    Smtp { server: String, from: String },
+    /// The URL of a web service.
+    Webhook(String),
+}
+
+impl Channel {
+    /// How `message` is sent, e.g. "email to ann@example.com".
+    pub fn describe(&self, message: &Message) -> String {
+        match *self {
+            Channel::Smtp { .. } => match message.email {
+                Some(ref to) => format!("email to {}", to),
+                None => "email".to_string(),
+            },
+            Channel::Webhook(ref url) => format!("webhook {}", url),
+        }
+    }
+    /// Whether `message` can be sent at all, which it cannot by email
+    /// to a student with no address.
+    fn check(&self, message: &Message) -> Result<(), String> {
+        match (self, &message.email) {
+            (&Channel::Smtp { .. }, &None) => Err("No email address".to_string()),
+            _ => Ok(()),
+        }
+    }
+    pub fn send(&self, message: &Message) -> Result<(), String> {
+        self.check(message)?;
+        match *self {
+            Channel::Smtp { ref server, ref from } => {
+                let to = message.email.as_deref().unwrap_or_default();
+                send_email(server, from, to, message, outbox::now())
+            }
+            Channel::Webhook(ref url) => post(url, message),
+        }
+    }
+}
+
+/// What one student is told about a day.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    /// The secret path of the course.
+    pub course: String,
+    pub day: usize,
+    pub day_name: String,
+    pub student: String,
+    pub email: Option<String>,
+    pub section: String,
+    pub team: String,
+    pub partners: Vec<String>,
+    /// The link to the section's meeting, if it has one.
+    pub meeting: String,
+    /// The team's links, labelled.
+    pub links: Vec<(String, String)>,
+    pub subject: String,
+    pub text: String,
+}
+
+/// A message sent (or not) to a student.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Delivery {
+    pub student: Student,
+    /// How the message was sent, e.g. "email to ann@example.com".
+    pub channel: String,
+    /// What went wrong, if the message was not delivered.
+    pub error: Option<String>,
+    /// When, in seconds since 1970.
+    pub time: u64,
+}
+
+/// The message for each student on a team on `day`.
+pub fn messages(data: &Data, day: Day) -> Vec<Message> {
+    let day = data.improve_day(day);
+    let sheet = sheet::day_sheet(data, day);
+    let mut messages = Vec::new();
+    for s in sheet.sections.iter() {
+        for t in s.teams.iter() {
+            for &student in t.partners.iter() {
+                let partners: Vec<String> = t.partners.iter().filter(|&&p| p != student)
+                    .map(|p| p.name.to_string()).collect();
+                let mut text = format!("Hi {},\n\nFor {} you are on team {}", student,
+                                       day.pretty(), t.team);
+                text.push_str(&if partners.is_empty() {
+                    ", working on your own".to_string()
+                } else {
+                    format!(" with {}", partners.join(" and "))
+                });
+                text.push_str(&format!(", in section {}.\n", s.section));
+                if !s.meeting.is_empty() {
+                    text.push_str(&format!("\nMeeting: {}\n", s.meeting));
+                }
+                for (label, link) in t.links.iter() {
+                    text.push_str(&format!("{}: {}\n", label, link));
+                }
+                messages.push(Message {
+                    course: data.path().to_string(),
+                    day: day.id,
+                    day_name: day.pretty(),
+                    student: student.name.to_string(),
+                    email: data.student_email(student).map(|e| e.to_string()),
+                    section: s.section.name.to_string(),
+                    team: t.team.name.to_string(),
+                    partners,
+                    meeting: s.meeting.clone(),
+                    links: t.links.clone(),
+                    subject: format!("Your team for {}", day.pretty()),
+                    text,
+                });
+            }
+        }
+    }
+    messages
+}
+
+/// Log the result of sending `message` through `channel` with its day,
+/// for the course saved in `course_file`.
+pub fn log_delivery(course_file: &Path, channel: &Channel, message: &Message,
+                    result: Result<(), String>) -> Result<(), String> {
+    let mut data = Data::open(course_file)?;
+    data.log_deliveries(Day::from(message.day), vec![Delivery {
+        student: Student::from(message.student.clone()),
+        channel: channel.describe(message),
+        error: result.err(),
+        time: outbox::now(),
+    }]);
+    data.save();
+    Ok(())
+}
+
+/// How many messages for `day` are waiting to be sent.
+pub fn waiting(data: &Data, day: Day) -> usize {
+    outbox::pending(data.file()).iter()
+        .filter(|p| match p.job {
+            Job::Message { ref message, .. } => message.day == day.id,
+            _ => false,
+        })
+        .count()
+}
+
+/// Lock `day` and queue each student on a team their message through
+/// every channel, returning how many were queued.  The course is saved
+/// first, since the messages are sent, and their deliveries logged, in
+/// the background.  A day is only published a second time if `again`,
+/// so that a repeated request sends nothing twice.
+pub fn publish_day(channels: &[Channel], data: &mut Data, day: Day, again: bool)
+                   -> Result<usize, String> {
+    if channels.is_empty() {
+        return Err("This server has no channels to send messages through".to_string());
+    }
+    let published = format!("{} has already been published", day.pretty());
+    if !again && !data.deliveries(day).is_empty() {
+        return Err(published);
+    }
+    if data.day_unlocked(day) {
+        data.toggle_lock_day(day);
+    }
+    let mut unsendable = Vec::new();
+    let mut jobs = Vec::new();
+    for message in messages(data, day) {
+        for channel in channels {
+            match channel.check(&message) {
+                Ok(()) => jobs.push(Job::Message { channel: channel.clone(),
+                                                   message: message.clone() }),
+                Err(e) => unsendable.push(Delivery {
+                    student: Student::from(message.student.clone()),
+                    channel: channel.describe(&message),
+                    error: Some(e),
+                    time: outbox::now(),
+                }),
+            }
+        }
+    }
+    let queued = jobs.len();
+    data.save();
+    let day_id = day.id;
+    let clash = |job: &Job| !again && match *job {
+        Job::Message { ref message, .. } => message.day == day_id,
+        _ => false,
+    };
+    if !outbox::enqueue_unless(data.file(), jobs, clash)? {
+        return Err(published);
+    }
+    if !unsendable.is_empty() {
+        data.log_deliveries(day, unsendable);
+        data.save();
+    }
+    Ok(queued)
+}
+
+/// Check that `email` looks like an address we can send to, and
+/// cannot add to the headers of a message.
+pub fn check_email(email: &str) -> Result<(), String> {
+    let ok = match email.find('@') {
+        Some(at) => at > 0 && at + 1 < email.len() && !email[at+1..].contains('@'),
+        None => false,
+    };
+    if !ok || email.chars().any(|c| c.is_whitespace() || c.is_control()
+                                 || "<>,;\"()[]\\".contains(c)) {
+        return Err(format!("{:?} is not an email address", email));
+    }
+    Ok(())
+}
+
+fn post(url: &str, message: &Message) -> Result<(), String> {
+    let body = serde_json::to_string(message).map_err(|e| e.to_string())?;
+    ureq::post(url)
+        .timeout(TIMEOUT)
+        .set("Content-Type", "application/json")
+        .send_string(&body)
+        .map_err(|e| format!("Error posting to {}: {}", url, e))?;
+    Ok(())
+}
+
+/// Send `message` to `to` by email, dated `time` (in seconds since
+/// 1970).
+fn send_email(server: &str, from: &str, to: &str, message: &Message, time: u64)
+              -> Result<(), String> {
+    let address = server.to_socket_addrs().ok().and_then(|mut a| a.next())
+        .ok_or_else(|| format!("Unable to find {:?}", server))?;
+    let stream = TcpStream::connect_timeout(&address, TIMEOUT)
+        .map_err(|e| format!("Unable to connect to {}: {}", server, e))?;
+    stream.set_read_timeout(Some(TIMEOUT)).ok();
+    stream.set_write_timeout(Some(TIMEOUT)).ok();
+    let mut smtp = Smtp {
+        reader: BufReader::new(stream.try_clone().map_err(|e| e.to_string())?),
+        writer: stream,
+    };
+    let domain = from.rsplit('@').next().unwrap_or("localhost");
+    smtp.reply(220)?;
+    smtp.command(&format!("EHLO {}", domain), 250)?;
+    smtp.command(&format!("MAIL FROM:<{}>", from), 250)?;
+    smtp.command(&format!("RCPT TO:<{}>", to), 250)?;
+    smtp.command("DATA", 354)?;
+    let mut mail = format!("Date: {}\r\nMessage-ID: <{}.{:016x}@{}>\r\n\
+                            From: {}\r\nTo: {}\r\nSubject: {}\r\nMIME-Version: 1.0\r\n\
+                            Content-Type: text/plain; charset=utf-8\r\n\
+                            Content-Transfer-Encoding: 8bit\r\n\r\n",
+                           registry::format_email_date(time), time, rand::random::<u64>(),
+                           domain, from, to, encode_header(&message.subject));
+    for line in message.text.lines() {
+        // A line with just a dot would end the message early.
+        if line.starts_with('.') {
+            mail.push('.');
+        }
+        mail.push_str(line);
+        mail.push_str("\r\n");
+    }
+    mail.push('.');
+    smtp.command(&mail, 250)?;
+    smtp.command("QUIT", 221).ok();
+    Ok(())
+}
+
+struct Smtp {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Smtp {
+    /// Read a reply, which may span several lines, and check that its
+    /// code is in the same class as `expected`.
+    fn reply(&mut self, expected: u32) -> Result<(), String> {
+        loop {
+            let mut line = String::new();
+            self.reader.read_line(&mut line).map_err(|e| format!("SMTP error: {}", e))?;
+            let code: u32 = line.get(..3).and_then(|c| c.parse().ok())
+                .ok_or_else(|| format!("Unexpected SMTP reply {:?}", line))?;
+            if code / 100 != expected / 100 {
+                return Err(format!("SMTP error: {}", line.trim_end()));
+            }
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return Ok(());
+            }
+        }
+    }
+    fn command(&mut self, command: &str, expected: u32) -> Result<(), String> {
+        self.writer.write_all(format!("{}\r\n", command).as_bytes())
+            .map_err(|e| format!("SMTP error: {}", e))?;
+        self.reply(expected)
+    }
+}
+
+/// A header value made safe to send: with line breaks removed, and as
+/// a base64 "encoded word" if it is not plain ASCII.
+fn encode_header(value: &str) -> String {
+    let value: String = value.chars().filter(|c| !c.is_control()).collect();
+    if value.is_ascii() {
+        return value;
+    }
+    const DIGITS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = "=?utf-8?b?".to_string();
+    for chunk in value.as_bytes().chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8*i));
+        for i in 0..4 {
+            out.push(if i <= chunk.len() {
+                DIGITS[(n >> (18 - 6*i) & 63) as usize] as char
+            } else {
+                '='
+            });
+        }
+    }
+    out.push_str("?=");
+    out
+}
+
+#[test]
+fn test_publish_day() {
+    use std::net::TcpListener;
+    use database::{Section, ShuffleMode, Team, Zoom};
+    let dir = ::tempfile::TempDir::new().unwrap();
+    let mut data = Data::new(dir.path(), "testcourse1234");
+    let section = Section::from("A".to_string());
+    data.new_section(section, Zoom::from("https://example.com/a".to_string()));
+    let s: Vec<_> = ["ann", "bob", "cat"].iter().map(|n| Student::from(n.to_string())).collect();
+    for &student in s.iter() {
+        data.new_student(student, section);
+    }
+    data.new_team(Team::from("red".to_string()));
+    data.new_team(Team::from("blue".to_string()));
+    data.set_email(s[0], "ann@example.com").unwrap();
+    data.set_email(s[1], "bob@example.com").unwrap();
+    assert!(data.set_email(s[2], "cat\r\nBcc: x@y").is_err());
+    data.add_day();
+    let day = Day::from(0);
+    data.run_shuffle(day, ShuffleMode::Shuffle, None);
+    data.toggle_lock_day(day);
+    assert!(data.day_unlocked(day));
+
+    // A local SMTP sink, which accepts every message and keeps it.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let server = listener.local_addr().unwrap().to_string();
+    let sink = ::std::thread::spawn(move || {
+        let mut mail: Vec<String> = Vec::new();
+        for _ in 0..2 {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            writer.write_all(b"220 sink\r\n").unwrap();
+            let mut data: Option<String> = None;
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 0 {
+                let reply: &[u8] = match data {
+                    Some(ref mut text) if line == ".\r\n" => {
+                        mail.push(::std::mem::take(text));
+                        data = None;
+                        b"250 queued\r\n"
+                    }
+                    Some(ref mut text) => {
+                        text.push_str(&line);
+                        b""
+                    }
+                    None if line.starts_with("EHLO") => b"250-sink\r\n250 8BITMIME\r\n",
+                    None if line.starts_with("DATA") => {
+                        data = Some(String::new());
+                        b"354 go ahead\r\n"
+                    }
+                    None if line.starts_with("QUIT") => b"221 bye\r\n",
+                    None => b"250 ok\r\n",
+                };
+                writer.write_all(reply).unwrap();
+                line.clear();
+            }
+        }
+        mail
+    });
+    let channels = vec![Channel::Smtp { server, from: "pairs@example.com".to_string() }];
+    // Only the messages which can be sent are queued; nothing is sent
+    // until the outbox is delivered.
+    assert_eq!(publish_day(&channels, &mut data, day, false), Ok(2));
+    assert!(!data.day_unlocked(day));
+    assert_eq!(waiting(&data, day), 2);
+    assert_eq!(data.deliveries(day).len(), 1);
+    assert_eq!(data.deliveries(day)[0].error, Some("No email address".to_string()));
+    // Publishing twice by accident sends nothing more.
+    assert!(publish_day(&channels, &mut data, day, false).is_err());
+    assert_eq!(waiting(&data, day), 2);
+
+    outbox::deliver_course(data.file(), outbox::now()).unwrap();
+    let mut data = Data::open(data.file()).unwrap();
+    assert_eq!(waiting(&data, day), 0);
+    let deliveries = data.deliveries(day);
+    assert_eq!(deliveries.len(), 3);
+    assert!(deliveries.iter().filter(|d| d.error.is_none()).all(|d| d.channel.starts_with("email to ")));
+
+    let mail = sink.join().unwrap();
+    assert_eq!(mail.len(), 2);
+    for m in mail.iter() {
+        assert!(m.starts_with("Date: "), "{}", m);
+        assert!(m.contains("\r\nMessage-ID: <"), "{}", m);
+        assert!(m.contains("Subject: Your team for Day 0\r\n"), "{}", m);
+        assert!(m.contains("\r\nMeeting: https://example.com/a\r\n"), "{}", m);
+    }
+    assert!(mail.iter().any(|m| m.contains("To: ann@example.com\r\n")));
+
+    assert!(publish_day(&[], &mut data, day, true).is_err());
+    assert!(publish_day(&channels, &mut data, day, false).is_err());
+    assert_eq!(data.deliveries(day).len(), 3);
+}
+
+#[test]
+fn test_encode_header() {
+    assert_eq!(encode_header("Your team for Day 1"), "Your team for Day 1");
+    assert_eq!(encode_header("Day\r\nBcc: x"), "DayBcc: x");
+    assert_eq!(encode_header("Día"), "=?utf-8?b?RMOtYQ==?=");
+    assert!(check_email("ann@example.com").is_ok());
+    assert!(check_email("ann").is_err());
+    assert!(check_email("<ann@example.com>").is_err());
+}
@@ -0,0 +1,373 @@
+//! The work a course leaves for other servers: webhook events to post,
+//! teams to provision and messages to send.  Each job is put in an outbox kept beside
+//! the course file, and a thread of the server does the jobs, retrying
+//! with growing delays while another server is down, so that neither
+//! saving a course nor answering a request waits for anyone else.
+//!
+//! Jobs in the same queue (e.g. the events for one endpoint) are done
+//! in order, and a queue stops at its first failure until its retry is
+//! due.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_yaml;
+
+use atomicfile::AtomicFile;
+use database::Data;
+use hooks::{self, Hook, TeamEvent};
+use notify::{self, Channel, Message};
+use webhooks::{self, Event};
+
+/// How often the outboxes are checked when nothing new is queued.
+const POLL: Duration = Duration::from_secs(10);
+/// How many times a job is tried before it is dropped.
+const MAX_ATTEMPTS: u32 = 12;
+/// The delay after the first failure, in seconds, which doubles with
+/// each further failure up to `LAST_RETRY`.
+pub const FIRST_RETRY: u64 = 30;
+const LAST_RETRY: u64 = 60*60;
+
+/// Held while an outbox is read or written, and signalled when jobs
+/// are queued.
+static OUTBOXES: Mutex<()> = Mutex::new(());
+static QUEUED: Condvar = Condvar::new();
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Job {
+    /// Post `event` of `course`, saved at `time`, to `endpoint`.
+    Event { endpoint: String, course: String, time: u64, event: Event },
+    /// Run `hook` for `event`, keeping the links it reports.
+    Provision { hook: Hook, event: TeamEvent },
+    /// Send `message` through `channel`, logging the delivery.
+    Message { channel: Channel, message: Message },
+}
+
+impl Job {
+    /// The queue of the job, within which jobs are done in order.
+    fn queue(&self) -> String {
+        match *self {
+            Job::Event { ref endpoint, .. } => format!("event {}", endpoint),
+            Job::Provision { ref event, .. } => format!("provision {}", event.team),
+            Job::Message { ref channel, ref message } => {
+                format!("message {}", channel.describe(message))
+            }
+        }
+    }
+    pub fn is_event(&self) -> bool {
+        matches!(*self, Job::Event { .. })
+    }
+    /// What the job is, for listing.
+    pub fn describe(&self) -> String {
+        match *self {
+            Job::Event { ref endpoint, ref event, .. } => {
+                format!("post {} to {}", event.name(), endpoint)
+            }
+            Job::Provision { ref event, .. } => {
+                format!("provision {} ({})", event.team, event.event.name())
+            }
+            Job::Message { ref channel, ref message } => {
+                format!("send day {} to {} by {}", message.day, message.student,
+                        channel.describe(message))
+            }
+        }
+    }
+    /// Do the job numbered `id` for the course saved in `course_file`.
+    fn run(&self, id: u64, course_file: &Path) -> Result<(), String> {
+        match *self {
+            Job::Event { ref endpoint, ref course, time, ref event } => {
+                webhooks::post(endpoint, &webhooks::Envelope {
+                    id,
+                    course: course.clone(),
+                    time,
+                    event: event.clone(),
+                })
+            }
+            Job::Provision { ref hook, ref event } => {
+                let resources = hook.run(event)?;
+                let mut data = Data::open(course_file)?;
+                hooks::keep_resources(&mut data, event, resources);
+                data.save();
+                Ok(())
+            }
+            Job::Message { ref channel, ref message } => {
+                channel.send(message)?;
+                notify::log_delivery(course_file, channel, message, Ok(()))
+            }
+        }
+    }
+    /// Record that the job has been given up on after `error`.
+    fn give_up(&self, course_file: &Path, error: &str) -> Result<(), String> {
+        match *self {
+            Job::Message { ref channel, ref message } => {
+                notify::log_delivery(course_file, channel, message, Err(error.to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A job waiting to be done.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Pending {
+    pub id: u64,
+    pub job: Job,
+    pub attempts: u32,
+    /// When to try next, in seconds since 1970.
+    pub next_attempt: u64,
+    /// What went wrong the last time.
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+struct Outbox {
+    next_id: u64,
+    pending: Vec<Pending>,
+}
+
+/// The outbox of the course saved in `course_file`.
+fn outbox_file(course_file: &Path) -> PathBuf {
+    course_file.with_extension("outbox")
+}
+
+fn read(file: &Path) -> Result<Outbox, String> {
+    if !file.exists() {
+        return Ok(Outbox::default());
+    }
+    let f = ::std::fs::File::open(file)
+        .map_err(|e| format!("Unable to open {:?}: {}", file, e))?;
+    serde_yaml::from_reader(&f).map_err(|e| format!("Unable to parse {:?}: {}", file, e))
+}
+
+fn write(file: &Path, outbox: &Outbox) -> Result<(), String> {
+    let f = AtomicFile::create(file).map_err(|e| format!("Unable to create {:?}: {}", file, e))?;
+    serde_yaml::to_writer(&f, outbox).map_err(|e| format!("Unable to write {:?}: {}", file, e))
+}
+
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Queue `jobs` for the course saved in `course_file`.
+pub fn enqueue(course_file: &Path, jobs: Vec<Job>) -> Result<(), String> {
+    if jobs.is_empty() {
+        return Ok(());
+    }
+    enqueue_unless(course_file, jobs, |_| false).map(|_| ())
+}
+
+/// Queue `jobs` for the course saved in `course_file`, unless a job for
+/// which `clash` is true is already waiting, returning whether they
+/// were queued.
+pub fn enqueue_unless<F: Fn(&Job) -> bool>(course_file: &Path, jobs: Vec<Job>, clash: F)
+                                           -> Result<bool, String> {
+    let _lock = OUTBOXES.lock().unwrap();
+    let file = outbox_file(course_file);
+    let mut outbox = read(&file)?;
+    if outbox.pending.iter().any(|p| clash(&p.job)) {
+        return Ok(false);
+    }
+    if jobs.is_empty() {
+        return Ok(true);
+    }
+    let time = now();
+    for job in jobs {
+        outbox.pending.push(Pending {
+            id: outbox.next_id,
+            job,
+            attempts: 0,
+            next_attempt: time,
+            error: None,
+        });
+        outbox.next_id += 1;
+    }
+    write(&file, &outbox)?;
+    QUEUED.notify_all();
+    Ok(true)
+}
+
+/// Drop the jobs of the course saved in `course_file` for which `keep`
+/// is false.
+pub fn retain<F: Fn(&Job) -> bool>(course_file: &Path, keep: F) -> Result<(), String> {
+    let _lock = OUTBOXES.lock().unwrap();
+    let file = outbox_file(course_file);
+    if !file.exists() {
+        return Ok(());
+    }
+    let mut outbox = read(&file)?;
+    outbox.pending.retain(|p| keep(&p.job));
+    write(&file, &outbox)
+}
+
+/// The jobs of the course saved in `course_file` which have yet to be
+/// done.
+pub fn pending(course_file: &Path) -> Vec<Pending> {
+    let _lock = OUTBOXES.lock().unwrap();
+    read(&outbox_file(course_file)).map(|o| o.pending).unwrap_or_default()
+}
+
+/// Do the jobs in the outbox `file` which are due at `now`, in order
+/// for each queue, stopping at the first failure.  The outbox is only
+/// locked while it is read and updated, not while the jobs are done.
+fn deliver(file: &Path, now: u64) -> Result<(), String> {
+    let course_file = file.with_extension("yaml");
+    let mut queues: Vec<(String, Vec<Pending>)> = Vec::new();
+    {
+        let _lock = OUTBOXES.lock().unwrap();
+        for p in read(file)?.pending {
+            let queue = p.job.queue();
+            match queues.iter_mut().find(|(q, _)| *q == queue) {
+                Some((_, jobs)) => jobs.push(p),
+                None => queues.push((queue, vec![p])),
+            }
+        }
+    }
+    queues.retain(|(_, jobs)| jobs[0].next_attempt <= now);
+    let mut results = Vec::new();
+    for (_, jobs) in queues {
+        for p in jobs {
+            let result = p.job.run(p.id, &course_file);
+            let failed = result.is_err();
+            results.push((p.id, result));
+            if failed {
+                break;
+            }
+        }
+    }
+    if results.is_empty() {
+        return Ok(());
+    }
+    let mut given_up = Vec::new();
+    let lock = OUTBOXES.lock().unwrap();
+    let mut outbox = read(file)?;
+    for (id, result) in results {
+        let i = match outbox.pending.iter().position(|p| p.id == id) {
+            Some(i) => i,
+            None => continue,
+        };
+        match result {
+            Ok(()) => {
+                outbox.pending.remove(i);
+            }
+            Err(e) => {
+                let p = &mut outbox.pending[i];
+                p.attempts += 1;
+                if p.attempts >= MAX_ATTEMPTS {
+                    println!("Giving up on job {} ({}): {}", id, p.job.describe(), e);
+                    given_up.push((p.job.clone(), e));
+                    outbox.pending.remove(i);
+                } else {
+                    let delay = FIRST_RETRY.saturating_mul(1 << (p.attempts - 1).min(20));
+                    p.next_attempt = now + delay.min(LAST_RETRY);
+                    p.error = Some(e);
+                }
+            }
+        }
+    }
+    write(file, &outbox)?;
+    drop(lock);
+    for (job, e) in given_up {
+        job.give_up(&course_file, &e)?;
+    }
+    Ok(())
+}
+
+/// Do the due jobs of the course saved in `course_file`.
+pub fn deliver_course(course_file: &Path, now: u64) -> Result<(), String> {
+    let file = outbox_file(course_file);
+    if !file.exists() {
+        return Ok(());
+    }
+    deliver(&file, now)
+}
+
+/// Do the due jobs of every course in `data_dir`.
+pub fn deliver_all(data_dir: &Path, now: u64) {
+    let entries = match ::std::fs::read_dir(data_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("Unable to read {:?}: {}", data_dir, e);
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let file = entry.path();
+        if file.extension().map(|e| e == "outbox").unwrap_or(false) {
+            if let Err(e) = deliver(&file, now) {
+                println!("Error doing queued jobs: {}", e);
+            }
+        }
+    }
+}
+
+/// Start the thread which does the jobs of every course in `data_dir`.
+pub fn start(data_dir: PathBuf) {
+    ::std::thread::spawn(move || loop {
+        deliver_all(&data_dir, now());
+        let lock = OUTBOXES.lock().unwrap();
+        drop(QUEUED.wait_timeout(lock, POLL).unwrap());
+    });
+}
+
+#[test]
+fn test_outbox() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    let dir = ::tempfile::TempDir::new().unwrap();
+    let course_file = dir.path().join("testcourse.yaml");
+    // An endpoint which answers two requests, then goes down.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let up = format!("http://{}/events", listener.local_addr().unwrap());
+    let server = ::std::thread::spawn(move || {
+        let mut bodies = Vec::new();
+        for _ in 0..2 {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut length = 0;
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                if line.to_lowercase().starts_with("content-length:") {
+                    length = line[15..].trim().parse().unwrap();
+                }
+                line.clear();
+            }
+            let mut body = vec![0; length];
+            ::std::io::Read::read_exact(&mut reader, &mut body).unwrap();
+            bodies.push(String::from_utf8(body).unwrap());
+            (&stream).write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").unwrap();
+        }
+        bodies
+    });
+    let down = {
+        let l = TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}/events", l.local_addr().unwrap())
+    };
+    let events = vec![Event::DayCreated { day: 0, name: "Day 0".to_string() },
+                      Event::DayLocked { day: 0, locked: true }];
+    webhooks::enqueue(&course_file, "testcourse", &[up.clone(), down.clone()], events).unwrap();
+    assert_eq!(pending(&course_file).len(), 4);
+
+    let start = now();
+    deliver_all(dir.path(), start);
+    let bodies = server.join().unwrap();
+    assert!(bodies[0].contains("\"type\":\"DayCreated\""), "{}", bodies[0]);
+    assert!(bodies[1].contains("\"id\":1,"), "{}", bodies[1]);
+    assert!(bodies[1].contains("\"type\":\"DayLocked\""), "{}", bodies[1]);
+    // The down endpoint keeps both events, in order, and is retried later.
+    let left = pending(&course_file);
+    assert_eq!(left.len(), 2);
+    assert!(left.iter().all(|p| p.job.queue() == format!("event {}", down)));
+    assert_eq!((left[0].attempts, left[0].id), (1, 2));
+    assert_eq!(left[0].next_attempt, start + FIRST_RETRY);
+    assert!(left[0].error.is_some());
+    assert_eq!(left[1].attempts, 0);
+    deliver_all(dir.path(), start + 1);
+    assert_eq!(pending(&course_file)[0].attempts, 1);
+    deliver_all(dir.path(), start + FIRST_RETRY);
+    assert_eq!(pending(&course_file)[0].attempts, 2);
+    assert_eq!(pending(&course_file)[0].next_attempt, start + 3*FIRST_RETRY);
+    webhooks::retain_endpoints(&course_file, &[up]).unwrap();
+    assert!(pending(&course_file).is_empty());
+}
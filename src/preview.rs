@@ -0,0 +1,233 @@
+//! Previews of the shuffles: candidate pairings for a day, scored and
+//! compared with the current pairings, so that one of them can be
+//! accepted in place of shuffling blind.
+
+use std::collections::{BTreeSet, HashSet};
+
+use database::{Data, Day, Pairing, Section, ShuffleMode, Student};
+use statistics;
+
+/// A possible set of pairings for a day, as made by a shuffle.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Candidate {
+    pub mode: ShuffleMode,
+    #[serde(default)]
+    pub section: Option<Section>,
+    pub pairings: HashSet<Pairing>,
+    #[serde(default)]
+    pub warnings: Vec<String>,
+}
+
+impl Candidate {
+    pub fn label(&self) -> String {
+        match self.section {
+            Some(section) => format!("{} ({})", self.mode.label(), section.name),
+            None => self.mode.label().to_string(),
+        }
+    }
+}
+
+/// The candidates for a day, along with the pairings the day had when
+/// they were made.  A candidate may only be accepted while the day is
+/// unchanged.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Preview {
+    pub base: HashSet<Pairing>,
+    pub candidates: Vec<Candidate>,
+}
+
+/// How a set of pairings for a day turns out.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Score {
+    /// The pairs who have worked together on an earlier day.
+    pub repeats: usize,
+    pub solos: usize,
+    /// The students on the same team as on the previous day.
+    pub continuity: usize,
+    /// How far the students are, in total, from having driven and
+    /// navigated equally often, counting this day.
+    pub role_imbalance: usize,
+    /// The students whose placement differs from the current pairings.
+    pub changes: usize,
+}
+
+/// Make `count` candidates for `day` with `mode`, or with every
+/// strategy that makes sense for the course if `mode` is `None`.  Each
+/// is made by running the shuffle on a copy of the course.  Duplicate
+/// candidates are dropped.
+pub fn candidates(data: &Data, day: Day, mode: Option<ShuffleMode>, section: Option<Section>,
+                  count: usize) -> Vec<Candidate> {
+    if data.existing_day(day).is_none() {
+        return Vec::new();
+    }
+    let modes = match mode {
+        Some(mode) => vec![mode],
+        None => strategies(data),
+    };
+    let mut candidates: Vec<Candidate> = Vec::new();
+    for mode in modes {
+        for _ in 0..count {
+            let mut copy = data.clone();
+            let warnings = copy.run_shuffle(day, mode, section);
+            let pairings = copy.pairings()[day.id].clone();
+            if !candidates.iter().any(|c| c.pairings == pairings) {
+                candidates.push(Candidate {
+                    mode,
+                    section: if mode.is_grand() { None } else { section },
+                    pairings,
+                    warnings,
+                });
+            }
+        }
+    }
+    candidates
+}
+
+/// The shuffles worth previewing for this course.
+fn strategies(data: &Data) -> Vec<ShuffleMode> {
+    ShuffleMode::all().into_iter().filter(|&m| match m {
+        ShuffleMode::Clear => false,
+        ShuffleMode::Scheduled => !data.rotation().is_empty(),
+        ShuffleMode::Balance | ShuffleMode::Group => data.balance_attribute().is_some(),
+        _ => true,
+    }).collect()
+}
+
+/// Score `pairings` as the pairings for `day`, relative to the
+/// current pairings `base`.
+pub fn score(data: &Data, day: Day, base: &HashSet<Pairing>,
+             pairings: &HashSet<Pairing>) -> Score {
+    let mut score = Score::default();
+    let mut balance = statistics::role_balance(data, day);
+    let previous = if day.id > 0 { data.pairings().get(day.id - 1) } else { None };
+    for p in pairings.iter() {
+        match *p {
+            Pairing::Pair { primary, secondary, .. } => {
+                if !data.partner_history(day, primary, secondary).is_empty() {
+                    score.repeats += 1;
+                }
+                *balance.entry(primary).or_insert(0) += 1;
+                *balance.entry(secondary).or_insert(0) -= 1;
+            }
+            Pairing::Solo { .. } => score.solos += 1,
+            _ => (),
+        }
+        if let (Some(team), Some(previous)) = (p.team(), previous) {
+            score.continuity += p.present_students().into_iter()
+                .filter(|&s| previous.iter().any(|q| q.team() == Some(team)
+                                                  && q.present_students().contains(&s)))
+                .count();
+        }
+    }
+    score.role_imbalance = balance.values().map(|b| b.unsigned_abs()).sum();
+    score.changes = diff(base, pairings).len();
+    score
+}
+
+/// A summary of the teams on `day` in `section` (or every section),
+/// such as "12 teams, 1 repeat".
+pub fn summary(data: &Data, day: Day, section: Option<Section>) -> String {
+    let pairings: HashSet<Pairing> = data.pairings()[day.id].iter().cloned()
+        .filter(|p| p.team().is_some() && (section.is_none() || p.section() == section))
+        .collect();
+    let score = score(data, day, &pairings, &pairings);
+    let mut summary = format!("{}, {}", plural(pairings.len(), "team"),
+                              plural(score.repeats, "repeat"));
+    if score.solos > 0 {
+        summary.push_str(&format!(", {}", plural(score.solos, "solo")));
+    }
+    summary
+}
+
+fn plural(n: usize, noun: &str) -> String {
+    format!("{} {}{}", n, noun, if n == 1 { "" } else { "s" })
+}
+
+/// Where `student` is in `pairings`, in words.
+pub fn placement(pairings: &HashSet<Pairing>, student: Student) -> String {
+    match pairings.iter().find(|p| p.allocated_students().contains(&student)) {
+        Some(&Pairing::Pair { primary, secondary, team, .. }) => {
+            let partner = if primary == student { secondary } else { primary };
+            format!("{} with {}", team.name, partner.name)
+        }
+        Some(&Pairing::Solo { team, .. }) => format!("{} alone", team.name),
+        Some(&Pairing::Absent(_)) => "absent".to_string(),
+        _ => "unassigned".to_string(),
+    }
+}
+
+/// A student whose placement differs between two sets of pairings.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Change {
+    pub student: Student,
+    pub before: String,
+    pub after: String,
+}
+
+/// The students placed differently in `after` than in `before`.
+pub fn diff(before: &HashSet<Pairing>, after: &HashSet<Pairing>) -> Vec<Change> {
+    let students: BTreeSet<Student> = before.iter().chain(after.iter())
+        .flat_map(|p| p.allocated_students())
+        .collect();
+    students.into_iter().filter_map(|student| {
+        let (before, after) = (placement(before, student), placement(after, student));
+        if before == after {
+            None
+        } else {
+            Some(Change { student, before, after })
+        }
+    }).collect()
+}
+
+#[test]
+fn test_preview() {
+    use database::{Team, Zoom};
+    let mut data = Data::empty("testcourse", "testcourse.yaml");
+    let section = Section::from("A".to_string());
+    data.new_section(section, Zoom::from("".to_string()));
+    let s: Vec<_> = ["ann", "bob", "cat", "dan", "eve"].iter()
+        .map(|n| Student::from(n.to_string())).collect();
+    for &st in s.iter() {
+        data.new_student(st, section);
+    }
+    for t in ["red", "blue", "green"].iter() {
+        data.new_team(Team::from(t.to_string()));
+    }
+    data.add_day();
+    data.run_shuffle(Day::from(0), ShuffleMode::Shuffle, None);
+    data.add_day();
+    let day = Day::from(1);
+    let before = data.pairings()[1].clone();
+
+    // Shuffles which happen to agree are only offered once, so check
+    // which modes are tried rather than which candidates survive.
+    let modes = strategies(&data);
+    assert!(modes.contains(&ShuffleMode::Grand));
+    assert!(!modes.iter().any(|&m| m == ShuffleMode::Clear || m == ShuffleMode::Balance));
+    let all = candidates(&data, day, None, None, 2);
+    assert!(!all.is_empty());
+    assert!(all.iter().all(|c| modes.contains(&c.mode)));
+    assert_eq!(data.pairings()[1], before, "previewing changed the day");
+    let candidates = candidates(&data, day, Some(ShuffleMode::Repeat), None, 3);
+    assert_eq!(candidates.len(), 1);
+    let repeat = &candidates[0];
+    let score = score(&data, day, &before, &repeat.pairings);
+    assert_eq!(score.repeats, 2);
+    assert_eq!(score.solos, 1);
+    assert_eq!(score.continuity, 5);
+    assert_eq!(score.changes, 5);
+    assert!(score.role_imbalance <= 5);
+    assert_eq!(diff(&repeat.pairings, &repeat.pairings), Vec::new());
+    assert_eq!(summary(&data, Day::from(0), None), "3 teams, 0 repeats, 1 solo");
+
+    data.set_preview(day, candidates.clone());
+    data.new_team(Team::from("gold".to_string()));
+    data.assign_student(day, s[0], section, Team::from("gold".to_string()));
+    assert!(data.accept_candidate(day, 0).is_err());
+    data.set_preview(day, candidates.clone());
+    assert!(data.accept_candidate(day, candidates.len()).is_err());
+    data.accept_candidate(day, 0).unwrap();
+    assert_eq!(data.pairings()[1], candidates[0].pairings);
+    assert!(data.preview(day).is_none());
+    assert!(data.check().is_empty());
+}
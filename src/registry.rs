@@ -0,0 +1,176 @@
+//! The registry of courses, which is simply the set of course files in
+//! the data directory, each of which knows its own title and owner.
+
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use database::Data;
+
+/// What we know about a course, apart from its contents.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct CourseInfo {
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub term: String,
+    #[serde(default)]
+    pub owner: String,
+    /// The date the course was created, as YYYY-MM-DD.
+    #[serde(default)]
+    pub created: String,
+    #[serde(default)]
+    pub archived: bool,
+}
+
+impl CourseInfo {
+    pub fn new(title: &str, term: &str, owner: &str) -> Self {
+        CourseInfo {
+            title: title.to_string(),
+            term: term.to_string(),
+            owner: owner.to_string(),
+            created: today(),
+            archived: false,
+        }
+    }
+    pub fn pretty(&self) -> String {
+        if self.title.is_empty() {
+            "Untitled course".to_string()
+        } else {
+            self.title.clone()
+        }
+    }
+}
+
+/// A summary of a course, as shown on the dashboard.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CourseSummary {
+    pub path: String,
+    pub info: CourseInfo,
+    pub num_students: usize,
+    pub num_days: usize,
+}
+
+/// Find all the courses in the data directory, most recently created
+/// first.
+pub fn list_courses<P: AsRef<Path>>(data_dir: P) -> Vec<CourseSummary> {
+    let mut courses = Vec::new();
+    let entries = match ::std::fs::read_dir(data_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("Unable to list courses: {}", e);
+            return courses;
+        }
+    };
+    for entry in entries.flatten() {
+        let file = entry.path();
+        if file.extension().map(|e| e != "yaml").unwrap_or(true) {
+            continue;
+        }
+        let path = match file.file_stem().and_then(|s| s.to_str()) {
+            Some(path) if is_course_path(path) => path.to_string(),
+            _ => continue,
+        };
+        if let Ok(data) = Data::open(&file) {
+            courses.push(CourseSummary {
+                path,
+                info: data.info().clone(),
+                num_students: data.list_students().len(),
+                num_days: data.list_days().len(),
+            });
+        }
+    }
+    courses.sort_by(|a, b| b.info.created.cmp(&a.info.created)
+                    .then_with(|| a.info.title.cmp(&b.info.title)));
+    courses
+}
+
+/// Is this a valid secret path for a course?
+pub fn is_course_path(path: &str) -> bool {
+    path.len() > 10 && path.chars().all(char::is_alphanumeric)
+}
+
+/// Open an existing course in the data directory.
+pub fn open_course<P: AsRef<Path>>(data_dir: P, path: &str) -> Result<Data, String> {
+    if !is_course_path(path) {
+        return Err(format!("Invalid course {:?}", path));
+    }
+    Data::open(data_dir.as_ref().join(format!("{}.yaml", path)))
+}
+
+/// A fresh secret path for a new course.
+pub fn new_course_path() -> String {
+    memorable_wordlist::camel_case(44)
+}
+
+/// Create a new course in `data_dir` with the structure of `original`,
+/// returning its secret path.  See `Data::clone_structure`.
+pub fn clone_course<P: AsRef<Path>>(data_dir: P, original: &Data, info: CourseInfo,
+                                    roster: bool) -> String {
+    let data_dir = data_dir.as_ref();
+    let mut path = new_course_path();
+    while data_dir.join(format!("{}.yaml", path)).exists() {
+        path = new_course_path();
+    }
+    original.clone_structure(data_dir, &path, info, roster).save();
+    path
+}
+
+/// Today's date (in UTC) as YYYY-MM-DD.
+pub fn today() -> String {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs()).unwrap_or(0);
+    format_date(secs / (24*60*60))
+}
+
+/// A time, in seconds since 1970, as YYYY-MM-DD HH:MM in UTC.
+pub fn format_time(secs: u64) -> String {
+    let minutes = secs / 60 % (24*60);
+    format!("{} {:02}:{:02}", format_date(secs / (24*60*60)), minutes / 60, minutes % 60)
+}
+
+/// Format a count of days since 1970-01-01 as YYYY-MM-DD.
+fn format_date(days: u64) -> String {
+    let (year, month, day) = civil_date(days);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Format a time, in seconds since 1970, as the date of an email
+/// (RFC 5322), e.g. "Mon, 19 Oct 2026 13:05:59 +0000".
+pub fn format_email_date(secs: u64) -> String {
+    const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun",
+                                "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    let days = secs / (24*60*60);
+    let (year, month, day) = civil_date(days);
+    let seconds = secs % (24*60*60);
+    format!("{}, {} {} {} {:02}:{:02}:{:02} +0000", WEEKDAYS[(days % 7) as usize], day,
+            MONTHS[month as usize - 1], year, seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+/// The year, month and day of a count of days since 1970-01-01, using
+/// the algorithm from Howard Hinnant's "chrono-Compatible Low-Level
+/// Date Algorithms".
+fn civil_date(days: u64) -> (i64, i64, i64) {
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe/1460 + doe/36_524 - doe/146_096) / 365;
+    let doy = doe - (365*yoe + yoe/4 - yoe/100);
+    let mp = (5*doy + 2)/153;
+    let day = doy - (153*mp + 2)/5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era*400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[test]
+fn test_format_date() {
+    assert_eq!(format_date(0), "1970-01-01");
+    assert_eq!(format_date(59), "1970-03-01");
+    assert_eq!(format_date(11_016), "2000-02-29");
+    assert_eq!(format_date(20_745), "2026-10-19");
+    assert_eq!(format_time(20_745*24*60*60 + 13*60*60 + 5*60 + 59), "2026-10-19 13:05");
+    assert_eq!(format_email_date(20_745*24*60*60 + 13*60*60 + 5*60 + 59),
+               "Mon, 19 Oct 2026 13:05:59 +0000");
+    assert_eq!(format_email_date(0), "Thu, 1 Jan 1970 00:00:00 +0000");
+}
@@ -0,0 +1,560 @@
+//! Keeping the roster in step with the registrar's list.  A roster
+//! provider reads who is enrolled in which section, whether from a csv
+//! file, a web service answering with JSON, or an LTI names and roles
+//! service.  A sync compares the enrollments with the roster, adds new
+//! students, moves those whose section changed, and marks those who
+//! are no longer enrolled as inactive, so that their history is kept.
+//! Each sync is logged with the course.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{self, Value};
+use ureq;
+
+use attributes;
+use links;
+use notify;
+use database::{Data, Section, Student, Zoom};
+
+/// How long a web service may take to answer.
+const TIMEOUT: Duration = Duration::from_secs(20);
+/// The most pages of a names and roles service we will follow.
+const MAX_PAGES: usize = 100;
+
+/// A student's enrollment, as the registrar has it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Enrollment {
+    pub student: Student,
+    pub section: Section,
+    pub email: Option<String>,
+}
+
+pub trait RosterProvider {
+    /// Read the students enrolled now.
+    fn fetch(&self) -> Result<Vec<Enrollment>, String>;
+    /// The sections the provider knows about, or `None` if it knows the
+    /// whole course.  Students in other sections are left alone.
+    fn sections(&self) -> Option<Vec<Section>> {
+        None
+    }
+}
+
+/// Where a course's roster comes from.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RosterSource {
+    /// A csv file on the server, see `CsvFile`.
+    Csv(PathBuf),
+    /// A web service answering with JSON, see `JsonRoster`.
+    Json(String),
+    /// An LTI names and roles service, see `NamesAndRoles`.
+    Lti { url: String, section: String, #[serde(default)] token: String },
+}
+
+impl RosterSource {
+    /// The source of `kind` "csv", "json" or "lti" at `location`, or
+    /// none for an empty `kind`.  An LTI source needs the `section` to
+    /// put its students in, and may need a `token`.
+    pub fn new(kind: &str, location: &str, section: &str, token: &str)
+               -> Result<Option<RosterSource>, String> {
+        let location = location.trim();
+        if !kind.is_empty() && kind != "csv" && !links::is_web_link(location) {
+            return Err(format!("{:?} does not start with http:// or https://", location));
+        }
+        match kind {
+            "" => Ok(None),
+            "csv" if location.is_empty() => Err("A csv roster needs a file".to_string()),
+            "csv" => Ok(Some(RosterSource::Csv(PathBuf::from(location)))),
+            "json" => Ok(Some(RosterSource::Json(location.to_string()))),
+            "lti" if section.trim().is_empty() => {
+                Err("An LTI roster needs a section to put its students in".to_string())
+            }
+            "lti" => Ok(Some(RosterSource::Lti {
+                url: location.to_string(),
+                section: section.trim().to_string(),
+                token: token.trim().to_string(),
+            })),
+            _ => Err(format!("Unknown kind of roster {:?}", kind)),
+        }
+    }
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            RosterSource::Csv(_) => "csv",
+            RosterSource::Json(_) => "json",
+            RosterSource::Lti { .. } => "lti",
+        }
+    }
+    pub fn location(&self) -> String {
+        match *self {
+            RosterSource::Csv(ref file) => file.display().to_string(),
+            RosterSource::Json(ref url) => url.clone(),
+            RosterSource::Lti { ref url, .. } => url.clone(),
+        }
+    }
+    pub fn provider(&self) -> Box<dyn RosterProvider> {
+        match *self {
+            RosterSource::Csv(ref file) => Box::new(CsvFile(file.clone())),
+            RosterSource::Json(ref url) => Box::new(JsonRoster(url.clone())),
+            RosterSource::Lti { ref url, ref section, ref token } => Box::new(NamesAndRoles {
+                url: url.clone(),
+                section: Section::from(section.clone()),
+                token: token.clone(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for RosterSource {
+    /// The source without its token, for showing and logging.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RosterSource::Csv(ref file) => write!(f, "csv {}", file.display()),
+            RosterSource::Json(ref url) => write!(f, "json {}", url),
+            RosterSource::Lti { ref url, ref section, .. } => {
+                write!(f, "lti {} (section {})", url, section)
+            }
+        }
+    }
+}
+
+/// A csv file with a header row naming its columns: the student's name
+/// (as "name" or "student"), their "section", and optionally their
+/// "email".
+pub struct CsvFile(pub PathBuf);
+
+impl RosterProvider for CsvFile {
+    fn fetch(&self) -> Result<Vec<Enrollment>, String> {
+        let text = ::std::fs::read_to_string(&self.0)
+            .map_err(|e| format!("Unable to read {:?}: {}", self.0, e))?;
+        CsvText(text).fetch()
+    }
+}
+
+/// Csv as read by `CsvFile`, e.g. pasted into a form.
+pub struct CsvText(pub String);
+
+impl RosterProvider for CsvText {
+    fn fetch(&self) -> Result<Vec<Enrollment>, String> {
+        let rows = attributes::parse_csv(&self.0)?;
+        let (header, rows) = rows.split_first().ok_or("The csv is empty")?;
+        let column = |names: &[&str]| header.iter()
+            .position(|h| names.contains(&h.trim().to_lowercase().as_str()));
+        let name = column(&["name", "student"]).ok_or("The csv has no name column")?;
+        let section = column(&["section"]).ok_or("The csv has no section column")?;
+        let email = column(&["email"]);
+        rows.iter().enumerate().map(|(i, row)| {
+            let cell = |c: usize| row.get(c).map(|v| v.trim()).unwrap_or("");
+            enrollment(cell(name), cell(section), email.map(cell))
+                .map_err(|e| format!("Line {}: {}", i+2, e))
+        }).collect()
+    }
+}
+
+/// A web service answering a GET with a JSON list of students, each an
+/// object with a "name", "section" and optionally "email", either on
+/// its own or as the "students" of an object.
+pub struct JsonRoster(pub String);
+
+impl RosterProvider for JsonRoster {
+    fn fetch(&self) -> Result<Vec<Enrollment>, String> {
+        let body = ureq::get(&self.0)
+            .timeout(TIMEOUT)
+            .set("Accept", "application/json")
+            .call()
+            .map_err(|e| format!("Error fetching {}: {}", self.0, e))?
+            .into_string()
+            .map_err(|e| format!("Error reading {}: {}", self.0, e))?;
+        parse_json(&body)
+    }
+}
+
+fn parse_json(body: &str) -> Result<Vec<Enrollment>, String> {
+    let value: Value = serde_json::from_str(body).map_err(|e| format!("Invalid JSON: {}", e))?;
+    let students = value.as_array().or_else(|| value["students"].as_array())
+        .ok_or("Expected a list of students")?;
+    students.iter().enumerate().map(|(i, s)| {
+        let text = |key: &str| s[key].as_str().unwrap_or("").trim();
+        let name = if text("name").is_empty() { text("student") } else { text("name") };
+        let email = Some(text("email")).filter(|e| !e.is_empty());
+        enrollment(name, text("section"), email).map_err(|e| format!("Student {}: {}", i+1, e))
+    }).collect()
+}
+
+/// An LTI names and roles service, whose learners are all put in one
+/// section, since the service does not know about sections.  Pages
+/// are followed by their "next" links.
+pub struct NamesAndRoles {
+    pub url: String,
+    pub section: Section,
+    /// The bearer token for the service, if it needs one.
+    pub token: String,
+}
+
+impl RosterProvider for NamesAndRoles {
+    fn fetch(&self) -> Result<Vec<Enrollment>, String> {
+        let mut enrollments = Vec::new();
+        let mut url = Some(self.url.clone());
+        for _ in 0..MAX_PAGES {
+            let page = match url.take() {
+                Some(page) => page,
+                None => return Ok(enrollments),
+            };
+            let mut request = ureq::get(&page)
+                .timeout(TIMEOUT)
+                .set("Accept", "application/vnd.ims.lti-nrps.v2.membershipcontainer+json");
+            if !self.token.is_empty() {
+                request = request.set("Authorization", &format!("Bearer {}", self.token));
+            }
+            let response = request.call().map_err(|e| format!("Error fetching {}: {}", page, e))?;
+            url = response.header("Link").and_then(next_link);
+            let body = response.into_string()
+                .map_err(|e| format!("Error reading {}: {}", page, e))?;
+            enrollments.extend(parse_names_and_roles(&body, self.section)?);
+        }
+        Err(format!("{} has more than {} pages", self.url, MAX_PAGES))
+    }
+    fn sections(&self) -> Option<Vec<Section>> {
+        Some(vec![self.section])
+    }
+}
+
+/// The learners of a names and roles membership container who are
+/// still active.
+fn parse_names_and_roles(body: &str, section: Section) -> Result<Vec<Enrollment>, String> {
+    let value: Value = serde_json::from_str(body).map_err(|e| format!("Invalid JSON: {}", e))?;
+    let members = value["members"].as_array().ok_or("Expected a list of members")?;
+    members.iter().filter(|m| {
+        let learner = m["roles"].as_array().map(|roles| roles.iter().any(|r| {
+            r.as_str().map(|r| r == "Learner" || r.ends_with("#Learner")).unwrap_or(false)
+        })).unwrap_or(false);
+        learner && m["status"].as_str().map(|s| s == "Active").unwrap_or(true)
+    }).enumerate().map(|(i, m)| {
+        let text = |key: &str| m[key].as_str().unwrap_or("").trim().to_string();
+        let mut name = text("name");
+        if name.is_empty() {
+            name = format!("{} {}", text("given_name"), text("family_name")).trim().to_string();
+        }
+        let email = Some(text("email")).filter(|e| !e.is_empty());
+        enrollment(&name, &section.name, email.as_deref())
+            .map_err(|e| format!("Member {}: {}", i+1, e))
+    }).collect()
+}
+
+/// The "next" link of a Link header, if any.
+fn next_link(header: &str) -> Option<String> {
+    header.split(',').find(|l| l.contains("rel=\"next\""))
+        .and_then(|l| Some(l[l.find('<')?+1..l.find('>')?].to_string()))
+}
+
+fn enrollment(name: &str, section: &str, email: Option<&str>) -> Result<Enrollment, String> {
+    if name.is_empty() {
+        return Err("A student has no name".to_string());
+    }
+    if section.is_empty() {
+        return Err(format!("{} has no section", name));
+    }
+    Ok(Enrollment {
+        student: Student::from(name.to_string()),
+        section: Section::from(section.to_string()),
+        email: email.filter(|e| !e.is_empty()).map(|e| e.to_string()),
+    })
+}
+
+/// What a sync changed, as logged with the course.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RosterSync {
+    /// When, in seconds since 1970.
+    pub time: u64,
+    /// Where the roster came from.
+    pub source: String,
+    /// The students added, or who were inactive and enrolled again.
+    pub added: Vec<(Student, Section)>,
+    /// The students marked inactive.
+    pub dropped: Vec<Student>,
+    /// The students whose section changed, from and to.
+    pub moved: Vec<(Student, Section, Section)>,
+    /// Anything from the provider which was not kept, for the caller to
+    /// report.  These are not logged.
+    #[serde(skip)]
+    pub warnings: Vec<String>,
+}
+
+impl RosterSync {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.dropped.is_empty() && self.moved.is_empty()
+    }
+    pub fn summary(&self) -> String {
+        let names = |students: Vec<&Student>| {
+            let names: Vec<_> = students.iter().map(|s| s.name.as_str()).collect();
+            names.join(", ")
+        };
+        let mut parts = Vec::new();
+        if !self.added.is_empty() {
+            parts.push(format!("added {}", names(self.added.iter().map(|(s, _)| s).collect())));
+        }
+        if !self.dropped.is_empty() {
+            parts.push(format!("dropped {}", names(self.dropped.iter().collect())));
+        }
+        if !self.moved.is_empty() {
+            let moves: Vec<_> = self.moved.iter()
+                .map(|(s, from, to)| format!("{} from {} to {}", s, from, to)).collect();
+            parts.push(format!("moved {}", moves.join(", ")));
+        }
+        if parts.is_empty() {
+            "no changes".to_string()
+        } else {
+            parts.join("; ")
+        }
+    }
+}
+
+/// Compare `enrollments` with the roster of `data`, only as far as
+/// `sections` if the enrollments cover no others.
+pub fn compare(data: &Data, enrollments: &[Enrollment], sections: Option<&[Section]>,
+               source: &str) -> Result<RosterSync, String> {
+    if enrollments.is_empty() {
+        return Err(format!("{} lists no students", source));
+    }
+    let mut enrolled: BTreeMap<Student, Section> = BTreeMap::new();
+    for e in enrollments {
+        if enrolled.insert(e.student, e.section).is_some() {
+            return Err(format!("{} lists {} more than once", source, e.student));
+        }
+    }
+    let roster: HashMap<Student, Section> = data.list_students().into_iter()
+        .filter_map(|s| data.student_section(s).map(|section| (s, section))).collect();
+    let mut sync = RosterSync {
+        time: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+        source: source.to_string(),
+        added: Vec::new(),
+        dropped: Vec::new(),
+        moved: Vec::new(),
+        warnings: Vec::new(),
+    };
+    let known = |section: &Section| sections.map(|s| s.contains(section)).unwrap_or(true);
+    for (&student, &section) in enrolled.iter() {
+        match roster.get(&student) {
+            Some(old) if !known(old) => (),
+            Some(&old) if data.is_active(student) => {
+                if old != section {
+                    sync.moved.push((student, old, section));
+                }
+            }
+            _ => sync.added.push((student, section)),
+        }
+    }
+    sync.dropped = data.list_students().into_iter()
+        .filter(|s| data.is_active(*s) && !enrolled.contains_key(s))
+        .filter(|s| roster.get(s).map(known).unwrap_or(true))
+        .collect();
+    Ok(sync)
+}
+
+/// Fetch the roster from `provider` and bring `data` into line with it,
+/// logging what changed.  Email addresses are taken from the provider
+/// when it has them, and any which are not valid are left out with a
+/// warning.  With `dry_run`, nothing is changed or logged.
+pub fn sync(data: &mut Data, provider: &dyn RosterProvider, source: &str, dry_run: bool)
+            -> Result<RosterSync, String> {
+    let enrollments = provider.fetch()?;
+    let mut sync = compare(data, &enrollments, provider.sections().as_deref(), source)?;
+    let mut emails = Vec::new();
+    for e in enrollments.iter() {
+        if let Some(ref email) = e.email {
+            match notify::check_email(email.trim()) {
+                Ok(()) => emails.push((e.student, email)),
+                Err(err) => sync.warnings.push(format!("Not keeping the email of {}: {}",
+                                                       e.student, err)),
+            }
+        }
+    }
+    if dry_run {
+        return Ok(sync);
+    }
+    let new_sections = sync.added.iter().map(|&(_, section)| section)
+        .chain(sync.moved.iter().map(|&(_, _, section)| section));
+    for section in new_sections {
+        if !data.list_sections().contains(&section) {
+            data.new_section(section, Zoom::from("".to_string()));
+        }
+    }
+    for &(student, section) in sync.added.iter() {
+        match data.student_section(student) {
+            Some(old) if old == section => (),
+            Some(_) => data.rename_student(student, student, section)?,
+            None => data.new_student(student, section),
+        }
+        data.set_active(student, true);
+    }
+    for &(student, _, section) in sync.moved.iter() {
+        data.rename_student(student, student, section)?;
+    }
+    for &student in sync.dropped.iter() {
+        data.set_active(student, false);
+    }
+    for (student, email) in emails {
+        if data.student_email(student) != Some(email.trim()) {
+            data.set_email(student, email)?;
+        }
+    }
+    if !sync.is_empty() {
+        data.log_roster_sync(sync.clone());
+    }
+    Ok(sync)
+}
+
+#[test]
+fn test_sync() {
+    use database::{Day, ShuffleMode, Team};
+    let mut data = Data::empty("testcourse", "testcourse.yaml");
+    let (a, b) = (Section::from("A".to_string()), Section::from("B".to_string()));
+    data.new_section(a, Zoom::from("".to_string()));
+    data.new_section(b, Zoom::from("".to_string()));
+    let s: Vec<_> = ["ann", "bob", "cat", "dan"].iter()
+        .map(|n| Student::from(n.to_string())).collect();
+    for &st in s.iter() {
+        data.new_student(st, a);
+    }
+    for t in ["red", "blue"].iter() {
+        data.new_team(Team::from(t.to_string()));
+    }
+    data.add_day();
+    data.run_shuffle(Day::from(0), ShuffleMode::Shuffle, None);
+
+    let csv = CsvText("Email,Section,Name\nann@example.com,A,ann\n,B,bob\ncat,A,cat\n,C,eve\n"
+                      .to_string());
+    let plan = sync(&mut data, &csv, "csv", true).unwrap();
+    assert_eq!(data.list_students().len(), 4, "a dry run changed the roster");
+    assert!(data.roster_log().is_empty());
+    let done = sync(&mut data, &csv, "csv", false).unwrap();
+    assert_eq!(plan, done);
+    assert_eq!(plan.warnings, vec!["Not keeping the email of cat: \"cat\" is not an email address"
+                                   .to_string()]);
+    let eve = Student::from("eve".to_string());
+    assert_eq!(done.added, vec![(eve, Section::from("C".to_string()))]);
+    assert_eq!(done.dropped, vec![s[3]]);
+    assert_eq!(done.moved, vec![(s[1], a, b)]);
+    assert_eq!(data.roster_log(), &[done]);
+    assert_eq!(data.student_section(s[1]), Some(b));
+    assert_eq!(data.student_email(s[0]), Some("ann@example.com"));
+    assert_eq!(data.student_email(s[2]), None);
+    assert!(data.list_sections().contains(&Section::from("C".to_string())));
+    // Dropped students keep their history but get no new pairings.
+    assert!(!data.is_active(s[3]));
+    assert_eq!(data.list_students().len(), 5);
+    assert!(data.pairings()[0].iter().any(|p| p.allocated_students().contains(&s[3])));
+    data.add_day();
+    data.run_shuffle(Day::from(1), ShuffleMode::Shuffle, None);
+    assert!(!data.pairings()[1].iter().any(|p| p.allocated_students().contains(&s[3])));
+    assert!(!data.unassigned_students(Day::from(1)).contains(&s[3]));
+
+    // Syncing again changes nothing, and enrolling again reactivates.
+    assert!(sync(&mut data, &csv, "csv", false).unwrap().is_empty());
+    assert_eq!(data.roster_log().len(), 1);
+    let csv = CsvText("name,section\nann,A\nbob,B\ncat,A\neve,C\ndan,A\n".to_string());
+    assert_eq!(sync(&mut data, &csv, "csv", false).unwrap().added, vec![(s[3], a)]);
+    assert!(data.is_active(s[3]));
+
+    assert!(sync(&mut data, &CsvText("name,section\n".to_string()), "csv", false).is_err());
+    assert!(sync(&mut data, &CsvText("name,section\nann,A\nann,B\n".to_string()), "csv", false)
+            .is_err());
+    assert!(sync(&mut data, &CsvText("name\nann\n".to_string()), "csv", false).is_err());
+}
+
+#[test]
+fn test_json_roster() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    // A stub registrar, which answers one request.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/roster", listener.local_addr().unwrap());
+    let stub = ::std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 2 {
+            line.clear();
+        }
+        let body = r#"{"students": [{"name": "ann", "section": "A", "email": "ann@example.com"},
+                                    {"student": "bob", "section": "B"}]}"#;
+        write!(&stream, "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                         Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+    });
+    let enrollments = JsonRoster(url).fetch().unwrap();
+    stub.join().unwrap();
+    assert_eq!(enrollments.len(), 2);
+    assert_eq!(enrollments[0].email, Some("ann@example.com".to_string()));
+    assert_eq!(enrollments[1].section, Section::from("B".to_string()));
+    assert!(parse_json(r#"[{"name": "ann"}]"#).is_err());
+}
+
+#[test]
+fn test_names_and_roles() {
+    let body = r#"{
+      "id": "https://lms.example.com/sections/2923/memberships",
+      "context": {"id": "2923-abc", "title": "Physics"},
+      "members": [
+        {"status": "Active", "name": "Jane Q. Public", "email": "jane@example.edu",
+         "user_id": "0ae836b9", "roles": ["http://purl.imsglobal.org/vocab/lis/v2/membership#Learner"]},
+        {"status": "Inactive", "name": "Gone Student", "user_id": "1",
+         "roles": ["http://purl.imsglobal.org/vocab/lis/v2/membership#Learner"]},
+        {"name": "Prof", "user_id": "2",
+         "roles": ["http://purl.imsglobal.org/vocab/lis/v2/membership#Instructor"]},
+        {"given_name": "Sam", "family_name": "Lee", "user_id": "3", "roles": ["Learner"]}
+      ]
+    }"#;
+    let section = Section::from("Lab".to_string());
+    let enrollments = parse_names_and_roles(body, section).unwrap();
+    let names: Vec<_> = enrollments.iter().map(|e| e.student.name.as_str()).collect();
+    assert_eq!(names, vec!["Jane Q. Public", "Sam Lee"]);
+    assert_eq!(enrollments[0].email, Some("jane@example.edu".to_string()));
+    assert!(enrollments.iter().all(|e| e.section == section));
+    assert_eq!(next_link("<https://lms.example.com/m?p=2>; rel=\"next\", \
+                         <https://lms.example.com/m?p=1>; rel=\"first\""),
+               Some("https://lms.example.com/m?p=2".to_string()));
+    assert_eq!(next_link("<https://lms.example.com/m?p=1>; rel=\"first\""), None);
+}
+
+#[test]
+fn test_sync_one_section() {
+    /// A names and roles service which reports on section A alone.
+    struct Lms(Section);
+    impl RosterProvider for Lms {
+        fn fetch(&self) -> Result<Vec<Enrollment>, String> {
+            parse_names_and_roles(r#"{"members": [
+              {"name": "ann", "roles": ["Learner"]},
+              {"name": "bob", "roles": ["Learner"]},
+              {"name": "eve", "roles": ["Learner"]}
+            ]}"#, self.0)
+        }
+        fn sections(&self) -> Option<Vec<Section>> {
+            Some(vec![self.0])
+        }
+    }
+    let mut data = Data::empty("testcourse", "testcourse.yaml");
+    let (a, b) = (Section::from("A".to_string()), Section::from("B".to_string()));
+    data.new_section(a, Zoom::from("".to_string()));
+    data.new_section(b, Zoom::from("".to_string()));
+    let s: Vec<_> = ["ann", "cat"].iter().map(|n| Student::from(n.to_string())).collect();
+    for &st in s.iter() {
+        data.new_student(st, a);
+    }
+    let (bob, dan) = (Student::from("bob".to_string()), Student::from("dan".to_string()));
+    data.new_student(bob, b);
+    data.new_student(dan, b);
+
+    let done = sync(&mut data, &Lms(a), "lti", false).unwrap();
+    // Only cat, who is in section A, is dropped; bob and dan in section
+    // B are left where they are.
+    assert_eq!(done.added, vec![(Student::from("eve".to_string()), a)]);
+    assert_eq!(done.dropped, vec![s[1]]);
+    assert!(done.moved.is_empty());
+    assert_eq!(data.student_section(bob), Some(b));
+    assert!(data.is_active(bob) && data.is_active(dan));
+    assert!(!data.is_active(s[1]));
+    let source = NamesAndRoles { url: String::new(), section: a, token: String::new() };
+    assert_eq!(source.sections(), Some(vec![a]));
+}
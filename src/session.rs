@@ -0,0 +1,180 @@
+//! Browser sessions.  Each session has a secret token which every form
+//! must send back (as the hidden `csrf` field of `csrf.html`), to
+//! protect against cross-site request forgery, and flash messages
+//! reporting what a form changed, which are shown on the page the
+//! browser is redirected to afterwards.  A session is only started when
+//! a page with a form is shown, so that merely looking at a page does
+//! not.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use askama::Template;
+use rouille::{Request, Response};
+
+/// The name of the session cookie.
+pub const COOKIE: &str = "pairs-session";
+/// How long, in seconds, a session lasts without being used.
+pub const TIMEOUT: u64 = 7*24*60*60;
+/// The name of the form field carrying the token.
+const TOKEN: &str = "csrf";
+
+struct Session {
+    token: String,
+    flash: Vec<String>,
+    last_used: u64,
+}
+
+#[derive(Template)]
+#[template(path = "flash.html")]
+struct Flash<'a> {
+    messages: &'a [String],
+}
+
+#[derive(Default)]
+pub struct Sessions {
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+impl Sessions {
+    /// Use session `id`, starting it if need be.
+    fn with<T>(&self, id: &str, f: impl FnOnce(&mut Session) -> T) -> T {
+        let now = now();
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, s| s.last_used + TIMEOUT > now);
+        let session = sessions.entry(id.to_string()).or_insert_with(|| Session {
+            token: ::rouille::session::generate_session_id(),
+            flash: Vec::new(),
+            last_used: now,
+        });
+        session.last_used = now;
+        f(session)
+    }
+    /// Use session `id` if it has been started and not yet expired.
+    fn existing<T>(&self, id: &str, f: impl FnOnce(&mut Session) -> T) -> Option<T> {
+        let now = now();
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.get_mut(id).filter(|s| s.last_used + TIMEOUT > now).map(|session| {
+            session.last_used = now;
+            f(session)
+        })
+    }
+    /// The token for the forms of session `id`, which is started if it
+    /// has not been already.
+    pub fn token(&self, id: &str) -> String {
+        self.with(id, |s| s.token.clone())
+    }
+    /// Check that a form posted in session `id` carries its token,
+    /// returning the request with the form still to be read.
+    pub fn check(&self, id: Option<&str>, request: &Request) -> Result<Request, Response> {
+        let mut body = Vec::new();
+        if let Some(mut data) = request.data() {
+            data.read_to_end(&mut body).map_err(|_| Response::empty_400())?;
+        }
+        let token = id.and_then(|id| self.existing(id, |s| s.token.clone()));
+        let valid = match (token, form_field(&body, TOKEN)) {
+            (Some(token), Some(posted)) => same_bytes(token.as_bytes(), posted.as_bytes()),
+            _ => false,
+        };
+        if !valid {
+            return Err(Response::text("This form has expired.  \
+                                       Please reload the page and try again.")
+                       .with_status_code(403));
+        }
+        let headers = request.headers().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        let (from, method, url) = (*request.remote_addr(), request.method(), request.raw_url());
+        Ok(if request.is_secure() {
+            Request::fake_https_from(from, method, url, headers, body)
+        } else {
+            Request::fake_http_from(from, method, url, headers, body)
+        })
+    }
+    /// Remember a message to show on the next page of session `id`.
+    pub fn flash(&self, id: &str, message: String) {
+        self.with(id, |s| s.flash.push(message));
+    }
+    /// A page for session `id`, if there is one, which shows (and
+    /// forgets) any flash messages.
+    pub fn page(&self, id: Option<&str>, html: String) -> Response {
+        let flash = id.and_then(|id| self.existing(id, |s| ::std::mem::take(&mut s.flash)))
+            .unwrap_or_default();
+        let mut html = html;
+        if !flash.is_empty() {
+            let messages = Flash { messages: &flash }.render().unwrap();
+            if let Some(i) = html.find("<article>") {
+                html.insert_str(i + "<article>".len(), &messages);
+            }
+        }
+        Response::html(html)
+    }
+}
+
+/// The value of field `name` of a url-encoded form, which is taken as
+/// is, since we only look for tokens, which need no decoding.
+fn form_field<'a>(body: &'a [u8], name: &str) -> Option<&'a str> {
+    ::std::str::from_utf8(body).ok()?.split('&')
+        .filter_map(|field| {
+            let mut kv = field.splitn(2, '=');
+            Some((kv.next()?, kv.next().unwrap_or("")))
+        })
+        .find(|&(k, _)| k == name)
+        .map(|(_, v)| v)
+}
+
+/// Whether `a` and `b` are equal, taking as long to find out wherever
+/// they differ, so that a token cannot be guessed a byte at a time.
+fn same_bytes(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+#[test]
+fn test_flash() {
+    let sessions = Sessions::default();
+    sessions.flash("a", "Added <b>".to_string());
+    let page = |id| {
+        let mut body = String::new();
+        let response = sessions.page(id, "<article><form method=\"post\">".to_string());
+        ::std::io::Read::read_to_string(&mut response.data.into_reader_and_size().0,
+                                        &mut body).unwrap();
+        body
+    };
+    let first = page(Some("a"));
+    assert!(first.contains("Added &lt;b&gt;"), "{}", first);
+    assert!(!page(Some("a")).contains("Added"));
+    assert!(!page(Some("b")).contains("Added"));
+    assert!(!page(None).contains("Added"));
+    // Showing a page starts no session; showing a form does.
+    assert!(sessions.existing("b", |_| ()).is_none());
+    let token = sessions.token("a");
+    assert_ne!(token, sessions.token("b"));
+    assert_eq!(sessions.sessions.lock().unwrap().len(), 2);
+}
+
+#[test]
+fn test_check() {
+    let sessions = Sessions::default();
+    let token = sessions.token("a");
+    let post = |body: String| Request::fake_http("POST", "/pairs/x/teams?csrf=no", vec![],
+                                                 body.into_bytes());
+    let form = format!("oldname=&newname=red&csrf={}", token);
+    let request = sessions.check(Some("a"), &post(form.clone())).unwrap();
+    let mut body = String::new();
+    request.data().unwrap().read_to_string(&mut body).unwrap();
+    assert_eq!(body, form);
+    assert_eq!(request.raw_url(), "/pairs/x/teams?csrf=no");
+    // The token must be in the form itself, and belong to the session.
+    assert!(sessions.check(Some("a"), &post("newname=red".to_string())).is_err());
+    assert!(sessions.check(Some("b"), &post(form.clone())).is_err());
+    assert!(sessions.check(None, &post(form)).is_err());
+    assert!(sessions.check(Some("a"), &post(format!("csrf={}x", token))).is_err());
+    let other = if token.starts_with('x') { 'y' } else { 'x' };
+    assert!(sessions.check(Some("a"), &post(format!("csrf={}{}", other, &token[1..]))).is_err());
+    assert!(same_bytes(b"abc", b"abc"));
+    assert!(!same_bytes(b"abc", b"abd") && !same_bytes(b"abc", b"ab"));
+}
@@ -0,0 +1,185 @@
+//! A day's pairings laid out for people rather than forms: printed and
+//! taped to the door, shown on a projector, or exported as text.
+
+use database::{Data, Day, Pairing, Section, Student, Team};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SheetTeam {
+    pub team: Team,
+    pub partners: Vec<Student>,
+    /// The team's links, labelled, see `links`.
+    pub links: Vec<(String, String)>,
+}
+
+impl SheetTeam {
+    pub fn partners_string(&self) -> String {
+        let names: Vec<_> = self.partners.iter().map(|s| s.name.as_str()).collect();
+        names.join(" and ")
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SheetSection {
+    pub section: Section,
+    /// The link to the section's meeting, if it has one.
+    pub meeting: String,
+    pub teams: Vec<SheetTeam>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Sheet {
+    pub day: Day,
+    pub sections: Vec<SheetSection>,
+    pub unassigned: Vec<Student>,
+    pub absent: Vec<Student>,
+}
+
+/// The teams of `day` by section, with the links to each section's
+/// meeting and to each team's resources.
+pub fn day_sheet(data: &Data, day: Day) -> Sheet {
+    let zooms = data.get_zooms();
+    let sections = data.team_options(day).into_iter().map(|(section, teams)| {
+        SheetSection {
+            section,
+            meeting: zooms.get(&section).map(|&z| data.links().meeting(section, z))
+                .unwrap_or_default(),
+            teams: teams.into_iter().filter_map(|t| match t.current_pairing {
+                Pairing::Pair { primary, secondary, .. } => Some(vec![primary, secondary]),
+                Pairing::Solo { student, .. } => Some(vec![student]),
+                _ => None,
+            }.map(|partners| SheetTeam {
+                team: t.team,
+                partners,
+                links: data.team_links(section, t.team),
+            })).collect(),
+        }
+    }).collect();
+    let mut absent = data.absent_students(day);
+    absent.sort();
+    let mut unassigned = data.unassigned_students(day);
+    unassigned.retain(|s| !absent.contains(s));
+    unassigned.sort();
+    Sheet { day, sections, unassigned, absent }
+}
+
+/// The sheet as plain text, one line per team.
+pub fn to_text(sheet: &Sheet) -> String {
+    let mut out = format!("{}\n", sheet.day.pretty());
+    for s in sheet.sections.iter() {
+        out.push_str(&format!("\nSection {}\n", s.section));
+        if !s.meeting.is_empty() {
+            out.push_str(&format!("Meeting: {}\n", s.meeting));
+        }
+        let width = s.teams.iter().map(|t| t.team.name.chars().count()).max().unwrap_or(0);
+        for t in s.teams.iter() {
+            out.push_str(&format!("  {:<w$}  {}\n", t.team.name.as_str(), t.partners_string(),
+                                  w = width));
+            for (label, link) in t.links.iter() {
+                out.push_str(&format!("  {:<w$}    {}: {}\n", "", label, link, w = width));
+            }
+        }
+    }
+    for (label, students) in [("Unassigned", &sheet.unassigned), ("Absent", &sheet.absent)] {
+        if !students.is_empty() {
+            let names: Vec<_> = students.iter().map(|s| s.name.as_str()).collect();
+            out.push_str(&format!("\n{}: {}\n", label, names.join(", ")));
+        }
+    }
+    out
+}
+
+/// The sheet as Markdown, with a table of teams for each section.
+pub fn to_markdown(sheet: &Sheet) -> String {
+    let mut out = format!("# {}\n", markdown_escape(&sheet.day.pretty()));
+    for s in sheet.sections.iter() {
+        out.push_str(&format!("\n## Section {}\n\n", markdown_escape(&s.section.name)));
+        if !s.meeting.is_empty() {
+            out.push_str(&format!("Meeting: <{}>\n\n", s.meeting));
+        }
+        let with_links = s.teams.iter().any(|t| !t.links.is_empty());
+        out.push_str(if with_links {
+            "| Team | Partners | Links |\n| --- | --- | --- |\n"
+        } else {
+            "| Team | Partners |\n| --- | --- |\n"
+        });
+        for t in s.teams.iter() {
+            out.push_str(&format!("| {} | {} |", markdown_escape(&t.team.name),
+                                  markdown_escape(&t.partners_string())));
+            if with_links {
+                let links: Vec<_> = t.links.iter()
+                    .map(|(label, link)| format!("[{}](<{}>)", markdown_escape(label), link))
+                    .collect();
+                out.push_str(&format!(" {} |", links.join(" ")));
+            }
+            out.push('\n');
+        }
+    }
+    for (label, students) in [("Unassigned", &sheet.unassigned), ("Absent", &sheet.absent)] {
+        if !students.is_empty() {
+            let names: Vec<_> = students.iter().map(|s| markdown_escape(&s.name)).collect();
+            out.push_str(&format!("\n**{}:** {}\n", label, names.join(", ")));
+        }
+    }
+    out
+}
+
+/// Escape the characters that Markdown (or html within it) would
+/// otherwise interpret, so that names appear as written.
+fn markdown_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '\\' | '`' | '*' | '_' | '[' | ']' | '|' | '#' => {
+                out.push('\\');
+                out.push(c);
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[test]
+fn test_sheet() {
+    use database::{ShuffleMode, Zoom};
+    let mut data = Data::empty("testcourse", "testcourse.yaml");
+    let section = Section::from("A".to_string());
+    data.new_section(section, Zoom::from("https://example.com/a".to_string()));
+    for name in &["ann", "bob", "cat", "dan|jr"] {
+        data.new_student(Student::from(name.to_string()), section);
+    }
+    for name in &["red", "blue"] {
+        data.new_team(Team::from(name.to_string()));
+    }
+    data.add_day();
+    let day = Day::from(0);
+    data.run_shuffle(day, ShuffleMode::Shuffle, None);
+    data.assign_student(day, Student::from("cat".to_string()), Section::from("".to_string()),
+                        Team::from("".to_string()));
+    let sheet = day_sheet(&data, day);
+    assert_eq!(sheet.sections.len(), 1);
+    assert_eq!(sheet.sections[0].meeting, "https://example.com/a");
+    let placed: usize = sheet.sections[0].teams.iter().map(|t| t.partners.len()).sum();
+    assert_eq!(placed + sheet.unassigned.len(), 3);
+    assert_eq!(sheet.absent, vec![Student::from("cat".to_string())]);
+
+    let text = to_text(&sheet);
+    assert!(text.starts_with("Day 0\n\nSection A\nMeeting: https://example.com/a\n"), "{}", text);
+    assert!(text.ends_with("\nAbsent: cat\n"), "{}", text);
+    let markdown = to_markdown(&sheet);
+    assert!(markdown.contains("| Team | Partners |\n"), "{}", markdown);
+    assert!(markdown.contains("Meeting: <https://example.com/a>\n"), "{}", markdown);
+    assert!(markdown.contains("dan\\|jr"), "{}", markdown);
+    assert!(!markdown.contains("dan|jr"), "{}", markdown);
+
+    let mut links = data.links().clone();
+    links.set_team_link("Board", "https://wb.example.com/{team}").unwrap();
+    data.set_links(links);
+    let sheet = day_sheet(&data, day);
+    assert!(to_text(&sheet).contains("    Board: https://wb.example.com/"));
+    assert!(to_markdown(&sheet).contains("| Links |\n"));
+    assert!(to_markdown(&sheet).contains(" [Board](<https://wb.example.com/"));
+}
@@ -0,0 +1,384 @@
+//! Participation and pairing statistics over the days of a course.
+
+use std::collections::{HashMap, HashSet};
+
+use database::{Data, Day, Pairing, Section, Student};
+
+/// What happened to one student over the term.  Days before the first
+/// day on which the student appears are not counted, nor are the days
+/// after a student who has dropped the course last appears.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StudentStats {
+    pub student: Student,
+    pub section: Section,
+    /// The number of days since the student first appeared.
+    pub days: usize,
+    pub attended: usize,
+    pub distinct_partners: usize,
+    /// The number of days on which the student worked with someone
+    /// they had already worked with.
+    pub repeats: usize,
+    pub solos: usize,
+    /// The number of days the student was present but not on a team.
+    pub unassigned: usize,
+    /// The number of days as primary (the driver) of a pair.
+    pub driver: usize,
+    /// The number of days as secondary (the navigator) of a pair.
+    pub navigator: usize,
+    pub longest_absence: usize,
+    /// Whether this student has had notably more bad luck than the rest
+    /// of their section.
+    pub flagged: bool,
+}
+
+impl StudentStats {
+    /// How often the student has been dealt a bad outcome by the
+    /// shuffle: working solo, repeating a partner, or sitting
+    /// unassigned.
+    pub fn bad_luck(&self) -> usize {
+        self.solos + self.repeats + self.unassigned
+    }
+    /// How many more times the student has driven than navigated.
+    pub fn role_balance(&self) -> isize {
+        self.driver as isize - self.navigator as isize
+    }
+    pub fn attendance_rate(&self) -> f64 {
+        if self.days == 0 {
+            0.0
+        } else {
+            self.attended as f64 / self.days as f64
+        }
+    }
+    pub fn attendance_percent(&self) -> String {
+        format!("{:.0}%", 100.0*self.attendance_rate())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SectionStats {
+    pub section: Section,
+    pub students: usize,
+    pub mean_attendance: f64,
+    pub mean_distinct_partners: f64,
+    pub repeats: usize,
+    pub solos: usize,
+    pub unassigned: usize,
+}
+
+impl SectionStats {
+    pub fn attendance_percent(&self) -> String {
+        format!("{:.0}%", 100.0*self.mean_attendance)
+    }
+    pub fn partners(&self) -> String {
+        format!("{:.1}", self.mean_distinct_partners)
+    }
+}
+
+/// The columns by which the student statistics may be sorted.
+pub const COLUMNS: &[&str] = &["student", "section", "attendance", "partners",
+                               "repeats", "solos", "unassigned", "absence", "luck",
+                               "driver", "navigator"];
+
+pub fn student_stats(data: &Data) -> Vec<StudentStats> {
+    student_stats_before(data, Day::from(data.pairings().len()))
+}
+
+/// The bad luck of each student prior to `day`.
+pub fn bad_luck(data: &Data, day: Day) -> HashMap<Student, usize> {
+    student_stats_before(data, day).into_iter().map(|s| (s.student, s.bad_luck())).collect()
+}
+
+/// The `StudentStats::role_balance` of each student prior to `day`.
+pub fn role_balance(data: &Data, day: Day) -> HashMap<Student, isize> {
+    student_stats_before(data, day).into_iter().map(|s| (s.student, s.role_balance())).collect()
+}
+
+/// The statistics for the days prior to `day`.
+pub fn student_stats_before(data: &Data, day: Day) -> Vec<StudentStats> {
+    let pairings = &data.pairings()[..::std::cmp::min(day.id, data.pairings().len())];
+    let mut stats = Vec::new();
+    for student in data.list_students() {
+        let appears = |d: &HashSet<Pairing>| d.iter().any(|p| p.allocated_students()
+                                                           .contains(&student));
+        let first = match pairings.iter().position(appears) {
+            Some(first) => first,
+            None => pairings.len(),
+        };
+        let last = if data.is_active(student) {
+            pairings.len()
+        } else {
+            pairings.iter().rposition(appears).map_or(first, |last| last + 1)
+        };
+        let mut st = StudentStats {
+            student,
+            section: data.student_section(student).unwrap(),
+            days: last - first,
+            attended: 0,
+            distinct_partners: 0,
+            repeats: 0,
+            solos: 0,
+            unassigned: 0,
+            driver: 0,
+            navigator: 0,
+            longest_absence: 0,
+            flagged: false,
+        };
+        let mut partners = HashSet::new();
+        let mut absence = 0;
+        for day in pairings[first..last].iter() {
+            match day.iter().find(|p| p.allocated_students().contains(&student)) {
+                Some(&Pairing::Absent(_)) => {
+                    absence += 1;
+                    st.longest_absence = ::std::cmp::max(st.longest_absence, absence);
+                    continue;
+                }
+                Some(&Pairing::Pair { primary, secondary, .. }) => {
+                    let partner = if primary == student {
+                        st.driver += 1;
+                        secondary
+                    } else {
+                        st.navigator += 1;
+                        primary
+                    };
+                    if !partners.insert(partner) {
+                        st.repeats += 1;
+                    }
+                }
+                Some(&Pairing::Solo { .. }) => {
+                    st.solos += 1;
+                }
+                Some(&Pairing::Unassigned { .. }) | None => {
+                    st.unassigned += 1;
+                }
+            }
+            absence = 0;
+            st.attended += 1;
+        }
+        st.distinct_partners = partners.len();
+        stats.push(st);
+    }
+    flag_unlucky(data, &mut stats);
+    stats
+}
+
+/// Flag the students still taking the course whose bad luck is at
+/// least two, and more than one above the average for their section.
+fn flag_unlucky(data: &Data, stats: &mut [StudentStats]) {
+    let mut totals: HashMap<Section, (usize, usize)> = HashMap::new();
+    for s in stats.iter().filter(|s| data.is_active(s.student)) {
+        let t = totals.entry(s.section).or_insert((0, 0));
+        t.0 += s.bad_luck();
+        t.1 += 1;
+    }
+    for s in stats.iter_mut().filter(|s| data.is_active(s.student)) {
+        let (total, n) = totals[&s.section];
+        let mean = total as f64 / n as f64;
+        s.flagged = s.bad_luck() >= 2 && s.bad_luck() as f64 > mean + 1.0;
+    }
+}
+
+pub fn section_stats(data: &Data, students: &[StudentStats]) -> Vec<SectionStats> {
+    let mut stats = Vec::new();
+    for section in data.list_sections() {
+        let students: Vec<_> = students.iter().filter(|s| s.section == section).collect();
+        let n = students.len();
+        let mean = |f: &dyn Fn(&StudentStats) -> f64| {
+            if n == 0 {
+                0.0
+            } else {
+                students.iter().map(|s| f(s)).sum::<f64>() / n as f64
+            }
+        };
+        stats.push(SectionStats {
+            section,
+            students: n,
+            mean_attendance: mean(&|s| s.attendance_rate()),
+            mean_distinct_partners: mean(&|s| s.distinct_partners as f64),
+            repeats: students.iter().map(|s| s.repeats).sum(),
+            solos: students.iter().map(|s| s.solos).sum(),
+            unassigned: students.iter().map(|s| s.unassigned).sum(),
+        });
+    }
+    stats
+}
+
+/// Sort by one of the `COLUMNS`.  Counts are sorted largest first,
+/// since those are the students we want to notice.
+pub fn sort_students(stats: &mut [StudentStats], column: &str) {
+    stats.sort_by(|a, b| match column {
+        "section" => a.section.cmp(&b.section),
+        "attendance" => a.attendance_rate().partial_cmp(&b.attendance_rate()).unwrap(),
+        "partners" => a.distinct_partners.cmp(&b.distinct_partners),
+        "repeats" => b.repeats.cmp(&a.repeats),
+        "solos" => b.solos.cmp(&a.solos),
+        "unassigned" => b.unassigned.cmp(&a.unassigned),
+        "absence" => b.longest_absence.cmp(&a.longest_absence),
+        "luck" => b.bad_luck().cmp(&a.bad_luck()),
+        "driver" => b.driver.cmp(&a.driver),
+        "navigator" => b.navigator.cmp(&a.navigator),
+        _ => ::std::cmp::Ordering::Equal,
+    }.then_with(|| a.student.cmp(&b.student)));
+}
+
+/// The student statistics as comma separated values.
+pub fn to_csv(stats: &[StudentStats]) -> String {
+    let mut csv = "student,section,days,attended,attendance_rate,distinct_partners,\
+                   repeats,solos,unassigned,longest_absence,bad_luck,driver,navigator\n"
+        .to_string();
+    for s in stats {
+        csv.push_str(&format!("{},{},{},{},{:.3},{},{},{},{},{},{},{},{}\n",
+                              csv_field(&s.student.name), csv_field(&s.section.name),
+                              s.days, s.attended, s.attendance_rate(),
+                              s.distinct_partners, s.repeats, s.solos, s.unassigned,
+                              s.longest_absence, s.bad_luck(), s.driver, s.navigator));
+    }
+    csv
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains(&[',', '"', '\n'][..]) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+#[test]
+fn test_student_stats() {
+    use database::{Day, Team, Zoom};
+    let mut data = Data::empty("testcourse", "testcourse.yaml");
+    let section = Section::from("A".to_string());
+    data.new_section(section, Zoom::from("".to_string()));
+    let names = ["ann", "bob", "cat", "dan, jr."];
+    let s: Vec<_> = names.iter().map(|n| Student::from(n.to_string())).collect();
+    for &st in s.iter() {
+        data.new_student(st, section);
+    }
+    let red = Team::from("red".to_string());
+    let blue = Team::from("blue".to_string());
+    data.new_team(red);
+    data.new_team(blue);
+    // Day 0: ann+bob, cat solo, dan absent
+    // Day 1: ann+bob again, cat+dan
+    // Day 2: ann absent, bob+cat, dan unassigned
+    // Day 3: ann absent, bob solo, cat+dan again
+    let days: Vec<Vec<(usize, Option<Team>)>> = vec![
+        vec![(0, Some(red)), (1, Some(red)), (2, Some(blue)), (3, None)],
+        vec![(0, Some(red)), (1, Some(red)), (2, Some(blue)), (3, Some(blue))],
+        vec![(0, None), (1, Some(red)), (2, Some(red))],
+        vec![(0, None), (1, Some(red)), (2, Some(blue)), (3, Some(blue))],
+    ];
+    for (id, assignments) in days.into_iter().enumerate() {
+        data.add_day();
+        for (i, team) in assignments {
+            match team {
+                Some(team) => data.assign_student(Day::from(id), s[i], section, team),
+                None => data.assign_student(Day::from(id), s[i],
+                                            Section::from("".to_string()), red),
+            }
+        }
+    }
+    data.unpair_student(Day::from(2), s[3]);
+    let stats = student_stats(&data);
+    let get = |i: usize| stats.iter().find(|st| st.student == s[i]).unwrap();
+    assert_eq!((get(0).attended, get(0).longest_absence, get(0).repeats), (2, 2, 1));
+    assert_eq!(get(0).attendance_percent(), "50%");
+    assert_eq!((get(1).distinct_partners, get(1).repeats, get(1).solos), (2, 1, 1));
+    assert_eq!((get(2).distinct_partners, get(2).solos), (2, 1));
+    assert_eq!((get(3).attended, get(3).unassigned, get(3).repeats), (3, 1, 1));
+    assert_eq!(get(0).driver + get(0).navigator, 2);
+
+    let sections = section_stats(&data, &stats);
+    assert_eq!(sections.len(), 1);
+    assert_eq!((sections[0].students, sections[0].solos), (4, 2));
+
+    let mut sorted = stats.clone();
+    sort_students(&mut sorted, "attendance");
+    assert_eq!(sorted[0].student, s[0]);
+    assert!(to_csv(&sorted).contains("\n\"dan, jr.\",A,4,3,0.750,"));
+}
+
+#[test]
+fn test_solo_spread() {
+    use database::{Team, Zoom, ShuffleMode};
+    let mut data = Data::empty("testcourse", "testcourse.yaml");
+    let section = Section::from("A".to_string());
+    data.new_section(section, Zoom::from("".to_string()));
+    let s: Vec<_> = ["ann", "bob", "cat"].iter().map(|n| Student::from(n.to_string())).collect();
+    for &st in s.iter() {
+        data.new_student(st, section);
+    }
+    data.new_team(Team::from("red".to_string()));
+    data.new_team(Team::from("blue".to_string()));
+    for id in 0..3 {
+        data.add_day();
+        data.run_shuffle(Day::from(id), ShuffleMode::Shuffle, None);
+    }
+    // With three students, each day one is solo and the others pair
+    // up, so spreading the solos evenly means everyone is solo once.
+    let stats = student_stats(&data);
+    for st in stats.iter() {
+        assert_eq!(st.solos, 1, "{:?}", stats);
+        assert!(!st.flagged);
+    }
+}
+
+#[test]
+fn test_role_rotation() {
+    use database::{Team, Zoom, ShuffleMode};
+    let mut data = Data::empty("testcourse", "testcourse.yaml");
+    let section = Section::from("A".to_string());
+    data.new_section(section, Zoom::from("".to_string()));
+    let s: Vec<_> = ["ann", "bob", "cat", "dan"].iter()
+        .map(|n| Student::from(n.to_string())).collect();
+    for &st in s.iter() {
+        data.new_student(st, section);
+    }
+    data.new_team(Team::from("red".to_string()));
+    data.new_team(Team::from("blue".to_string()));
+    let modes = [ShuffleMode::Shuffle, ShuffleMode::Repeat, ShuffleMode::Shuffle,
+                 ShuffleMode::Continuity, ShuffleMode::Grand, ShuffleMode::Repeat];
+    for (id, &mode) in modes.iter().enumerate() {
+        data.add_day();
+        data.run_shuffle(Day::from(id), mode, None);
+        // Everyone takes turns, so nobody gets more than one turn ahead.
+        for st in student_stats(&data) {
+            assert!(st.role_balance().abs() <= 1, "{} after day {}: {:?}", mode, id, st);
+        }
+    }
+    for st in student_stats(&data) {
+        assert_eq!((st.driver, st.navigator), (3, 3));
+    }
+}
+
+#[test]
+fn test_dropped_student() {
+    use database::{Team, Zoom, ShuffleMode};
+    use roster::{sync, CsvText};
+    let mut data = Data::empty("testcourse", "testcourse.yaml");
+    let section = Section::from("A".to_string());
+    data.new_section(section, Zoom::from("".to_string()));
+    let s: Vec<_> = ["ann", "bob", "cat"].iter().map(|n| Student::from(n.to_string())).collect();
+    for &st in s.iter() {
+        data.new_student(st, section);
+    }
+    data.new_team(Team::from("red".to_string()));
+    data.new_team(Team::from("blue".to_string()));
+    for id in 0..2 {
+        data.add_day();
+        data.run_shuffle(Day::from(id), ShuffleMode::Shuffle, None);
+    }
+    sync(&mut data, &CsvText("name,section\nann,A\nbob,A\n".to_string()), "csv", false).unwrap();
+    let cat = |data: &Data| student_stats(data).into_iter().find(|st| st.student == s[2]).unwrap();
+    let dropped = cat(&data);
+    assert_eq!((dropped.days, dropped.attended), (2, 2));
+    // The days after cat dropped the course are not held against them.
+    for id in 2..6 {
+        data.add_day();
+        data.run_shuffle(Day::from(id), ShuffleMode::Shuffle, None);
+    }
+    assert_eq!(cat(&data), dropped);
+    assert!(!cat(&data).flagged);
+    assert_eq!(student_stats_before(&data, Day::from(1)).iter()
+               .find(|st| st.student == s[2]).unwrap().days, 1);
+}
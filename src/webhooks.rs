@@ -0,0 +1,280 @@
+//! Webhooks which tell other tools, such as gradebook scripts or a chat
+//! bot, when a course changes.  Each course lists the endpoints to tell.
+//! The `Data` mutation methods note what they change, and when the
+//! course is saved the changes become events, which are put in the
+//! course's outbox (see `outbox`) to be posted as JSON, in order for
+//! each endpoint, so that saving a course never waits for an endpoint.
+//!
+//! Each event has an id, which stays the same when it is retried, so
+//! that an endpoint which sees an event twice can tell.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::Path;
+use std::time::Duration;
+
+use serde_json;
+use ureq;
+
+use database::{Pairing, Section, Student};
+use outbox::{self, Job, Pending};
+
+/// How long an endpoint may take to answer.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type")]
+pub enum Event {
+    DayCreated { day: usize, name: String },
+    DayLocked { day: usize, locked: bool },
+    /// The students whose placement on a day changed.
+    PairingsChanged { day: usize, changes: Vec<Move> },
+    RosterChanged {
+        added: Vec<Enrollment>,
+        removed: Vec<String>,
+        /// The students now in a different section.
+        moved: Vec<Enrollment>,
+        renamed: Vec<Rename>,
+    },
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Event::DayCreated { .. } => "DayCreated",
+            Event::DayLocked { .. } => "DayLocked",
+            Event::PairingsChanged { .. } => "PairingsChanged",
+            Event::RosterChanged { .. } => "RosterChanged",
+        }
+    }
+}
+
+/// Where a student is on a day.  A student who is on no team is in
+/// their section, unassigned, unless absent.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Placement {
+    pub section: Option<String>,
+    pub team: Option<String>,
+    pub partner: Option<String>,
+    pub absent: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Move {
+    pub student: String,
+    pub before: Placement,
+    pub after: Placement,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Enrollment {
+    pub student: String,
+    pub section: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Rename {
+    pub from: String,
+    pub to: String,
+}
+
+/// What has changed in a course since it was read, as noted by the
+/// mutation methods of `Data`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Changes {
+    /// Events which need no comparing, in the order they happened.
+    pub events: Vec<Event>,
+    /// The pairings of each changed day before it changed.
+    pub days: BTreeMap<usize, HashSet<Pairing>>,
+    /// The roster before it changed.
+    pub roster: Option<HashMap<Student, Section>>,
+    pub renamed: Vec<(Student, Student)>,
+    /// Whether the endpoints have changed, so that events for those
+    /// which were removed can be dropped.
+    pub endpoints: bool,
+}
+
+impl Changes {
+    /// The events for the changes so far, given the current pairings
+    /// and roster, after which there are no changes.
+    pub fn take(&mut self, days: &[HashSet<Pairing>],
+                roster: &HashMap<Student, Section>) -> Vec<Event> {
+        let mut events = ::std::mem::take(&mut self.events);
+        if let Some(before) = self.roster.take() {
+            let renamed: Vec<(Student, Student)> = self.renamed.iter().cloned()
+                .filter(|(old, new)| before.contains_key(old) && roster.contains_key(new)
+                        && !roster.contains_key(old))
+                .collect();
+            let old_name: HashMap<Student, Student> = renamed.iter().map(|&(o, n)| (n, o)).collect();
+            let enrollment = |&(s, section): &(&Student, &Section)| Enrollment {
+                student: s.name.to_string(),
+                section: section.name.to_string(),
+            };
+            let mut now: Vec<_> = roster.iter().collect();
+            now.sort();
+            let added: Vec<_> = now.iter()
+                .filter(|(s, _)| !before.contains_key(s) && !old_name.contains_key(s))
+                .map(enrollment).collect();
+            let moved: Vec<_> = now.iter()
+                .filter(|&&(s, section)| {
+                    before.get(old_name.get(s).unwrap_or(s)).map(|b| b != section)
+                        .unwrap_or(false)
+                })
+                .map(enrollment).collect();
+            let mut removed: Vec<_> = before.keys()
+                .filter(|s| !roster.contains_key(s) && !renamed.iter().any(|(o, _)| o == *s))
+                .map(|s| s.name.to_string()).collect();
+            removed.sort();
+            let renamed: Vec<_> = renamed.iter()
+                .map(|(o, n)| Rename { from: o.name.to_string(), to: n.name.to_string() })
+                .collect();
+            if !(added.is_empty() && removed.is_empty() && moved.is_empty() && renamed.is_empty()) {
+                events.push(Event::RosterChanged { added, removed, moved, renamed });
+            }
+        }
+        self.renamed.clear();
+        for (day, before) in ::std::mem::take(&mut self.days) {
+            let after = match days.get(day) {
+                Some(after) => after,
+                None => continue,
+            };
+            let students: BTreeSet<Student> = before.iter().chain(after.iter())
+                .flat_map(|p| p.allocated_students()).collect();
+            let changes: Vec<Move> = students.into_iter().filter_map(|s| {
+                let (before, after) = (placement(&before, s), placement(after, s));
+                if before == after {
+                    None
+                } else {
+                    Some(Move { student: s.name.to_string(), before, after })
+                }
+            }).collect();
+            if !changes.is_empty() {
+                events.push(Event::PairingsChanged { day, changes });
+            }
+        }
+        events
+    }
+}
+
+fn placement(pairings: &HashSet<Pairing>, student: Student) -> Placement {
+    match pairings.iter().find(|p| p.allocated_students().contains(&student)) {
+        Some(&Pairing::Pair { section, team, primary, secondary }) => Placement {
+            section: Some(section.name.to_string()),
+            team: Some(team.name.to_string()),
+            partner: Some(if primary == student { secondary } else { primary }.name.to_string()),
+            absent: false,
+        },
+        Some(&Pairing::Solo { section, team, .. }) => Placement {
+            section: Some(section.name.to_string()),
+            team: Some(team.name.to_string()),
+            ..Placement::default()
+        },
+        Some(&Pairing::Unassigned { section, .. }) => Placement {
+            section: Some(section.name.to_string()),
+            ..Placement::default()
+        },
+        Some(&Pairing::Absent(_)) => Placement { absent: true, ..Placement::default() },
+        None => Placement::default(),
+    }
+}
+
+/// An event as it is posted.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    pub id: u64,
+    /// The secret path of the course.
+    pub course: String,
+    /// When the course was saved, in seconds since 1970.
+    pub time: u64,
+    pub event: Event,
+}
+
+/// Queue `events` of the course saved in `course_file` for each of
+/// `endpoints`.
+pub fn enqueue(course_file: &Path, course: &str, endpoints: &[String],
+               events: Vec<Event>) -> Result<(), String> {
+    let time = outbox::now();
+    let jobs = endpoints.iter().flat_map(|endpoint| events.iter().map(move |event| Job::Event {
+        endpoint: endpoint.clone(),
+        course: course.to_string(),
+        time,
+        event: event.clone(),
+    })).collect();
+    outbox::enqueue(course_file, jobs)
+}
+
+/// Drop the events waiting for any endpoint other than `endpoints`.
+pub fn retain_endpoints(course_file: &Path, endpoints: &[String]) -> Result<(), String> {
+    outbox::retain(course_file, |job| match *job {
+        Job::Event { ref endpoint, .. } => endpoints.contains(endpoint),
+        _ => true,
+    })
+}
+
+/// The events of the course saved in `course_file` which have yet to
+/// be posted.
+pub fn pending(course_file: &Path) -> Vec<Pending> {
+    outbox::pending(course_file).into_iter().filter(|p| p.job.is_event()).collect()
+}
+
+pub fn post(endpoint: &str, envelope: &Envelope) -> Result<(), String> {
+    let body = serde_json::to_string(envelope).map_err(|e| e.to_string())?;
+    ureq::post(endpoint)
+        .timeout(TIMEOUT)
+        .set("Content-Type", "application/json")
+        .send_string(&body)
+        .map_err(|e| format!("Error posting to {}: {}", endpoint, e))?;
+    Ok(())
+}
+
+#[test]
+fn test_changes() {
+    use database::{Data, Day, ShuffleMode, Team, Zoom};
+    let mut data = Data::empty("testcourse", "testcourse.yaml");
+    let (a, b) = (Section::from("A".to_string()), Section::from("B".to_string()));
+    data.new_section(a, Zoom::from("".to_string()));
+    data.new_section(b, Zoom::from("".to_string()));
+    let s: Vec<_> = ["ann", "bob", "cat"].iter().map(|n| Student::from(n.to_string())).collect();
+    for &st in s.iter() {
+        data.new_student(st, a);
+    }
+    data.new_team(Team::from("red".to_string()));
+    data.take_events();
+
+    data.add_day();
+    let day = Day::from(0);
+    data.toggle_lock_day(day);
+    data.run_shuffle(day, ShuffleMode::Shuffle, None);
+    let events = data.take_events();
+    assert_eq!(events[0], Event::DayCreated { day: 0, name: "Day 0".to_string() });
+    assert_eq!(events[1], Event::DayLocked { day: 0, locked: false });
+    match events[2] {
+        Event::PairingsChanged { day: 0, ref changes } => {
+            assert!(changes.iter().all(|c| c.before == Placement::default()));
+            assert_eq!(changes.iter().filter(|c| c.after.team.is_some()).count(), 2);
+        }
+        ref e => panic!("unexpected {:?}", e),
+    }
+    assert_eq!(events.len(), 3);
+    assert!(data.take_events().is_empty());
+
+    // Undoing a change within one save makes no event.
+    let placed = data.pairings()[0].clone();
+    data.unpair_team(day, Team::from("red".to_string()));
+    for p in placed.iter() {
+        if let Pairing::Pair { section, team, primary, secondary } = *p {
+            data.assign_student(day, primary, section, team);
+            data.assign_student(day, secondary, section, team);
+        }
+    }
+    assert!(data.take_events().is_empty());
+
+    data.rename_student(s[0], Student::from("ann2".to_string()), b).unwrap();
+    data.new_student(Student::from("dan".to_string()), b);
+    data.delete_student(s[1]);
+    assert_eq!(data.take_events(), vec![Event::RosterChanged {
+        added: vec![Enrollment { student: "dan".to_string(), section: "B".to_string() }],
+        removed: vec!["bob".to_string()],
+        moved: vec![Enrollment { student: "ann2".to_string(), section: "B".to_string() }],
+        renamed: vec![Rename { from: "ann".to_string(), to: "ann2".to_string() }],
+    }]);
+}